@@ -0,0 +1,645 @@
+// src/dhjc_alarm.rs
+//
+// 报警（dhjc_config.toml 里的 [[alarm]] 规则）：
+// - 持续型：Rate 高于 / 低于阈值、阶段中长时间没有脉冲；条件成立满 for_s 秒触发，
+//   条件消失满 RETURN_HOLD_S 秒后标记为已恢复
+// - 事件型：阶段 Total Arcs 超限、session 超时结束、[ERROR] 行；每次发生都触发
+// - 报警一直留在列表里，直到用户清除；确认只是表示已知晓（停止响铃）
+// - 每条规则在列表里最多一条：再次触发时重新启用原来那条
+// - 每次状态变化返回一个 AlarmTransition，由 GUI 写日志、响铃
+// 时间 t 为秒（与曲线横轴同一起点），wall 为对应的本地时间
+
+use crate::dhjc_core::CoreEventKind;
+use chrono::{DateTime, Local};
+
+/// 持续型报警的恢复条件要连续消失这么久（秒）才算恢复，阈值附近来回跳时不反复触发
+const RETURN_HOLD_S: f64 = 2.0;
+
+/// 清除后条件仍成立时，至少等这么久（秒）才再次触发；for_s 更长时按 for_s
+const REARM_MIN_S: f64 = RETURN_HOLD_S;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmKind {
+    /// Rate 高于 value（脉冲/秒）持续 for_s 秒
+    RateAbove,
+    /// 阶段进行中 Rate 低于 value 持续 for_s 秒
+    RateBelow,
+    /// 阶段报告的 Total Arcs 大于 value
+    StageArcsAbove,
+    /// 阶段进行中 for_s 秒没有新脉冲
+    NoPulses,
+    /// TOTAL SUMMARY 的 Status 为超时结束
+    SessionTimeout,
+    /// 收到 [ERROR] 行
+    ErrorLine,
+}
+
+impl AlarmKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rate_above" => Some(AlarmKind::RateAbove),
+            "rate_below" => Some(AlarmKind::RateBelow),
+            "stage_arcs_above" => Some(AlarmKind::StageArcsAbove),
+            "no_pulses" => Some(AlarmKind::NoPulses),
+            "session_timeout" => Some(AlarmKind::SessionTimeout),
+            "error_line" => Some(AlarmKind::ErrorLine),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlarmKind::RateAbove => "rate_above",
+            AlarmKind::RateBelow => "rate_below",
+            AlarmKind::StageArcsAbove => "stage_arcs_above",
+            AlarmKind::NoPulses => "no_pulses",
+            AlarmKind::SessionTimeout => "session_timeout",
+            AlarmKind::ErrorLine => "error_line",
+        }
+    }
+
+    /// 需要阈值 value
+    pub fn needs_value(self) -> bool {
+        matches!(
+            self,
+            AlarmKind::RateAbove | AlarmKind::RateBelow | AlarmKind::StageArcsAbove
+        )
+    }
+
+    /// 需要持续时间 for_s（rate_* 可以为 0，表示立即触发）
+    pub fn needs_duration(self) -> bool {
+        self == AlarmKind::NoPulses
+    }
+
+    // 条件可以消失（恢复）的规则；其余按事件触发
+    fn sustained(self) -> bool {
+        matches!(
+            self,
+            AlarmKind::RateAbove | AlarmKind::RateBelow | AlarmKind::NoPulses
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlarmRule {
+    pub name: String,
+    pub kind: AlarmKind,
+    pub value: f64,
+    pub for_s: f64,
+    /// 触发时响铃，确认前每隔几秒重复
+    pub sound: bool,
+}
+
+impl AlarmRule {
+    /// 触发条件的说明（悬停提示用）
+    pub fn describe(&self) -> String {
+        match self.kind {
+            AlarmKind::RateAbove => {
+                format!("Rate > {} pulses/s 持续 {} s", self.value, self.for_s)
+            }
+            AlarmKind::RateBelow => format!(
+                "阶段进行中 Rate < {} pulses/s 持续 {} s",
+                self.value, self.for_s
+            ),
+            AlarmKind::StageArcsAbove => format!("阶段 Total Arcs > {}", self.value),
+            AlarmKind::NoPulses => format!("阶段进行中 {} s 没有新脉冲", self.for_s),
+            AlarmKind::SessionTimeout => "session 超时结束".to_string(),
+            AlarmKind::ErrorLine => "收到 [ERROR] 行".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveAlarm {
+    /// 对应的规则；规则被改掉（热加载）后、或由 notify 发出时为 None
+    rule: Option<usize>,
+    pub name: String,
+    pub message: String,
+    pub raised_at: DateTime<Local>,
+    pub acknowledged: bool,
+    /// 条件已消失
+    pub returned: bool,
+    pub sound: bool,
+}
+
+/// 报警状态的变化，写进日志
+#[derive(Debug, Clone)]
+pub enum AlarmTransition {
+    Raised {
+        name: String,
+        message: String,
+        sound: bool,
+    },
+    Returned {
+        name: String,
+    },
+    Acknowledged {
+        name: String,
+    },
+    Cleared {
+        name: String,
+    },
+}
+
+impl AlarmTransition {
+    pub fn log_line(&self) -> String {
+        match self {
+            AlarmTransition::Raised { name, message, .. } => {
+                format!("[ALARM] 触发 {}: {}", name, message)
+            }
+            AlarmTransition::Returned { name } => format!("[ALARM] 恢复 {}", name),
+            AlarmTransition::Acknowledged { name } => format!("[ALARM] 确认 {}", name),
+            AlarmTransition::Cleared { name } => format!("[ALARM] 清除 {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AlarmEngine {
+    rules: Vec<AlarmRule>,
+    /// 持续型规则：条件开始成立的时间
+    since: Vec<Option<f64>>,
+    /// 持续型规则：报警未恢复时，条件开始消失的时间
+    clear_since: Vec<Option<f64>>,
+    /// 持续型规则：报警在条件仍成立时被清除，since 为清除时间，等条件消失或再次触发
+    rearmed: Vec<bool>,
+    alarms: Vec<ActiveAlarm>,
+    /// 阶段进行中（见到带阶段号的 Live，还没出阶段报告 / 汇总）
+    stage: Option<i32>,
+    last_count: Option<i32>,
+    last_pulse_s: Option<f64>,
+}
+
+impl AlarmEngine {
+    pub fn new(rules: Vec<AlarmRule>) -> Self {
+        Self {
+            since: vec![None; rules.len()],
+            clear_since: vec![None; rules.len()],
+            rearmed: vec![false; rules.len()],
+            rules,
+            ..Self::default()
+        }
+    }
+
+    pub fn rule_of(&self, alarm: &ActiveAlarm) -> Option<&AlarmRule> {
+        alarm.rule.and_then(|i| self.rules.get(i))
+    }
+
+    /// 热加载：换规则；现有报警按名称和类型对应到新规则，对不上的视为已恢复
+    pub fn set_rules(&mut self, rules: Vec<AlarmRule>) {
+        if rules == self.rules {
+            return;
+        }
+        for a in self.alarms.iter_mut().filter(|a| a.rule.is_some()) {
+            let old = a.rule.and_then(|i| self.rules.get(i));
+            a.rule = old.and_then(|old| {
+                rules
+                    .iter()
+                    .position(|r| r.name == old.name && r.kind == old.kind)
+            });
+            if a.rule.is_none() {
+                a.returned = true;
+            }
+        }
+        self.since = vec![None; rules.len()];
+        self.clear_since = vec![None; rules.len()];
+        self.rearmed = vec![false; rules.len()];
+        self.rules = rules;
+    }
+
+    /// 时间基准变了（手动复位）：重新计时，已有报警保留
+    pub fn reset(&mut self) {
+        self.since.iter_mut().for_each(|s| *s = None);
+        self.clear_since.iter_mut().for_each(|s| *s = None);
+        self.stage = None;
+        self.last_count = None;
+        self.last_pulse_s = None;
+    }
+
+    pub fn alarms(&self) -> &[ActiveAlarm] {
+        &self.alarms
+    }
+
+    /// 有未确认且需要响铃的报警
+    pub fn needs_sound(&self) -> bool {
+        self.alarms.iter().any(|a| a.sound && !a.acknowledged)
+    }
+
+    pub fn on_event(
+        &mut self,
+        t: f64,
+        wall: DateTime<Local>,
+        kind: &CoreEventKind,
+    ) -> Vec<AlarmTransition> {
+        let mut out = Vec::new();
+        match kind {
+            CoreEventKind::SystemReset | CoreEventKind::Banner(_) => {
+                self.end_stage();
+            }
+            CoreEventKind::Live(l) => {
+                if l.stage.is_none() {
+                    return out;
+                }
+                if l.stage != self.stage {
+                    self.stage = l.stage;
+                    self.last_count = None;
+                    self.last_pulse_s = Some(t);
+                }
+                if let Some(c) = l.count {
+                    if self.last_count.is_some_and(|last| c != last) {
+                        self.last_pulse_s = Some(t - l.wait_ms.unwrap_or(0.0) / 1000.0);
+                    }
+                    self.last_count = Some(c);
+                }
+            }
+            CoreEventKind::StageReport(r) => {
+                self.end_stage();
+                if let Some(arcs) = r.arcs {
+                    let stage = r
+                        .stage_id
+                        .map(|id| format!("Stage {} ", id))
+                        .unwrap_or_default();
+                    for i in self.matching(AlarmKind::StageArcsAbove) {
+                        if arcs as f64 > self.rules[i].value {
+                            let msg = format!(
+                                "{}Total Arcs {} 超过 {}",
+                                stage, arcs, self.rules[i].value
+                            );
+                            out.push(self.raise(i, wall, msg));
+                        }
+                    }
+                }
+            }
+            CoreEventKind::TotalSummary(s) => {
+                self.end_stage();
+                let status = s.status.as_deref().unwrap_or("");
+                if status.contains("Timeout") {
+                    for i in self.matching(AlarmKind::SessionTimeout) {
+                        out.push(self.raise(i, wall, format!("Status: {}", status)));
+                    }
+                }
+            }
+            CoreEventKind::Error(text) => {
+                for i in self.matching(AlarmKind::ErrorLine) {
+                    out.push(self.raise(i, wall, text.clone()));
+                }
+            }
+            _ => {}
+        }
+        out
+    }
+
+    /// 每帧调用；rate 为 None 表示未连接，持续型规则暂停计时
+    pub fn tick(
+        &mut self,
+        t: f64,
+        wall: DateTime<Local>,
+        rate: Option<f64>,
+    ) -> Vec<AlarmTransition> {
+        let mut out = Vec::new();
+        // 未连接：不开始计时，也不把已有报警当作恢复
+        let Some(rate) = rate else {
+            return out;
+        };
+        for i in 0..self.rules.len() {
+            let rule = &self.rules[i];
+            if !rule.kind.sustained() {
+                continue;
+            }
+            let in_stage = self.stage.is_some();
+            let silent_s = self.last_pulse_s.map(|p| t - p).unwrap_or(0.0);
+            let failing = match rule.kind {
+                AlarmKind::RateAbove => (rate > rule.value)
+                    .then(|| format!("Rate {:.2} pulses/s 高于 {}", rate, rule.value)),
+                AlarmKind::RateBelow => (in_stage && rate < rule.value)
+                    .then(|| format!("Rate {:.2} pulses/s 低于 {}", rate, rule.value)),
+                _ => (in_stage && silent_s >= rule.for_s).then(|| {
+                    format!(
+                        "Stage {} 已 {:.0} s 没有新脉冲",
+                        self.stage.unwrap_or(0),
+                        silent_s
+                    )
+                }),
+            };
+            // no_pulses 自己按最后一个脉冲计时；清除后从清除时间重新计时
+            let hold_s = if self.rearmed[i] {
+                rule.for_s.max(REARM_MIN_S)
+            } else if rule.kind == AlarmKind::NoPulses {
+                0.0
+            } else {
+                rule.for_s
+            };
+            let show_held = rule.kind != AlarmKind::NoPulses && hold_s > 0.0;
+
+            let open = self.open_alarm(i);
+            match failing {
+                Some(msg) => {
+                    self.clear_since[i] = None;
+                    let since = *self.since[i].get_or_insert(t);
+                    if open.is_none() && t - since >= hold_s {
+                        self.rearmed[i] = false;
+                        let msg = if show_held {
+                            format!("{}，已持续 {:.0} s", msg, t - since)
+                        } else {
+                            msg
+                        };
+                        out.push(self.raise(i, wall, msg));
+                    }
+                }
+                None => {
+                    self.since[i] = None;
+                    self.rearmed[i] = false;
+                    let Some(a) = open else {
+                        continue;
+                    };
+                    let clear_since = *self.clear_since[i].get_or_insert(t);
+                    if t - clear_since >= RETURN_HOLD_S {
+                        self.clear_since[i] = None;
+                        let a = &mut self.alarms[a];
+                        a.returned = true;
+                        out.push(AlarmTransition::Returned {
+                            name: a.name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// 不属于任何规则的一次性提醒（如批次完成），同样显示在横幅里等待确认
+    pub fn notify(
+        &mut self,
+        name: &str,
+        message: String,
+        sound: bool,
+        wall: DateTime<Local>,
+    ) -> AlarmTransition {
+        let alarm = ActiveAlarm {
+            rule: None,
+            name: name.to_string(),
+            message: message.clone(),
+            raised_at: wall,
+            acknowledged: false,
+            returned: false,
+            sound,
+        };
+        // 同名提醒只留最新的一条
+        match self
+            .alarms
+            .iter()
+            .position(|a| a.rule.is_none() && a.name == name)
+        {
+            Some(i) => self.alarms[i] = alarm,
+            None => self.alarms.push(alarm),
+        }
+        AlarmTransition::Raised {
+            name: name.to_string(),
+            message,
+            sound,
+        }
+    }
+
+    pub fn acknowledge(&mut self, index: usize) -> Option<AlarmTransition> {
+        let a = self.alarms.get_mut(index)?;
+        if a.acknowledged {
+            return None;
+        }
+        a.acknowledged = true;
+        Some(AlarmTransition::Acknowledged {
+            name: a.name.clone(),
+        })
+    }
+
+    /// 从列表移除；条件仍成立的持续型规则从清除时间 t 重新计时，满 for_s（至少 REARM_MIN_S）后再次触发
+    pub fn clear(&mut self, index: usize, t: f64) -> Option<AlarmTransition> {
+        if index >= self.alarms.len() {
+            return None;
+        }
+        let a = self.alarms.remove(index);
+        if let Some(i) = a.rule.filter(|&i| i < self.rules.len()) {
+            if self.rules[i].kind.sustained() {
+                self.since[i] = Some(t);
+                self.rearmed[i] = true;
+            }
+            self.clear_since[i] = None;
+        }
+        Some(AlarmTransition::Cleared { name: a.name })
+    }
+
+    fn end_stage(&mut self) {
+        self.stage = None;
+        self.last_count = None;
+        self.last_pulse_s = None;
+    }
+
+    fn matching(&self, kind: AlarmKind) -> Vec<usize> {
+        (0..self.rules.len())
+            .filter(|&i| self.rules[i].kind == kind)
+            .collect()
+    }
+
+    // 该规则还在进行中（未恢复）的报警
+    fn open_alarm(&self, rule: usize) -> Option<usize> {
+        self.alarms
+            .iter()
+            .position(|a| a.rule == Some(rule) && !a.returned)
+    }
+
+    // 同一规则再次触发时替换原来的那条（已恢复 / 已确认的也一样），重新等待确认
+    fn raise(&mut self, rule: usize, wall: DateTime<Local>, message: String) -> AlarmTransition {
+        let r = &self.rules[rule];
+        let alarm = ActiveAlarm {
+            rule: Some(rule),
+            name: r.name.clone(),
+            message: message.clone(),
+            raised_at: wall,
+            acknowledged: false,
+            returned: false,
+            sound: r.sound,
+        };
+        match self.alarms.iter().position(|a| a.rule == Some(rule)) {
+            Some(i) => self.alarms[i] = alarm,
+            None => self.alarms.push(alarm),
+        }
+        AlarmTransition::Raised {
+            name: r.name.clone(),
+            message,
+            sound: r.sound,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhjc_core::LiveSample;
+
+    fn rule(name: &str, kind: AlarmKind, value: f64, for_s: f64) -> AlarmRule {
+        AlarmRule {
+            name: name.to_string(),
+            kind,
+            value,
+            for_s,
+            sound: true,
+        }
+    }
+
+    fn live(stage: i32, count: i32) -> CoreEventKind {
+        CoreEventKind::Live(LiveSample {
+            stage: Some(stage),
+            count: Some(count),
+            total: Some(count),
+            wait_ms: None,
+        })
+    }
+
+    fn names(ts: &[AlarmTransition]) -> Vec<String> {
+        ts.iter().map(AlarmTransition::log_line).collect()
+    }
+
+    fn raised(ts: &[AlarmTransition]) -> bool {
+        ts.iter().any(|t| matches!(t, AlarmTransition::Raised { .. }))
+    }
+
+    #[test]
+    fn raise_ack_return_clear_reraise() {
+        let now = Local::now();
+        let mut e = AlarmEngine::new(vec![rule("high", AlarmKind::RateAbove, 10.0, 1.0)]);
+
+        // 持续不足 for_s 不触发
+        assert!(e.tick(0.0, now, Some(20.0)).is_empty());
+        assert!(e.tick(0.5, now, Some(20.0)).is_empty());
+        let ts = e.tick(1.0, now, Some(20.0));
+        assert_eq!(names(&ts), ["[ALARM] 触发 high: Rate 20.00 pulses/s 高于 10，已持续 1 s"]);
+        assert!(e.needs_sound());
+        // 已触发的报警不重复触发
+        assert!(e.tick(5.0, now, Some(20.0)).is_empty());
+
+        assert!(e.acknowledge(0).is_some());
+        assert!(e.acknowledge(0).is_none());
+        assert!(!e.needs_sound());
+
+        // 条件消失满 2 s 才恢复；中途又成立则重新计
+        assert!(e.tick(6.0, now, Some(5.0)).is_empty());
+        assert!(e.tick(7.0, now, Some(20.0)).is_empty());
+        assert!(e.tick(7.5, now, Some(5.0)).is_empty());
+        assert!(e.tick(9.0, now, Some(5.0)).is_empty());
+        assert_eq!(names(&e.tick(9.5, now, Some(5.0))), ["[ALARM] 恢复 high"]);
+        assert!(e.alarms()[0].returned);
+        assert!(e.alarms()[0].acknowledged);
+
+        // 再次成立：替换原来那条，重新等待确认
+        assert!(e.tick(10.0, now, Some(20.0)).is_empty());
+        assert!(raised(&e.tick(11.0, now, Some(20.0))));
+        assert_eq!(e.alarms().len(), 1);
+        assert!(!e.alarms()[0].acknowledged && !e.alarms()[0].returned);
+
+        // 清除时条件仍成立：从清除时间起再满 for_s（至少 2 s）才再次触发
+        assert_eq!(names(&[e.clear(0, 12.0).unwrap()]), ["[ALARM] 清除 high"]);
+        assert!(e.alarms().is_empty());
+        assert!(e.tick(12.0, now, Some(20.0)).is_empty());
+        assert!(e.tick(13.5, now, Some(20.0)).is_empty());
+        assert!(raised(&e.tick(14.0, now, Some(20.0))));
+    }
+
+    #[test]
+    fn zero_hold_rate_rule_waits_after_clear() {
+        let now = Local::now();
+        let mut e = AlarmEngine::new(vec![rule("high", AlarmKind::RateAbove, 10.0, 0.0)]);
+        assert!(raised(&e.tick(0.0, now, Some(20.0))));
+        assert!(e.clear(0, 1.0).is_some());
+        assert!(e.tick(1.0, now, Some(20.0)).is_empty());
+        assert!(e.tick(2.9, now, Some(20.0)).is_empty());
+        assert!(raised(&e.tick(3.0, now, Some(20.0))));
+
+        // 条件消失后再成立：恢复原来的立即触发
+        assert!(e.clear(0, 4.0).is_some());
+        assert!(e.tick(4.0, now, Some(5.0)).is_empty());
+        assert!(raised(&e.tick(4.1, now, Some(20.0))));
+    }
+
+    #[test]
+    fn no_pulses_rearms_from_clear_time() {
+        let now = Local::now();
+        let mut e = AlarmEngine::new(vec![rule("quiet", AlarmKind::NoPulses, 0.0, 10.0)]);
+        e.on_event(0.0, now, &live(1, 0));
+        e.on_event(1.0, now, &live(1, 1));
+        assert!(e.tick(10.5, now, Some(0.0)).is_empty());
+        let ts = e.tick(11.0, now, Some(0.0));
+        assert_eq!(names(&ts), ["[ALARM] 触发 quiet: Stage 1 已 10 s 没有新脉冲"]);
+
+        // 仍然没有脉冲：清除后再等 10 s
+        assert!(e.clear(0, 20.0).is_some());
+        assert!(e.tick(20.0, now, Some(0.0)).is_empty());
+        assert!(e.tick(29.9, now, Some(0.0)).is_empty());
+        assert_eq!(
+            names(&e.tick(30.0, now, Some(0.0))),
+            ["[ALARM] 触发 quiet: Stage 1 已 29 s 没有新脉冲"]
+        );
+
+        // 阶段结束：不在阶段中，条件消失满 2 s 后恢复
+        e.on_event(31.0, now, &CoreEventKind::StageReport(Default::default()));
+        assert!(e.tick(31.0, now, Some(0.0)).is_empty());
+        assert_eq!(names(&e.tick(33.0, now, Some(0.0))), ["[ALARM] 恢复 quiet"]);
+    }
+
+    #[test]
+    fn disconnected_pauses_sustained_rules() {
+        let now = Local::now();
+        let mut e = AlarmEngine::new(vec![rule("high", AlarmKind::RateAbove, 10.0, 0.0)]);
+        assert!(raised(&e.tick(0.0, now, Some(20.0))));
+        // 未连接：不当作恢复
+        assert!(e.tick(5.0, now, None).is_empty());
+        assert!(!e.alarms()[0].returned);
+    }
+
+    #[test]
+    fn set_rules_remaps_by_name_and_kind() {
+        let now = Local::now();
+        let mut e = AlarmEngine::new(vec![
+            rule("a", AlarmKind::RateAbove, 10.0, 0.0),
+            rule("b", AlarmKind::RateAbove, 5.0, 0.0),
+        ]);
+        let ts = e.tick(0.0, now, Some(20.0));
+        assert_eq!(ts.len(), 2);
+
+        // b 移到最前并改了阈值；a 换了类型，对不上
+        e.set_rules(vec![
+            rule("b", AlarmKind::RateAbove, 6.0, 0.0),
+            rule("a", AlarmKind::RateBelow, 10.0, 0.0),
+        ]);
+        let a = &e.alarms()[0];
+        assert_eq!(a.name, "a");
+        assert!(a.returned);
+        assert!(e.rule_of(a).is_none());
+        let b = &e.alarms()[1];
+        assert!(!b.returned);
+        assert_eq!(e.rule_of(b).map(|r| r.value), Some(6.0));
+
+        // 对应上的报警仍是进行中：不重复触发
+        assert!(e.tick(1.0, now, Some(20.0)).is_empty());
+        assert_eq!(e.alarms().len(), 2);
+
+        // 同样的规则：什么都不变
+        let before = e.alarms().len();
+        e.set_rules(vec![
+            rule("b", AlarmKind::RateAbove, 6.0, 0.0),
+            rule("a", AlarmKind::RateBelow, 10.0, 0.0),
+        ]);
+        assert_eq!(e.alarms().len(), before);
+        assert!(e.rule_of(&e.alarms()[1]).is_some());
+    }
+
+    #[test]
+    fn event_rules_raise_each_time() {
+        let now = Local::now();
+        let mut e = AlarmEngine::new(vec![rule("err", AlarmKind::ErrorLine, 0.0, 0.0)]);
+        let err = CoreEventKind::Error("[ERROR] 连接断开".to_string());
+        assert!(raised(&e.on_event(0.0, now, &err)));
+        e.acknowledge(0);
+        assert!(raised(&e.on_event(1.0, now, &err)));
+        assert_eq!(e.alarms().len(), 1);
+        assert!(!e.alarms()[0].acknowledged);
+    }
+}
@@ -0,0 +1,148 @@
+// src/dhjc_batch.rs
+//
+// 批次模式：操作员给定目标弧数，从开始时刻累计 Total 的增量
+// - Total 回退（复位 / 新 session）时从新的 Total 接着累计，不丢数
+// - 达到目标后继续累计（停止命令没生效时能看到多打了多少）
+// - 剩余时间按当前 Rate 估计
+
+use chrono::{DateTime, Local};
+
+#[derive(Debug, Clone)]
+pub struct BatchRun {
+    pub target: u64,
+    pub done: u64,
+    last_total: i32,
+    pub started: DateTime<Local>,
+    pub finished: Option<DateTime<Local>>,
+}
+
+impl BatchRun {
+    /// total 为开始时的 Total，之前的计数不算
+    pub fn start(target: u64, total: i32, wall: DateTime<Local>) -> Self {
+        Self {
+            target,
+            done: 0,
+            last_total: total,
+            started: wall,
+            finished: None,
+        }
+    }
+
+    /// 按最新的 Total 累计；刚好达到目标时返回 true
+    pub fn update(&mut self, total: i32, wall: DateTime<Local>) -> bool {
+        let delta = if total >= self.last_total {
+            total - self.last_total
+        } else {
+            total
+        };
+        self.last_total = total;
+        self.done += delta.max(0) as u64;
+
+        if self.finished.is_none() && self.done >= self.target {
+            self.finished = Some(wall);
+            return true;
+        }
+        false
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.target.saturating_sub(self.done)
+    }
+
+    /// 0.0 ~ 1.0
+    pub fn fraction(&self) -> f32 {
+        if self.target == 0 {
+            return 1.0;
+        }
+        (self.done as f64 / self.target as f64).min(1.0) as f32
+    }
+
+    /// 按 rate（脉冲/秒）估计的剩余秒数；没有速率时为 None
+    pub fn eta_s(&self, rate_hz: f64) -> Option<f64> {
+        if self.finished.is_some() {
+            return Some(0.0);
+        }
+        (rate_hz > 0.0).then(|| self.remaining() as f64 / rate_hz)
+    }
+
+    /// 开始到完成（未完成时到 now）的时长
+    pub fn elapsed_s(&self, now: DateTime<Local>) -> f64 {
+        let end = self.finished.unwrap_or(now);
+        (end - self.started).num_milliseconds().max(0) as f64 / 1000.0
+    }
+}
+
+/// 1h 02m 03s / 2m 03s / 3s
+pub fn format_duration(s: f64) -> String {
+    let total = s.max(0.0).round() as u64;
+    let (h, m, sec) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{}h {:02}m {:02}s", h, m, sec)
+    } else if m > 0 {
+        format!("{}m {:02}s", m, sec)
+    } else {
+        format!("{}s", sec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn total_going_back_continues_from_new_total() {
+        let t0 = Local::now();
+        let mut b = BatchRun::start(100, 40, t0);
+        assert!(!b.update(50, t0));
+        assert_eq!(b.done, 10);
+        // 复位：新 session 从 0 数到 5，这 5 个都算
+        assert!(!b.update(5, t0));
+        assert_eq!(b.done, 15);
+        assert!(!b.update(8, t0));
+        assert_eq!(b.done, 18);
+        assert_eq!(b.remaining(), 82);
+        assert!((b.fraction() - 0.18).abs() < 1e-6);
+    }
+
+    #[test]
+    fn finishes_once_and_keeps_counting() {
+        let t0 = Local::now();
+        let t1 = t0 + TimeDelta::seconds(90);
+        let mut b = BatchRun::start(10, 0, t0);
+        assert!(!b.update(9, t0));
+        assert!(b.update(10, t1));
+        assert_eq!(b.finished, Some(t1));
+        // 超过目标：继续累计，不再报完成
+        assert!(!b.update(13, t1 + TimeDelta::seconds(5)));
+        assert_eq!(b.done, 13);
+        assert_eq!(b.remaining(), 0);
+        assert_eq!(b.fraction(), 1.0);
+        assert_eq!(b.finished, Some(t1));
+        // 用时到完成为止
+        assert_eq!(b.elapsed_s(t1 + TimeDelta::seconds(60)), 90.0);
+    }
+
+    #[test]
+    fn eta_needs_rate_until_finished() {
+        let t0 = Local::now();
+        let mut b = BatchRun::start(100, 0, t0);
+        b.update(40, t0);
+        assert_eq!(b.eta_s(0.0), None);
+        assert_eq!(b.eta_s(-1.0), None);
+        assert_eq!(b.eta_s(2.0), Some(30.0));
+        b.update(100, t0);
+        assert_eq!(b.eta_s(0.0), Some(0.0));
+        assert_eq!(b.eta_s(5.0), Some(0.0));
+    }
+
+    #[test]
+    fn format_duration_units() {
+        assert_eq!(format_duration(0.0), "0s");
+        assert_eq!(format_duration(-5.0), "0s");
+        assert_eq!(format_duration(2.6), "3s");
+        assert_eq!(format_duration(123.0), "2m 03s");
+        assert_eq!(format_duration(3723.0), "1h 02m 03s");
+        assert_eq!(format_duration(36000.0), "10h 00m 00s");
+    }
+}
@@ -0,0 +1,216 @@
+// src/dhjc_clock.rs
+//
+// 时间来源与日志时间戳：
+// - Clock：墙钟时间 + 连接后的单调时间，LogWriter 和 CoreState 共用一个
+// - TimestampFormat：日志行前缀的格式（HH:MM:SS / 带毫秒 / 完整 ISO-8601）
+// - parse_log_timestamp：读回任意一种格式的前缀（导入、导出、回放时用）
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeDelta};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 日志行时间戳格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// [13:31:55]（旧格式，日期只在文件名里）
+    Time,
+    /// [13:31:55.123]
+    TimeMillis,
+    /// [2025-12-12T13:31:55.123+08:00]
+    Iso,
+}
+
+impl TimestampFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "time" => Some(Self::Time),
+            "time_ms" => Some(Self::TimeMillis),
+            "iso" => Some(Self::Iso),
+            _ => None,
+        }
+    }
+}
+
+/// 共享时钟；clone 出来的副本看到同一个连接时刻
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    connected_at: Arc<Mutex<Option<Instant>>>,
+}
+
+/// 同一时刻的墙钟时间和连接后经过的时间
+#[derive(Debug, Clone, Copy)]
+pub struct Stamp {
+    pub wall: DateTime<Local>,
+    /// 未连接时为 None
+    pub since_connect: Option<Duration>,
+}
+
+impl Clock {
+    pub fn now(&self) -> Stamp {
+        let connected_at = *self.connected_at.lock().unwrap_or_else(|e| e.into_inner());
+        Stamp {
+            wall: Local::now(),
+            since_connect: connected_at.map(|t| t.elapsed()),
+        }
+    }
+
+    pub fn mark_connected(&self) {
+        *self.connected_at.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+    }
+
+    pub fn mark_disconnected(&self) {
+        *self.connected_at.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl Stamp {
+    /// 日志行前缀（不含方括号）；monotonic 时追加 " +12.345s"
+    pub fn log_prefix(&self, format: TimestampFormat, monotonic: bool) -> String {
+        let mut s = match format {
+            TimestampFormat::Time => self.wall.format("%H:%M:%S").to_string(),
+            TimestampFormat::TimeMillis => self.wall.format("%H:%M:%S%.3f").to_string(),
+            TimestampFormat::Iso => self.wall.to_rfc3339_opts(SecondsFormat::Millis, false),
+        };
+        if let (true, Some(d)) = (monotonic, self.since_connect) {
+            s.push_str(&format!(" +{:.3}s", d.as_secs_f64()));
+        }
+        s
+    }
+}
+
+/// 解析行首的时间戳前缀，返回 (时间, 余下的内容)
+/// 只有时分秒的格式用 file_date 补上日期；ISO 格式按本地时间返回
+pub fn parse_log_timestamp(line: &str, file_date: NaiveDate) -> Option<(NaiveDateTime, &str)> {
+    let rest = line.strip_prefix('[')?;
+    let (inside, after) = rest.split_once(']')?;
+
+    let mut parts = inside.split(' ');
+    let wall = parts.next()?;
+    // 可选的单调时间 "+12.345s"
+    if let Some(mono) = parts.next() {
+        let secs = mono.strip_prefix('+')?.strip_suffix('s')?;
+        secs.parse::<f64>().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let time = if let Ok(t) = NaiveTime::parse_from_str(wall, "%H:%M:%S%.f") {
+        file_date.and_time(t)
+    } else {
+        DateTime::parse_from_rfc3339(wall)
+            .ok()?
+            .with_timezone(&Local)
+            .naive_local()
+    };
+    Some((time, after))
+}
+
+/// at 往前推 ms 毫秒（阶段报告按 Duration 倒推开始时间）；
+/// 串口乱码可能给出极大的 Duration，超出范围时返回 None
+pub fn minus_millis(at: NaiveDateTime, ms: f64) -> Option<NaiveDateTime> {
+    let ms = ms.round();
+    if !ms.is_finite() {
+        return None;
+    }
+    let delta = TimeDelta::try_milliseconds(ms as i64)?;
+    at.checked_sub_signed(delta)
+}
+
+/// 去掉行首的时间戳前缀（没有则原样返回）
+pub fn strip_log_timestamp(line: &str) -> &str {
+    match parse_log_timestamp(line, NaiveDate::MIN) {
+        Some((_, rest)) => rest,
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").unwrap()
+    }
+
+    #[test]
+    fn minus_millis_rejects_out_of_range() {
+        let t = at("2025-12-12 13:33:21");
+        assert_eq!(minus_millis(t, 18740.0), Some(at("2025-12-12 13:33:02.260")));
+        // 乱码行里的超大 Duration
+        assert_eq!(minus_millis(t, 99999999999999999999.0), None);
+        assert_eq!(minus_millis(t, f64::INFINITY), None);
+        assert_eq!(minus_millis(t, f64::NAN), None);
+    }
+
+    fn stamp(monotonic: bool) -> Stamp {
+        let wall = at("2025-12-12 13:31:55.123")
+            .and_local_timezone(Local)
+            .single()
+            .unwrap();
+        Stamp {
+            wall,
+            since_connect: monotonic.then(|| Duration::from_millis(12_345)),
+        }
+    }
+
+    // 前缀写成一行日志再读回来
+    fn round_trip(format: TimestampFormat, monotonic: bool) -> (String, NaiveDateTime) {
+        let prefix = stamp(true).log_prefix(format, monotonic);
+        let line = format!("[{}] [Live] Stage:1 | Count:2 | Total:3", prefix);
+        let date = NaiveDate::from_ymd_opt(2025, 12, 12).unwrap();
+        let (t, rest) = parse_log_timestamp(&line, date).unwrap();
+        assert_eq!(rest, " [Live] Stage:1 | Count:2 | Total:3");
+        assert_eq!(strip_log_timestamp(&line), rest);
+        (prefix, t)
+    }
+
+    #[test]
+    fn time_prefix_round_trips() {
+        for monotonic in [false, true] {
+            let (prefix, t) = round_trip(TimestampFormat::Time, monotonic);
+            let expected = if monotonic { "13:31:55 +12.345s" } else { "13:31:55" };
+            assert_eq!(prefix, expected);
+            assert_eq!(t, at("2025-12-12 13:31:55"));
+        }
+    }
+
+    #[test]
+    fn millis_prefix_round_trips() {
+        for monotonic in [false, true] {
+            let (prefix, t) = round_trip(TimestampFormat::TimeMillis, monotonic);
+            let expected = if monotonic { "13:31:55.123 +12.345s" } else { "13:31:55.123" };
+            assert_eq!(prefix, expected);
+            assert_eq!(t, at("2025-12-12 13:31:55.123"));
+        }
+    }
+
+    #[test]
+    fn iso_prefix_round_trips() {
+        for monotonic in [false, true] {
+            let (prefix, t) = round_trip(TimestampFormat::Iso, monotonic);
+            assert!(prefix.starts_with("2025-12-12T13:31:55.123"), "{}", prefix);
+            assert_eq!(prefix.ends_with(" +12.345s"), monotonic);
+            assert_eq!(t, at("2025-12-12 13:31:55.123"));
+        }
+    }
+
+    #[test]
+    fn monotonic_suffix_needs_a_connection() {
+        let s = stamp(false);
+        assert_eq!(s.log_prefix(TimestampFormat::Time, true), "13:31:55");
+    }
+
+    #[test]
+    fn strip_leaves_unstamped_lines_alone() {
+        for line in [
+            "[Live] Stage:1 | Count:1 | Total:1 | Wait:135 ms",
+            "[ERROR] 连接 TCP 127.0.0.1:5000 失败",
+            "[13:31:55 extra] text",
+            "[12.5s] text",
+            "SYSTEM IS RUNNING",
+        ] {
+            assert_eq!(strip_log_timestamp(line), line);
+        }
+    }
+}
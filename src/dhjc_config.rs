@@ -0,0 +1,1452 @@
+// src/dhjc_config.rs
+//
+// 配置加载：
+// - dhjc_config.toml：依次查找 当前目录 -> 可执行文件目录 -> 用户配置目录
+//   （Windows: %APPDATA%\dhjc，其它: $XDG_CONFIG_HOME/dhjc 或 ~/.config/dhjc）
+// - 命令行参数优先级高于配置文件，方便桌面快捷方式按工位预设启动
+// - GUI 修改的设置可回写到配置文件（保留注释与键顺序，原子替换）
+// - 加载时校验各项取值，问题带文件名 + 行号交给 GUI 显示
+// - [profile.xxx] 按工位覆盖连接/日志/显示设置，顶层设置作为公共默认值
+// - 运行中重新加载（热加载），保留当前 profile 与命令行覆盖
+
+use crate::dhjc_alarm::{AlarmKind, AlarmRule};
+use crate::dhjc_clock::TimestampFormat;
+use crate::dhjc_rate::{RateMode, RateSettings};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, ImDocument, Item, Table, TableLike, Value};
+
+pub const CONFIG_FILE_NAME: &str = "dhjc_config.toml";
+
+const SAMPLE_CONFIG: &str = r#"# DHJC Rust GUI 配置文件
+
+# 串口模式
+port_name = "COM3"
+baud_rate = 115200
+
+# TCP 模式
+use_tcp  = false
+tcp_host = "127.0.0.1"
+tcp_port = 5000
+
+# 日志目录
+log_folder = "logs"
+
+# 窗口置顶
+always_on_top = false
+
+# 连接成功后自动保存当前设置
+auto_save_on_connect = false
+
+# 运行中修改本文件后自动重新加载（连接设置变化时会先询问是否重连）
+hot_reload = true
+
+# 额外输出 JSON Lines 事件日志（logs/YYYY-MM-DD.jsonl），每行一个解析后的事件
+json_log = false
+# 把 session / 阶段报告 / 汇总 / 降采样的 Live 总数写入 SQLite（logs/dhjc.sqlite3），便于查询
+sqlite_log = true
+
+# 日志保留（0 表示不限制）：
+# 超过天数 / 目录总大小时从最早的日期开始删除，当天的文件不会删
+log_max_age_days = 0
+log_max_total_mb = 0
+# 单个文本日志超过该大小时切到 YYYY-MM-DD.1.txt、.2.txt ...
+log_max_file_mb = 100
+# 往日的 .txt / .jsonl 压缩成 .gz
+log_compress = true
+# 每个 session（复位 / 启动 到 TOTAL SUMMARY）单独一个文本日志
+# YYYY-MM-DD_HHMMSS_session.txt，并在 sessions_index.csv 里记录起止时间和汇总
+log_per_session = false
+# 日志行时间戳："time" = [13:31:55]，"time_ms" = [13:31:55.123]，
+# "iso" = [2025-12-12T13:31:55.123+08:00]
+log_timestamp = "time"
+# 时间戳后追加连接以来的单调时间，如 [13:31:55.123 +12.345s]（不受系统校时影响）
+log_monotonic = false
+# TOTAL SUMMARY 的 Active Time 与各阶段 Duration 之和相差超过该值（毫秒）时提示
+active_time_tolerance_ms = 50
+# Rate 的算法："window" = 最近 rate_window_ms 内的脉冲数 / 时长，
+# "interval" = 最近两个脉冲之间的瞬时间隔
+rate_mode = "window"
+rate_window_ms = 5000
+# Rate 的 EMA 平滑时间常数（毫秒），0 表示不平滑
+rate_smoothing_ms = 0
+# Total / Rate 曲线的横轴显示本地时间（HH:MM:SS），false 为连接以来的秒数；界面上也可切换
+plot_clock_time = false
+# 事件日志中的设备标识，不填则用 profile 名或串口号 / TCP 地址
+# device_id = "bench_a"
+
+# 批次模式达到目标弧数时发给 MCU 的命令（发送时自动加换行），不填则只提示、不发送
+# batch_stop_command = "S"
+
+# 报警规则：每条一个 [[alarm]]，触发后在顶部横幅显示，可确认 / 清除，状态变化写入日志
+#   kind = "rate_above"       Rate 高于 value（脉冲/秒）持续 for_s 秒
+#   kind = "rate_below"       阶段进行中 Rate 低于 value 持续 for_s 秒
+#   kind = "stage_arcs_above" 阶段报告的 Total Arcs 大于 value
+#   kind = "no_pulses"        阶段进行中 for_s 秒没有新脉冲
+#   kind = "session_timeout"  TOTAL SUMMARY 为超时结束
+#   kind = "error_line"       收到 [ERROR] 行
+# sound = true 时触发后响铃，确认前每隔几秒重复
+# [[alarm]]
+# name  = "Rate 过高"
+# kind  = "rate_above"
+# value = 50
+# for_s = 5
+# sound = true
+#
+# [[alarm]]
+# name  = "停弧"
+# kind  = "no_pulses"
+# for_s = 30
+
+# 多工位：每个 [profile.xxx] 可覆盖上面的连接 / 日志 / 显示设置，
+# 启动时使用 default_profile，或命令行 --profile xxx
+# default_profile = "bench_a"
+#
+# [profile.bench_a]
+# port_name  = "COM5"
+# log_folder = "logs/bench_a"
+#
+# [profile.bridge]
+# use_tcp  = true
+# tcp_host = "192.168.1.20"
+# tcp_port = 5000
+"#;
+
+const USAGE: &str = "\
+用法: dhjc_rust_gui [选项]
+      dhjc_rust_gui import [--date YYYY-MM-DD] <日志文件>...   把文本日志解析为 JSON Lines
+      dhjc_rust_gui plot [--out DIR] [--size WxH] <日志文件>...     把日志曲线渲染成 PNG/SVG
+
+选项:
+  --config <path>       指定配置文件（默认按 当前目录/程序目录/用户配置目录 查找）
+  --profile <name>      使用配置文件中的 [profile.<name>]
+  --port <name>         串口号，例如 COM3 或 /dev/ttyUSB0（切换为串口模式）
+  --baud <rate>         波特率，例如 115200
+  --tcp <host:port>     TCP 地址，例如 127.0.0.1:5000（切换为 TCP 模式）
+  --log-folder <path>   日志目录
+  --replay <file>       回放一个已有的日志文件（切换为回放模式）
+  --auto-connect        启动后自动连接
+  -h, --help            显示本帮助";
+
+/// 可以在 [profile.xxx] 中覆盖的键
+const PROFILE_KEYS: &[&str] = &[
+    "port_name",
+    "baud_rate",
+    "log_folder",
+    "use_tcp",
+    "tcp_host",
+    "tcp_port",
+    "always_on_top",
+    "device_id",
+];
+
+/// [[alarm]] 中的键
+const ALARM_KEYS: &[&str] = &["name", "kind", "value", "for_s", "sound"];
+
+/// 只能写在顶层的键
+const GLOBAL_KEYS: &[&str] = &[
+    "auto_save_on_connect",
+    "hot_reload",
+    "json_log",
+    "sqlite_log",
+    "log_max_age_days",
+    "log_max_total_mb",
+    "log_max_file_mb",
+    "log_compress",
+    "log_per_session",
+    "log_timestamp",
+    "log_monotonic",
+    "active_time_tolerance_ms",
+    "rate_mode",
+    "rate_window_ms",
+    "rate_smoothing_ms",
+    "plot_clock_time",
+    "batch_stop_command",
+    "alarm",
+    "default_profile",
+    "profile",
+];
+
+const MAX_BAUD_RATE: i64 = 4_000_000;
+
+const STANDARD_BAUD_RATES: &[i64] = &[
+    300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 38400, 57600, 115_200, 230_400, 460_800,
+    921_600, 1_000_000, 2_000_000,
+];
+
+// 数值先按 i64 读进来，范围检查由 validate 给出可读的提示
+#[derive(Debug, Default, Deserialize)]
+struct RawConfig {
+    port_name: Option<String>,
+    baud_rate: Option<i64>,
+    log_folder: Option<String>,
+    use_tcp: Option<bool>,
+    tcp_host: Option<String>,
+    tcp_port: Option<i64>,
+    always_on_top: Option<bool>,
+    device_id: Option<String>,
+    auto_save_on_connect: Option<bool>,
+    hot_reload: Option<bool>,
+    json_log: Option<bool>,
+    sqlite_log: Option<bool>,
+    log_max_age_days: Option<i64>,
+    log_max_total_mb: Option<i64>,
+    log_max_file_mb: Option<i64>,
+    log_compress: Option<bool>,
+    log_per_session: Option<bool>,
+    log_timestamp: Option<String>,
+    log_monotonic: Option<bool>,
+    active_time_tolerance_ms: Option<i64>,
+    rate_mode: Option<String>,
+    rate_window_ms: Option<i64>,
+    rate_smoothing_ms: Option<i64>,
+    plot_clock_time: Option<bool>,
+    batch_stop_command: Option<String>,
+    #[serde(default)]
+    alarm: Vec<RawAlarm>,
+    default_profile: Option<String>,
+    #[serde(default)]
+    profile: BTreeMap<String, RawProfile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawAlarm {
+    name: Option<String>,
+    kind: Option<String>,
+    value: Option<f64>,
+    for_s: Option<f64>,
+    sound: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawProfile {
+    port_name: Option<String>,
+    baud_rate: Option<i64>,
+    log_folder: Option<String>,
+    use_tcp: Option<bool>,
+    tcp_host: Option<String>,
+    tcp_port: Option<i64>,
+    always_on_top: Option<bool>,
+    device_id: Option<String>,
+}
+
+impl RawConfig {
+    /// 顶层的可覆盖项，当作一个匿名 profile 处理
+    fn take_base(&mut self) -> RawProfile {
+        RawProfile {
+            port_name: self.port_name.take(),
+            baud_rate: self.baud_rate.take(),
+            log_folder: self.log_folder.take(),
+            use_tcp: self.use_tcp.take(),
+            tcp_host: self.tcp_host.take(),
+            tcp_port: self.tcp_port.take(),
+            always_on_top: self.always_on_top.take(),
+            device_id: self.device_id.take(),
+        }
+    }
+}
+
+/// 校验过的一组可覆盖设置；None 表示沿用上一层
+#[derive(Debug, Default, Clone)]
+struct ProfileSettings {
+    port_name: Option<String>,
+    baud_rate: Option<u32>,
+    log_folder: Option<String>,
+    /// log_folder 所在行，实际使用时才检查可写，报错要用
+    log_folder_line: Option<usize>,
+    use_tcp: Option<bool>,
+    tcp_host: Option<String>,
+    tcp_port: Option<u16>,
+    always_on_top: Option<bool>,
+    device_id: Option<String>,
+}
+
+/// 日志保留策略，0 表示不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogRetention {
+    pub max_age_days: u64,
+    pub max_total_mb: u64,
+    /// 单个文本日志的大小上限，超过后切到下一个分段
+    pub max_file_mb: u64,
+    /// 压缩往日的日志
+    pub compress: bool,
+}
+
+impl Default for LogRetention {
+    fn default() -> Self {
+        Self {
+            max_age_days: 0,
+            max_total_mb: 0,
+            max_file_mb: 100,
+            compress: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub port_name: String,
+    pub baud_rate: u32,
+    pub log_folder: String,
+    pub use_tcp: bool,
+    pub tcp_host: String,
+    pub tcp_port: u16,
+    pub always_on_top: bool,
+    /// 写入事件日志的设备标识；为空时用 profile 名或连接地址
+    pub device_id: Option<String>,
+    pub auto_save_on_connect: bool,
+    /// 运行中监视配置文件，改动后自动重新加载
+    pub hot_reload: bool,
+    /// 额外输出 JSON Lines 事件日志（logs/YYYY-MM-DD.jsonl）
+    pub json_log: bool,
+    /// 写入 SQLite 数据库（logs/dhjc.sqlite3）
+    pub sqlite_log: bool,
+    pub log_retention: LogRetention,
+    /// 文本日志按 session 分文件
+    pub log_per_session: bool,
+    /// 文本日志行的时间戳格式
+    pub log_timestamp: TimestampFormat,
+    /// 时间戳后追加连接以来的单调时间
+    pub log_monotonic: bool,
+    /// Active Time 与 Duration 之和允许的偏差
+    pub active_time_tolerance_ms: u64,
+    /// 顶部 Rate 的计算方式
+    pub rate: RateSettings,
+    /// 曲线横轴默认显示本地时间
+    pub plot_clock_time: bool,
+    /// 批次达到目标时发给 MCU 的命令（不含换行）
+    pub batch_stop_command: Option<String>,
+    /// [[alarm]] 报警规则
+    pub alarms: Vec<AlarmRule>,
+
+    /// 当前 profile，None 表示只用顶层设置
+    pub profile: Option<String>,
+    /// 配置文件里的全部 profile 名称（按名称排序）
+    pub profile_names: Vec<String>,
+
+    /// 回放文件（仅来自命令行）
+    pub replay_file: Option<String>,
+    /// 启动后自动连接（仅来自命令行）
+    pub auto_connect: bool,
+    /// 实际使用的配置文件路径
+    pub config_path: PathBuf,
+
+    default_profile: Option<String>,
+    base: ProfileSettings,
+    profiles: BTreeMap<String, ProfileSettings>,
+    /// 启动时的命令行参数；切换 profile 后不再叠加
+    cli: CliArgs,
+    cli_active: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            port_name: "COM3".to_string(),
+            baud_rate: 115_200,
+            log_folder: "logs".to_string(),
+            use_tcp: false,
+            tcp_host: "127.0.0.1".to_string(),
+            tcp_port: 5000,
+            always_on_top: false,
+            device_id: None,
+            auto_save_on_connect: false,
+            hot_reload: true,
+            json_log: false,
+            sqlite_log: true,
+            log_retention: LogRetention::default(),
+            log_per_session: false,
+            log_timestamp: TimestampFormat::Time,
+            log_monotonic: false,
+            active_time_tolerance_ms: 50,
+            rate: RateSettings::default(),
+            plot_clock_time: false,
+            batch_stop_command: None,
+            alarms: Vec::new(),
+            profile: None,
+            profile_names: Vec::new(),
+            replay_file: None,
+            auto_connect: false,
+            config_path: PathBuf::from(CONFIG_FILE_NAME),
+            default_profile: None,
+            base: ProfileSettings::default(),
+            profiles: BTreeMap::new(),
+            cli: CliArgs::default(),
+            cli_active: false,
+        }
+    }
+}
+
+// ----------------- 校验结果 -----------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueLevel {
+    Warning,
+    Error,
+}
+
+/// 一条配置问题；line 从 1 开始
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub level: IssueLevel,
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            IssueLevel::Warning => "WARN",
+            IssueLevel::Error => "ERROR",
+        };
+        match self.line {
+            Some(line) => write!(
+                f,
+                "[{}] {}:{}: {}",
+                level,
+                self.file.to_string_lossy(),
+                line,
+                self.message
+            ),
+            None => write!(
+                f,
+                "[{}] {}: {}",
+                level,
+                self.file.to_string_lossy(),
+                self.message
+            ),
+        }
+    }
+}
+
+/// 收集问题用，带上文件内容以便把键换算成行号
+struct IssueSink<'a> {
+    file: &'a Path,
+    doc: Option<ImDocument<&'a str>>,
+    content: &'a str,
+    issues: Vec<ConfigIssue>,
+}
+
+impl<'a> IssueSink<'a> {
+    fn new(file: &'a Path, content: &'a str) -> Self {
+        Self {
+            file,
+            doc: ImDocument::parse(content).ok(),
+            content,
+            issues: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, level: IssueLevel, line: Option<usize>, message: String) {
+        self.issues.push(ConfigIssue {
+            level,
+            file: self.file.to_path_buf(),
+            line,
+            message,
+        });
+    }
+
+    /// path 为所在表的路径，顶层为空，例如 ["profile", "bench_a"]；
+    /// [[xxx]] 数组后跟序号，例如 ["alarm", "0"]
+    fn table(&self, path: &[&str]) -> Option<&dyn TableLike> {
+        let mut table: &dyn TableLike = self.doc.as_ref()?.as_table();
+        let mut names = path.iter();
+        while let Some(name) = names.next() {
+            let item = table.get(name)?;
+            table = match item.as_array_of_tables() {
+                Some(array) => array.get(names.next()?.parse().ok()?)?,
+                None => item.as_table_like()?,
+            };
+        }
+        Some(table)
+    }
+
+    fn line_of_key(&self, path: &[&str], key: &str) -> Option<usize> {
+        let (k, _) = self.table(path)?.get_key_value(key)?;
+        k.span().map(|r| line_of_offset(self.content, r.start))
+    }
+
+    fn key_issue(&mut self, level: IssueLevel, path: &[&str], key: &str, message: String) {
+        let line = self.line_of_key(path, key);
+        self.push(level, line, message);
+    }
+
+    /// 表中不在 allowed 里的键都报 "未知配置项"
+    fn check_unknown_keys(&mut self, path: &[&str], allowed: &[&[&str]]) {
+        let unknown: Vec<String> = match self.table(path) {
+            Some(table) => table
+                .iter()
+                .map(|(k, _)| k.to_string())
+                .filter(|k| !allowed.iter().any(|keys| keys.contains(&k.as_str())))
+                .collect(),
+            None => Vec::new(),
+        };
+        for key in unknown {
+            // [[xxx]] 数组的序号显示为 xxx[1]（从 1 开始）
+            let mut full = String::new();
+            for name in path.iter().copied().chain([key.as_str()]) {
+                match name.parse::<usize>() {
+                    Ok(i) => full.push_str(&format!("[{}]", i + 1)),
+                    Err(_) if full.is_empty() => full.push_str(name),
+                    Err(_) => full.push_str(&format!(".{}", name)),
+                }
+            }
+            self.key_issue(
+                IssueLevel::Warning,
+                path,
+                &key,
+                format!("未知配置项 `{}`，已忽略", full),
+            );
+        }
+    }
+}
+
+fn line_of_offset(content: &str, offset: usize) -> usize {
+    let end = offset.min(content.len());
+    content.as_bytes()[..end]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count()
+        + 1
+}
+
+impl AppConfig {
+    /// 按 配置文件 -> profile -> 命令行 的顺序合并出最终配置，并返回发现的配置问题
+    pub fn load(cli: &CliArgs) -> (Self, Vec<ConfigIssue>) {
+        let path = find_config_file(cli);
+        let (mut cfg, mut issues) = Self::load_file(&path, cli.config.is_some());
+        cfg.config_path = path;
+
+        let wanted = cli.profile.clone().or_else(|| cfg.default_profile.clone());
+        if let Err(e) = cfg.select_profile(wanted.as_deref()) {
+            issues.push(ConfigIssue {
+                level: IssueLevel::Error,
+                file: cfg.config_path.clone(),
+                line: None,
+                message: format!("{}，使用顶层设置", e),
+            });
+            let _ = cfg.select_profile(None);
+        }
+        cfg.check_selected_log_folder(&mut issues);
+
+        cfg.apply_cli(cli);
+
+        // 命令行给的日志目录在这里检查，文件里的由 check_selected_log_folder 检查
+        if cli.log_folder.is_some() {
+            if let Err(e) = check_log_folder(&cfg.log_folder) {
+                issues.push(ConfigIssue {
+                    level: IssueLevel::Error,
+                    file: cfg.config_path.clone(),
+                    line: None,
+                    message: format!("--log-folder \"{}\" 不可写: {}", cfg.log_folder, e),
+                });
+            }
+        }
+
+        for issue in &issues {
+            eprintln!("[CFG] {}", issue);
+        }
+
+        println!(
+            "[CFG] 使用配置: file={} profile={} mode={} port={} baud={} tcp={}:{} log_folder={}{}",
+            cfg.config_path.to_string_lossy(),
+            cfg.profile.as_deref().unwrap_or("-"),
+            if cfg.replay_file.is_some() {
+                "Replay"
+            } else if cfg.use_tcp {
+                "TCP"
+            } else {
+                "Serial"
+            },
+            cfg.port_name,
+            cfg.baud_rate,
+            cfg.tcp_host,
+            cfg.tcp_port,
+            cfg.log_folder,
+            if cfg.auto_connect {
+                " auto_connect"
+            } else {
+                ""
+            }
+        );
+        (cfg, issues)
+    }
+
+    fn load_file(path: &Path, explicit: bool) -> (Self, Vec<ConfigIssue>) {
+        let default_cfg = AppConfig::default();
+
+        if !path.exists() {
+            if explicit {
+                let issue = ConfigIssue {
+                    level: IssueLevel::Error,
+                    file: path.to_path_buf(),
+                    line: None,
+                    message: "指定的配置文件不存在，使用默认".to_string(),
+                };
+                return (default_cfg, vec![issue]);
+            }
+            let _ = fs::write(path, SAMPLE_CONFIG);
+            println!("[CFG] 未找到 dhjc_config.toml，已生成示例配置文件，使用默认。");
+            return (default_cfg, Vec::new());
+        }
+
+        match Self::parse_file(path) {
+            Ok(r) => r,
+            Err(mut issue) => {
+                issue.message.push_str("，使用默认");
+                (default_cfg, vec![issue])
+            }
+        }
+    }
+
+    /// 读取并校验配置文件；读不了或语法/类型错误时返回 Err
+    fn parse_file(path: &Path) -> Result<(Self, Vec<ConfigIssue>), ConfigIssue> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigIssue {
+            level: IssueLevel::Error,
+            file: path.to_path_buf(),
+            line: None,
+            message: format!("读取失败: {}", e),
+        })?;
+
+        let mut sink = IssueSink::new(path, &content);
+
+        let raw: RawConfig = toml::from_str(&content).map_err(|e| ConfigIssue {
+            level: IssueLevel::Error,
+            file: path.to_path_buf(),
+            line: e.span().map(|r| line_of_offset(&content, r.start)),
+            message: format!("解析失败: {}", e.message()),
+        })?;
+
+        let cfg = Self::validate(raw, &mut sink);
+        Ok((cfg, sink.issues))
+    }
+
+    /// 重新读取配置文件（热加载用）
+    ///
+    /// 沿用当前 profile、回放文件和仍然生效的命令行覆盖；
+    /// 读不了或解析失败时返回 Err，调用方应继续使用旧配置。
+    pub fn reload(&self) -> Result<(Self, Vec<ConfigIssue>), ConfigIssue> {
+        let (mut cfg, mut issues) = Self::parse_file(&self.config_path)?;
+        cfg.config_path = self.config_path.clone();
+        cfg.replay_file = self.replay_file.clone();
+
+        if let Err(e) = cfg.select_profile(self.profile.as_deref()) {
+            issues.push(ConfigIssue {
+                level: IssueLevel::Warning,
+                file: cfg.config_path.clone(),
+                line: None,
+                message: format!("{}，改用顶层设置", e),
+            });
+            let _ = cfg.select_profile(None);
+        }
+        cfg.check_selected_log_folder(&mut issues);
+        if self.cli_active {
+            cfg.apply_cli(&self.cli);
+        }
+        Ok((cfg, issues))
+    }
+
+    /// 连接相关的设置是否不同；不同则需要重新连接才能生效
+    pub fn connection_differs(&self, other: &AppConfig) -> bool {
+        self.use_tcp != other.use_tcp
+            || self.port_name != other.port_name
+            || self.baud_rate != other.baud_rate
+            || self.tcp_host != other.tcp_host
+            || self.tcp_port != other.tcp_port
+    }
+
+    /// 文本日志的设置是否变化（需要重建 LogWriter）
+    pub fn text_log_differs(&self, other: &AppConfig) -> bool {
+        self.log_folder != other.log_folder
+            || self.log_retention != other.log_retention
+            || self.log_per_session != other.log_per_session
+            || self.log_timestamp != other.log_timestamp
+            || self.log_monotonic != other.log_monotonic
+    }
+
+    /// 逐项检查取值；有问题的项被忽略（沿用默认或上一层），其它项照常生效
+    fn validate(mut raw: RawConfig, sink: &mut IssueSink<'_>) -> Self {
+        let mut cfg = AppConfig::default();
+
+        sink.check_unknown_keys(&[], &[PROFILE_KEYS, GLOBAL_KEYS]);
+        cfg.base = validate_profile(raw.take_base(), sink, &[]);
+
+        for (name, raw_profile) in std::mem::take(&mut raw.profile) {
+            let path = ["profile", name.as_str()];
+            sink.check_unknown_keys(&path, &[PROFILE_KEYS]);
+            let settings = validate_profile(raw_profile, sink, &path);
+            cfg.profiles.insert(name, settings);
+        }
+        cfg.profile_names = cfg.profiles.keys().cloned().collect();
+
+        if let Some(name) = raw.default_profile {
+            if cfg.profiles.contains_key(&name) {
+                cfg.default_profile = Some(name);
+            } else {
+                sink.key_issue(
+                    IssueLevel::Error,
+                    &[],
+                    "default_profile",
+                    format!(
+                        "default_profile `{}` 没有对应的 [profile.{}]，已忽略",
+                        name, name
+                    ),
+                );
+            }
+        }
+        if let Some(a) = raw.auto_save_on_connect {
+            cfg.auto_save_on_connect = a;
+        }
+        if let Some(h) = raw.hot_reload {
+            cfg.hot_reload = h;
+        }
+        if let Some(j) = raw.json_log {
+            cfg.json_log = j;
+        }
+        if let Some(q) = raw.sqlite_log {
+            cfg.sqlite_log = q;
+        }
+
+        let r = &mut cfg.log_retention;
+        if let Some(v) = non_negative(sink, "log_max_age_days", raw.log_max_age_days) {
+            r.max_age_days = v;
+        }
+        if let Some(v) = non_negative(sink, "log_max_total_mb", raw.log_max_total_mb) {
+            r.max_total_mb = v;
+        }
+        if let Some(v) = non_negative(sink, "log_max_file_mb", raw.log_max_file_mb) {
+            r.max_file_mb = v;
+        }
+        if let Some(c) = raw.log_compress {
+            r.compress = c;
+        }
+        if let Some(p) = raw.log_per_session {
+            cfg.log_per_session = p;
+        }
+        if let Some(t) = raw.log_timestamp {
+            match TimestampFormat::parse(&t) {
+                Some(f) => cfg.log_timestamp = f,
+                None => sink.key_issue(
+                    IssueLevel::Error,
+                    &[],
+                    "log_timestamp",
+                    format!(
+                        "log_timestamp `{}` 无效（可选 \"time\" / \"time_ms\" / \"iso\"），已忽略",
+                        t
+                    ),
+                ),
+            }
+        }
+        if let Some(m) = raw.log_monotonic {
+            cfg.log_monotonic = m;
+        }
+        if let Some(v) = non_negative(
+            sink,
+            "active_time_tolerance_ms",
+            raw.active_time_tolerance_ms,
+        ) {
+            cfg.active_time_tolerance_ms = v;
+        }
+        if let Some(m) = raw.rate_mode {
+            match RateMode::parse(&m) {
+                Some(mode) => cfg.rate.mode = mode,
+                None => sink.key_issue(
+                    IssueLevel::Error,
+                    &[],
+                    "rate_mode",
+                    format!(
+                        "rate_mode `{}` 无效（可选 \"window\" / \"interval\"），已忽略",
+                        m
+                    ),
+                ),
+            }
+        }
+        match non_negative(sink, "rate_window_ms", raw.rate_window_ms) {
+            Some(0) => sink.key_issue(
+                IssueLevel::Error,
+                &[],
+                "rate_window_ms",
+                "rate_window_ms 不能为 0，已忽略".to_string(),
+            ),
+            Some(v) => cfg.rate.window_s = v as f64 / 1000.0,
+            None => {}
+        }
+        if let Some(v) = non_negative(sink, "rate_smoothing_ms", raw.rate_smoothing_ms) {
+            cfg.rate.smoothing_s = v as f64 / 1000.0;
+        }
+        if let Some(c) = raw.plot_clock_time {
+            cfg.plot_clock_time = c;
+        }
+        if let Some(c) = raw.batch_stop_command {
+            let c = c.trim();
+            if c.contains('\n') {
+                sink.key_issue(
+                    IssueLevel::Error,
+                    &[],
+                    "batch_stop_command",
+                    "batch_stop_command 只能是一行，已忽略".to_string(),
+                );
+            } else if !c.is_empty() {
+                cfg.batch_stop_command = Some(c.to_string());
+            }
+        }
+        for (i, a) in raw.alarm.into_iter().enumerate() {
+            let index = i.to_string();
+            let path = ["alarm", index.as_str()];
+            sink.check_unknown_keys(&path, &[ALARM_KEYS]);
+            if let Some(rule) = validate_alarm(a, sink, &path) {
+                cfg.alarms.push(rule);
+            }
+        }
+        cfg
+    }
+
+    /// 选定 profile 后检查实际使用的日志目录；不可写时报错并忽略该项，退回上一层的设置
+    fn check_selected_log_folder(&mut self, issues: &mut Vec<ConfigIssue>) {
+        loop {
+            let profile = self.profile.clone();
+            let layer = match profile.as_deref().and_then(|n| self.profiles.get_mut(n)) {
+                Some(p) if p.log_folder.is_some() => p,
+                _ => &mut self.base,
+            };
+            let folder = match &layer.log_folder {
+                Some(f) => f.clone(),
+                // 默认的 logs 不检查，打开日志时再报错
+                None => return,
+            };
+            let e = match check_log_folder(&folder) {
+                Ok(()) => return,
+                Err(e) => e,
+            };
+            issues.push(ConfigIssue {
+                level: IssueLevel::Error,
+                file: self.config_path.clone(),
+                line: layer.log_folder_line,
+                message: format!("日志目录 \"{}\" 不可写: {}，已忽略", folder, e),
+            });
+            layer.log_folder = None;
+            let _ = self.select_profile(profile.as_deref());
+        }
+    }
+
+    /// 切换 profile：默认值 <- 顶层 <- profile；之前的命令行覆盖不再保留
+    pub fn select_profile(&mut self, name: Option<&str>) -> Result<(), String> {
+        let overrides = match name {
+            Some(n) => Some(
+                self.profiles
+                    .get(n)
+                    .cloned()
+                    .ok_or_else(|| format!("profile `{}` 不存在", n))?,
+            ),
+            None => None,
+        };
+
+        let d = AppConfig::default();
+        self.port_name = d.port_name;
+        self.baud_rate = d.baud_rate;
+        self.log_folder = d.log_folder;
+        self.use_tcp = d.use_tcp;
+        self.tcp_host = d.tcp_host;
+        self.tcp_port = d.tcp_port;
+        self.always_on_top = d.always_on_top;
+        self.device_id = d.device_id;
+
+        let base = self.base.clone();
+        self.apply_profile_settings(&base);
+        if let Some(p) = &overrides {
+            self.apply_profile_settings(p);
+        }
+        self.profile = name.map(str::to_string);
+        self.cli_active = false;
+        Ok(())
+    }
+
+    fn apply_profile_settings(&mut self, s: &ProfileSettings) {
+        if let Some(p) = &s.port_name {
+            self.port_name = p.clone();
+        }
+        if let Some(b) = s.baud_rate {
+            self.baud_rate = b;
+        }
+        if let Some(f) = &s.log_folder {
+            self.log_folder = f.clone();
+        }
+        if let Some(u) = s.use_tcp {
+            self.use_tcp = u;
+        }
+        if let Some(h) = &s.tcp_host {
+            self.tcp_host = h.clone();
+        }
+        if let Some(p) = s.tcp_port {
+            self.tcp_port = p;
+        }
+        if let Some(t) = s.always_on_top {
+            self.always_on_top = t;
+        }
+        if let Some(d) = &s.device_id {
+            self.device_id = Some(d.clone());
+        }
+    }
+
+    /// 事件日志里的设备标识：device_id > profile 名 > 连接地址
+    pub fn device_id(&self) -> String {
+        if let Some(id) = &self.device_id {
+            return id.clone();
+        }
+        if let Some(p) = &self.profile {
+            return p.clone();
+        }
+        if self.use_tcp {
+            format!("{}:{}", self.tcp_host, self.tcp_port)
+        } else {
+            self.port_name.clone()
+        }
+    }
+
+    /// 把 GUI 可修改的设置写回配置文件
+    ///
+    /// 在原文件基础上逐键替换，保留用户注释与键顺序；
+    /// 选中了 profile 时写到对应的 [profile.xxx] 表里。
+    /// 仍是命令行给的连接设置（顶部栏没改过）不写，快捷方式的参数不进共用的配置文件。
+    /// 先写临时文件再 rename，避免中途崩溃留下半个文件。
+    pub fn save(&self) -> Result<(), String> {
+        let path = &self.config_path;
+
+        let content = if path.exists() {
+            fs::read_to_string(path)
+                .map_err(|e| format!("读取 {} 失败: {:?}", path.to_string_lossy(), e))?
+        } else {
+            SAMPLE_CONFIG.to_string()
+        };
+        let mut doc = content
+            .parse::<DocumentMut>()
+            .map_err(|e| format!("解析 {} 失败: {}", path.to_string_lossy(), e))?;
+
+        let table: &mut dyn TableLike = match &self.profile {
+            None => doc.as_table_mut(),
+            Some(name) => profile_table_mut(&mut doc, name)?,
+        };
+
+        let cli = self.cli_active.then_some(&self.cli);
+        let tcp = cli.and_then(|c| c.tcp.as_ref());
+        let use_tcp_from_cli = match cli {
+            Some(c) if c.tcp.is_some() => self.use_tcp,
+            Some(c) if c.port.is_some() => !self.use_tcp,
+            _ => false,
+        };
+
+        if cli.and_then(|c| c.port.as_ref()) != Some(&self.port_name) {
+            set_value(table, "port_name", self.port_name.as_str());
+        }
+        if cli.and_then(|c| c.baud) != Some(self.baud_rate) {
+            set_value(table, "baud_rate", self.baud_rate as i64);
+        }
+        if !use_tcp_from_cli {
+            set_value(table, "use_tcp", self.use_tcp);
+        }
+        if tcp.map(|(h, _)| h) != Some(&self.tcp_host) {
+            set_value(table, "tcp_host", self.tcp_host.as_str());
+        }
+        if tcp.map(|(_, p)| *p) != Some(self.tcp_port) {
+            set_value(table, "tcp_port", self.tcp_port as i64);
+        }
+        set_value(table, "always_on_top", self.always_on_top);
+
+        write_atomic(path, doc.to_string().as_bytes())
+            .map_err(|e| format!("写入 {} 失败: {:?}", path.to_string_lossy(), e))
+    }
+
+    fn apply_cli(&mut self, cli: &CliArgs) {
+        if let Some(p) = &cli.port {
+            self.port_name = p.clone();
+            self.use_tcp = false;
+        }
+        if let Some(b) = cli.baud {
+            self.baud_rate = b;
+        }
+        // --tcp 与 --port 同时给出时以 TCP 为准
+        if let Some((host, port)) = &cli.tcp {
+            self.tcp_host = host.clone();
+            self.tcp_port = *port;
+            self.use_tcp = true;
+        }
+        if let Some(f) = &cli.log_folder {
+            self.log_folder = f.clone();
+        }
+        if let Some(r) = &cli.replay {
+            self.replay_file = Some(r.clone());
+        }
+        self.auto_connect = cli.auto_connect;
+        self.cli = cli.clone();
+        self.cli_active = true;
+    }
+}
+
+/// 检查顶层或某个 profile 的取值
+fn non_negative(sink: &mut IssueSink<'_>, key: &str, value: Option<i64>) -> Option<u64> {
+    let v = value?;
+    if v < 0 {
+        sink.key_issue(
+            IssueLevel::Error,
+            &[],
+            key,
+            format!("{} 不能为负数 ({})，已忽略", key, v),
+        );
+        return None;
+    }
+    Some(v as u64)
+}
+
+/// 有问题的规则整条忽略；path 为 ["alarm", 序号]
+fn validate_alarm(raw: RawAlarm, sink: &mut IssueSink<'_>, path: &[&str]) -> Option<AlarmRule> {
+    let n = path[1].parse::<usize>().unwrap_or(0) + 1;
+    let fail = |sink: &mut IssueSink<'_>, key: &str, msg: String| {
+        sink.key_issue(
+            IssueLevel::Error,
+            path,
+            key,
+            format!("第 {} 条 [[alarm]] {}，该规则已忽略", n, msg),
+        );
+        None
+    };
+
+    let kind_text = match raw.kind {
+        Some(k) => k,
+        None => return fail(sink, "name", "缺少 kind".to_string()),
+    };
+    let kind = match AlarmKind::parse(&kind_text) {
+        Some(k) => k,
+        None => {
+            return fail(
+                sink,
+                "kind",
+                format!(
+                    "的 kind `{}` 无效（可选 rate_above / rate_below / stage_arcs_above / \
+                     no_pulses / session_timeout / error_line）",
+                    kind_text
+                ),
+            )
+        }
+    };
+    let value = match raw.value {
+        Some(v) if !v.is_finite() || v < 0.0 => {
+            return fail(sink, "value", format!("的 value 不能为负数 ({})", v))
+        }
+        Some(v) => v,
+        None if kind.needs_value() => {
+            return fail(sink, "kind", format!("（{}）缺少 value", kind.as_str()))
+        }
+        None => 0.0,
+    };
+    let for_s = match raw.for_s {
+        Some(v) if !v.is_finite() || v < 0.0 => {
+            return fail(sink, "for_s", format!("的 for_s 不能为负数 ({})", v))
+        }
+        Some(v) if v == 0.0 && kind.needs_duration() => {
+            return fail(
+                sink,
+                "for_s",
+                format!("（{}）的 for_s 不能为 0", kind.as_str()),
+            )
+        }
+        Some(v) => v,
+        None if kind.needs_duration() => {
+            return fail(sink, "kind", format!("（{}）缺少 for_s", kind.as_str()))
+        }
+        None => 0.0,
+    };
+
+    Some(AlarmRule {
+        name: raw
+            .name
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| kind.as_str().to_string()),
+        kind,
+        value,
+        for_s,
+        sound: raw.sound.unwrap_or(false),
+    })
+}
+
+fn validate_profile(raw: RawProfile, sink: &mut IssueSink<'_>, path: &[&str]) -> ProfileSettings {
+    let mut s = ProfileSettings::default();
+
+    if let Some(p) = raw.port_name {
+        if p.trim().is_empty() {
+            sink.key_issue(
+                IssueLevel::Error,
+                path,
+                "port_name",
+                "port_name 为空，已忽略".to_string(),
+            );
+        } else {
+            s.port_name = Some(p);
+        }
+    }
+    if let Some(b) = raw.baud_rate {
+        if !(1..=MAX_BAUD_RATE).contains(&b) {
+            sink.key_issue(
+                IssueLevel::Error,
+                path,
+                "baud_rate",
+                format!("波特率 {} 超出范围 (1 - {})，已忽略", b, MAX_BAUD_RATE),
+            );
+        } else {
+            if !STANDARD_BAUD_RATES.contains(&b) {
+                sink.key_issue(
+                    IssueLevel::Warning,
+                    path,
+                    "baud_rate",
+                    format!("波特率 {} 不是常用值，请确认与 MCU 一致", b),
+                );
+            }
+            s.baud_rate = Some(b as u32);
+        }
+    }
+    if let Some(f) = raw.log_folder {
+        if f.trim().is_empty() {
+            sink.key_issue(
+                IssueLevel::Error,
+                path,
+                "log_folder",
+                "log_folder 为空，已忽略".to_string(),
+            );
+        } else {
+            // 是否可写等选定 profile 后只查实际使用的那个（check_selected_log_folder）
+            s.log_folder_line = sink.line_of_key(path, "log_folder");
+            s.log_folder = Some(f);
+        }
+    }
+    s.use_tcp = raw.use_tcp;
+    if let Some(h) = raw.tcp_host {
+        if h.trim().is_empty() {
+            sink.key_issue(
+                IssueLevel::Error,
+                path,
+                "tcp_host",
+                "tcp_host 为空，已忽略".to_string(),
+            );
+        } else {
+            s.tcp_host = Some(h);
+        }
+    }
+    if let Some(p) = raw.tcp_port {
+        match u16::try_from(p) {
+            Ok(port) if port != 0 => s.tcp_port = Some(port),
+            _ => sink.key_issue(
+                IssueLevel::Error,
+                path,
+                "tcp_port",
+                format!("TCP 端口 {} 超出范围 (1 - 65535)，已忽略", p),
+            ),
+        }
+    }
+    s.always_on_top = raw.always_on_top;
+    if let Some(d) = raw.device_id {
+        if d.trim().is_empty() {
+            sink.key_issue(
+                IssueLevel::Error,
+                path,
+                "device_id",
+                "device_id 为空，已忽略".to_string(),
+            );
+        } else {
+            s.device_id = Some(d);
+        }
+    }
+    s
+}
+
+// ----------------- 命令行 -----------------
+
+#[derive(Debug, Default, Clone)]
+pub struct CliArgs {
+    pub config: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub port: Option<String>,
+    pub baud: Option<u32>,
+    pub tcp: Option<(String, u16)>,
+    pub log_folder: Option<String>,
+    pub replay: Option<String>,
+    pub auto_connect: bool,
+}
+
+impl CliArgs {
+    /// 解析进程参数；--help 或参数错误时直接退出
+    pub fn from_env() -> Self {
+        match Self::parse(env::args().skip(1)) {
+            Ok(Some(args)) => args,
+            Ok(None) => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Err(msg) => {
+                eprintln!("[CLI] {}\n\n{}", msg, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// 返回 Ok(None) 表示请求了 --help
+    fn parse<I: Iterator<Item = String>>(mut it: I) -> Result<Option<Self>, String> {
+        let mut args = CliArgs::default();
+
+        while let Some(arg) = it.next() {
+            // 同时支持 --key value 与 --key=value
+            let (key, inline) = match arg.split_once('=') {
+                Some((k, v)) if k.starts_with("--") => (k.to_string(), Some(v.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| it.next())
+                    .ok_or_else(|| format!("参数 {} 缺少取值", key))
+            };
+
+            match key.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--config" => args.config = Some(PathBuf::from(value()?)),
+                "--profile" => args.profile = Some(value()?),
+                "--port" => args.port = Some(value()?),
+                "--baud" => {
+                    let v = value()?;
+                    let baud = v
+                        .trim()
+                        .parse::<u32>()
+                        .map_err(|_| format!("无效的波特率: {}", v))?;
+                    args.baud = Some(baud);
+                }
+                "--tcp" => args.tcp = Some(parse_host_port(&value()?)?),
+                "--log-folder" => args.log_folder = Some(value()?),
+                "--replay" => args.replay = Some(value()?),
+                "--auto-connect" => args.auto_connect = true,
+                other => return Err(format!("未知参数: {}", other)),
+            }
+        }
+
+        Ok(Some(args))
+    }
+}
+
+fn parse_host_port(s: &str) -> Result<(String, u16), String> {
+    let (host, port) = s
+        .trim()
+        .rsplit_once(':')
+        .ok_or_else(|| format!("TCP 地址应为 host:port，实际为: {}", s))?;
+    if host.is_empty() {
+        return Err(format!("TCP 地址缺少主机名: {}", s));
+    }
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| format!("无效的 TCP 端口: {}", port))?;
+    Ok((host.to_string(), port))
+}
+
+// ----------------- 配置文件查找 -----------------
+
+fn find_config_file(cli: &CliArgs) -> PathBuf {
+    if let Some(p) = &cli.config {
+        return p.clone();
+    }
+
+    // 都找不到时，在当前目录生成示例（与旧版本行为一致）
+    candidate_paths()
+        .into_iter()
+        .find(|p| p.is_file())
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME))
+}
+
+fn candidate_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(CONFIG_FILE_NAME)];
+
+    if let Ok(exe) = env::current_exe() {
+        if let Some(dir) = exe.parent() {
+            paths.push(dir.join(CONFIG_FILE_NAME));
+        }
+    }
+
+    if let Some(dir) = user_config_dir() {
+        paths.push(dir.join("dhjc").join(CONFIG_FILE_NAME));
+    }
+
+    paths
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        env::var_os("APPDATA").map(PathBuf::from)
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    }
+}
+
+/// 确认日志目录可以创建并写入文件
+// 不创建目录：目录已存在时在里面试写一个临时文件；
+// 还不存在时检查最近的已存在上级目录（日志打开时再创建）
+fn check_log_folder(folder: &str) -> std::io::Result<()> {
+    let mut dir = Path::new(folder);
+    loop {
+        match fs::metadata(dir) {
+            Ok(m) if m.is_dir() => break,
+            Ok(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{} 不是目录", dir.to_string_lossy()),
+                ))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                dir = match dir.parent() {
+                    Some(p) if !p.as_os_str().is_empty() => p,
+                    _ if dir != Path::new(".") => Path::new("."),
+                    _ => return Err(e),
+                };
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let probe = dir.join(".dhjc_write_test");
+    File::create(&probe)?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+// ----------------- 配置回写 -----------------
+
+/// 替换表中某个键的值，沿用旧值的前后缀（行尾注释等）
+fn set_value(table: &mut dyn TableLike, key: &str, v: impl Into<Value>) {
+    let mut new_value: Value = v.into();
+    match table.get_mut(key) {
+        // 原地替换，键本身（含上方注释）保持不动
+        Some(item) => {
+            if let Some(old) = item.as_value() {
+                *new_value.decor_mut() = old.decor().clone();
+            }
+            *item = Item::Value(new_value);
+        }
+        None => {
+            table.insert(key, Item::Value(new_value));
+        }
+    }
+}
+
+/// 取得 [profile.<name>] 表，不存在时新建
+fn profile_table_mut<'a>(
+    doc: &'a mut DocumentMut,
+    name: &str,
+) -> Result<&'a mut dyn TableLike, String> {
+    let profiles = doc
+        .entry("profile")
+        .or_insert_with(|| {
+            let mut t = Table::new();
+            t.set_implicit(true);
+            Item::Table(t)
+        })
+        .as_table_like_mut()
+        .ok_or_else(|| "配置项 profile 不是表".to_string())?;
+
+    profiles
+        .entry(name)
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_like_mut()
+        .ok_or_else(|| format!("profile.{} 不是表", name))
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    {
+        let mut f = File::create(&tmp_path)?;
+        f.write_all(bytes)?;
+        f.sync_all()?;
+    }
+
+    fs::rename(&tmp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp_path);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config(name: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("dhjc_cfg_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn saved(path: &Path) -> toml::Value {
+        toml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn save_keeps_cli_overrides_out_of_the_file() {
+        let path = temp_config("cli_save", SAMPLE_CONFIG);
+        let cli = CliArgs {
+            config: Some(path.clone()),
+            port: Some("COM7".to_string()),
+            baud: Some(9600),
+            tcp: Some(("10.0.0.9".to_string(), 6000)),
+            ..Default::default()
+        };
+        let (mut cfg, _) = AppConfig::load(&cli);
+        assert!(cfg.use_tcp);
+        assert_eq!(cfg.port_name, "COM7");
+
+        cfg.always_on_top = true;
+        cfg.save().unwrap();
+        let v = saved(&path);
+        assert_eq!(v["port_name"].as_str(), Some("COM3"));
+        assert_eq!(v["baud_rate"].as_integer(), Some(115_200));
+        assert_eq!(v["use_tcp"].as_bool(), Some(false));
+        assert_eq!(v["tcp_host"].as_str(), Some("127.0.0.1"));
+        assert_eq!(v["tcp_port"].as_integer(), Some(5000));
+        assert_eq!(v["always_on_top"].as_bool(), Some(true));
+
+        // 顶部栏改过的值照常保存
+        cfg.port_name = "COM9".to_string();
+        cfg.tcp_port = 6001;
+        cfg.save().unwrap();
+        let v = saved(&path);
+        assert_eq!(v["port_name"].as_str(), Some("COM9"));
+        assert_eq!(v["tcp_port"].as_integer(), Some(6001));
+        assert_eq!(v["tcp_host"].as_str(), Some("127.0.0.1"));
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn save_writes_everything_without_cli_overrides() {
+        let path = temp_config("plain_save", SAMPLE_CONFIG);
+        let cli = CliArgs {
+            config: Some(path.clone()),
+            ..Default::default()
+        };
+        let (mut cfg, _) = AppConfig::load(&cli);
+        cfg.port_name = "COM5".to_string();
+        cfg.use_tcp = true;
+        cfg.save().unwrap();
+        let v = saved(&path);
+        assert_eq!(v["port_name"].as_str(), Some("COM5"));
+        assert_eq!(v["use_tcp"].as_bool(), Some(true));
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
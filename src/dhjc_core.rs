@@ -3,8 +3,13 @@
 // 从 tiny_dhjc.cpp 提出来的协议解析核心：
 // - 维护 Stage / Total / Active Time
 // - 解析 MCU 输出的 [Live]、[STAGE REPORT]、[TOTAL SUMMARY]
+// - 把多行报告块组装成结构化事件（CoreEvent），供 JSON / CSV 等输出使用
+// - 按 session 核对 Live 计数、Total Arcs、Grand Total，不一致时发出 ConsistencyWarning
+// - 另外累加各阶段 Duration，和 MCU 的 Active Time 一起放进汇总，便于发现漂移
 
-use chrono::Local;
+use crate::dhjc_clock::{strip_log_timestamp, Clock};
+use crate::dhjc_intervals::IntervalStats;
+use serde::Serialize;
 
 #[derive(Debug, Clone)]
 pub struct CoreState {
@@ -14,6 +19,16 @@ pub struct CoreState {
     pub last_timestamp: Option<String>,
 
     active_from_mcu: bool,
+
+    // 与 LogWriter 共用的时钟
+    clock: Clock,
+
+    // 正在收集的报告块，以及已完成、等待取走的事件
+    block: Option<ReportBlock>,
+    events: Vec<CoreEvent>,
+
+    // 本 session 的主机侧计数，用于一致性核对
+    tally: SessionTally,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -27,15 +42,39 @@ pub struct Change {
 
 impl CoreState {
     pub fn new() -> Self {
+        Self::with_clock(Clock::default())
+    }
+
+    /// 与 LogWriter 共用同一个时钟，Last 时间和日志时间戳一致
+    pub fn with_clock(clock: Clock) -> Self {
         Self {
             stage: 0,
             current_total: 0,
             active_time_s: 0.0,
             last_timestamp: None,
             active_from_mcu: false,
+            clock,
+            block: None,
+            events: Vec::new(),
+            tally: SessionTally::default(),
         }
     }
 
+    /// 取走自上次调用以来解析出的完整事件
+    pub fn take_events(&mut self) -> Vec<CoreEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// 正在收集 [STAGE REPORT] / [TOTAL SUMMARY] 块
+    pub fn in_block(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// 输入结束：把没等到分隔线的报告块作为不完整事件交出
+    pub fn flush(&mut self) {
+        self.finish_block(false);
+    }
+
     fn reset_session(&mut self) {
         self.current_total = 0;
         self.stage = 0;
@@ -60,6 +99,8 @@ impl CoreState {
             return change;
         }
 
+        self.collect_event(raw);
+
         // SYSTEM RESET OK -> 整体重置
         if clean.contains("SYSTEM RESET OK") {
             self.reset_session();
@@ -124,13 +165,432 @@ impl CoreState {
             change.total_changed = true;
         } else if new_total > self.current_total {
             self.current_total = new_total;
-            self.last_timestamp =
-                Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+            let now = self.clock.now().wall;
+            self.last_timestamp = Some(now.format("%Y-%m-%d %H:%M:%S").to_string());
             change.total_changed = true;
         }
     }
 }
 
+// ----------------- 结构化事件 -----------------
+
+/// 一条 [Live] 采样
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveSample {
+    pub stage: Option<i32>,
+    /// 本阶段计数（新固件 "Count:"，旧固件 "P:"）
+    pub count: Option<i32>,
+    /// 总计数（新固件 "Total:"，旧固件 "Tot:"）
+    pub total: Option<i32>,
+    pub wait_ms: Option<f64>,
+}
+
+/// 一个 [STAGE REPORT] 块
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StageReport {
+    pub stage_id: Option<i32>,
+    pub status: Option<String>,
+    /// "Total Arcs"，单脉冲报告里叫 "Total Pulses"
+    pub arcs: Option<i32>,
+    pub duration_ms: Option<f64>,
+    pub min_interval_ms: Option<f64>,
+    pub max_interval_ms: Option<f64>,
+    /// 单脉冲报告：Duration 为 "<10s (...)"，Interval 为 N/A
+    pub single_pulse: bool,
+    /// 收到结束分隔线（false 表示块被截断，可能丢行）
+    pub complete: bool,
+    /// 主机根据 Live 计数推出的间隔统计，由 GUI 在转发事件前填入
+    pub host_intervals: Option<Box<IntervalStats>>,
+}
+
+/// 一个 [TOTAL SUMMARY] 块
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TotalSummary {
+    pub status: Option<String>,
+    pub active_time_s: Option<f64>,
+    pub total_stages: Option<i32>,
+    pub grand_total: Option<i32>,
+    pub avg_frequency_hz: Option<f64>,
+    pub complete: bool,
+    /// 主机侧把本 session 各阶段 Duration 相加（秒）；中途连上或有阶段缺 Duration（如单脉冲）时为 None
+    pub summed_duration_s: Option<f64>,
+}
+
+impl TotalSummary {
+    /// MCU 的 Active Time 减去各阶段 Duration 之和
+    pub fn active_time_drift_s(&self) -> Option<f64> {
+        Some(self.active_time_s? - self.summed_duration_s?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CoreEventKind {
+    SystemReset,
+    /// "SYSTEM IS RUNNING" / "DHJC MONITOR READY" 等横幅
+    Banner(String),
+    Live(LiveSample),
+    StageReport(StageReport),
+    TotalSummary(TotalSummary),
+    /// 主机侧的 [ERROR] 行
+    Error(String),
+    /// 主机侧核对发现 MCU 报告的数字互相矛盾
+    ConsistencyWarning(ConsistencyWarning),
+}
+
+/// 一致性核对的项目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsistencyCheck {
+    /// 阶段最后一条 Live 的 Count 与该阶段的 Total Arcs
+    StageCount,
+    /// 阶段最后一条 Live 的 Total 与本 session 各阶段 Total Arcs 之和
+    LiveTotal,
+    /// 各阶段 Total Arcs 之和与 Grand Total
+    GrandTotal,
+    /// 收到的阶段报告个数与 Total Stages
+    StageTally,
+}
+
+/// expected 为由其它行推出的值，actual 为被核对的那一行报告的值
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsistencyWarning {
+    pub check: ConsistencyCheck,
+    pub stage_id: Option<i32>,
+    pub expected: i64,
+    pub actual: i64,
+}
+
+impl ConsistencyWarning {
+    pub fn message(&self) -> String {
+        let stage = self
+            .stage_id
+            .map(|id| format!("Stage {} ", id))
+            .unwrap_or_default();
+        let what = match self.check {
+            ConsistencyCheck::StageCount => "Total Arcs 与最后一条 Live 的 Count 不符",
+            ConsistencyCheck::LiveTotal => "最后一条 Live 的 Total 与各阶段 Total Arcs 之和不符",
+            ConsistencyCheck::GrandTotal => "Grand Total 与各阶段 Total Arcs 之和不符",
+            ConsistencyCheck::StageTally => "Total Stages 与收到的阶段报告个数不符",
+        };
+        format!(
+            "{}{}（应为 {}，实际 {}），可能丢行或固件计数有误",
+            stage, what, self.expected, self.actual
+        )
+    }
+}
+
+/// 解析出的完整事件，raw 为组成该事件的原始行
+#[derive(Debug, Clone)]
+pub struct CoreEvent {
+    pub kind: CoreEventKind,
+    pub raw: Vec<String>,
+}
+
+impl CoreEvent {
+    /// 事件类型名（JSON / CSV 中使用）
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            CoreEventKind::SystemReset => "system_reset",
+            CoreEventKind::Banner(_) => "banner",
+            CoreEventKind::Live(_) => "live",
+            CoreEventKind::StageReport(_) => "stage_report",
+            CoreEventKind::TotalSummary(_) => "total_summary",
+            CoreEventKind::Error(_) => "error",
+            CoreEventKind::ConsistencyWarning(_) => "consistency_warning",
+        }
+    }
+}
+
+/// 一个 session 内主机侧的累计
+#[derive(Debug, Clone, Default)]
+struct SessionTally {
+    // 从复位 / 启动横幅 / 上一个汇总之后开始统计；中途连上时不核对累计值
+    from_start: bool,
+    // 有阶段报告缺了 Total Arcs，累计值不可靠
+    incomplete: bool,
+    stages: i64,
+    arcs: i64,
+    // 各阶段 Duration 之和；有阶段缺 Duration 时为 None
+    duration_ms: Option<f64>,
+    // 当前阶段最后一条 Live
+    last_live: Option<LiveSample>,
+}
+
+impl SessionTally {
+    /// 从 0 开始计数的新 session
+    fn fresh() -> Self {
+        Self {
+            from_start: true,
+            duration_ms: Some(0.0),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Stage,
+    Summary,
+}
+
+#[derive(Debug, Clone)]
+struct ReportBlock {
+    kind: BlockKind,
+    fields: Vec<(String, String)>,
+    raw: Vec<String>,
+}
+
+impl CoreState {
+    fn collect_event(&mut self, raw: &str) {
+        let text = normalize_line(raw);
+        let text = text.as_str();
+        if text.is_empty() {
+            return;
+        }
+
+        // 报告块开始
+        let start = if text.contains("[STAGE REPORT]") {
+            Some(BlockKind::Stage)
+        } else if text.contains("[TOTAL SUMMARY]") {
+            Some(BlockKind::Summary)
+        } else {
+            None
+        };
+        if let Some(kind) = start {
+            self.finish_block(false);
+            self.block = Some(ReportBlock {
+                kind,
+                fields: Vec::new(),
+                raw: vec![raw.to_string()],
+            });
+            return;
+        }
+
+        // 报告块内部：字段行或结束分隔线
+        if let Some(block) = self.block.as_mut() {
+            if is_separator(text) {
+                block.raw.push(raw.to_string());
+                self.finish_block(true);
+                return;
+            }
+            if let Some((key, value)) = split_field(text) {
+                block.fields.push((key, value));
+                block.raw.push(raw.to_string());
+                return;
+            }
+            // 没等到分隔线就来了别的行
+            self.finish_block(false);
+        }
+
+        let kind = if text.contains("SYSTEM RESET OK") {
+            CoreEventKind::SystemReset
+        } else if text.contains("[Live]") || text.contains("[LIVE]") {
+            CoreEventKind::Live(LiveSample {
+                stage: find_int_after(text, "Stage:"),
+                count: find_int_after(text, "Count:").or_else(|| find_int_after(text, "P:")),
+                total: find_int_after(text, "Total:").or_else(|| find_int_after(text, "Tot:")),
+                wait_ms: find_double_after(text, "Wait:"),
+            })
+        } else if text.starts_with("[ERROR]") {
+            // 时间戳在 raw 里保留；消息本身不带日志前缀
+            CoreEventKind::Error(text.to_string())
+        } else if text.contains("SYSTEM IS RUNNING") || text.contains("MONITOR READY") {
+            CoreEventKind::Banner(text.to_string())
+        } else {
+            return;
+        };
+
+        match &kind {
+            CoreEventKind::SystemReset | CoreEventKind::Banner(_) => {
+                self.tally = SessionTally::fresh();
+            }
+            CoreEventKind::Live(l) => self.tally.last_live = Some(l.clone()),
+            _ => {}
+        }
+
+        self.events.push(CoreEvent {
+            kind,
+            raw: vec![raw.to_string()],
+        });
+    }
+
+    fn finish_block(&mut self, complete: bool) {
+        let block = match self.block.take() {
+            Some(b) => b,
+            None => return,
+        };
+
+        let kind = match block.kind {
+            BlockKind::Stage => {
+                let mut r = StageReport {
+                    complete,
+                    ..Default::default()
+                };
+                for (key, value) in &block.fields {
+                    match key.as_str() {
+                        "stage id" => r.stage_id = find_int_after(value, ""),
+                        "status" => r.status = Some(value.clone()),
+                        "total arcs" | "total pulses" => r.arcs = find_int_after(value, ""),
+                        "duration" => {
+                            if value.ends_with("ms") {
+                                r.duration_ms = find_double_after(value, "");
+                            } else if value.contains("Single") {
+                                r.single_pulse = true;
+                            }
+                        }
+                        "min interval" => r.min_interval_ms = find_double_after(value, ""),
+                        "max interval" => r.max_interval_ms = find_double_after(value, ""),
+                        "interval" if value.starts_with("N/A") => r.single_pulse = true,
+                        _ => {}
+                    }
+                }
+                CoreEventKind::StageReport(r)
+            }
+            BlockKind::Summary => {
+                let mut s = TotalSummary {
+                    complete,
+                    ..Default::default()
+                };
+                for (key, value) in &block.fields {
+                    match key.as_str() {
+                        "status" => s.status = Some(value.clone()),
+                        "active time" => s.active_time_s = find_double_after(value, ""),
+                        "total stages" => s.total_stages = find_int_after(value, ""),
+                        "grand total" => s.grand_total = find_int_after(value, ""),
+                        "avg frequency" => s.avg_frequency_hz = find_double_after(value, ""),
+                        _ => {}
+                    }
+                }
+                s.summed_duration_s = self.tally.duration_ms.map(|ms| ms / 1000.0);
+                CoreEventKind::TotalSummary(s)
+            }
+        };
+
+        let warnings = self.check_consistency(&kind);
+        self.events.push(CoreEvent {
+            kind,
+            raw: block.raw,
+        });
+        for w in warnings {
+            self.events.push(CoreEvent {
+                kind: CoreEventKind::ConsistencyWarning(w),
+                raw: Vec::new(),
+            });
+        }
+    }
+
+    // 阶段报告：和该阶段最后一条 Live 比；汇总：和本 session 的阶段报告累计比
+    fn check_consistency(&mut self, kind: &CoreEventKind) -> Vec<ConsistencyWarning> {
+        let mut warnings = Vec::new();
+        let mut check = |check, stage_id, expected: i64, actual: i64| {
+            if expected != actual {
+                warnings.push(ConsistencyWarning {
+                    check,
+                    stage_id,
+                    expected,
+                    actual,
+                });
+            }
+        };
+
+        let tally = &mut self.tally;
+        match kind {
+            CoreEventKind::StageReport(r) => {
+                tally.stages += 1;
+                // 单脉冲阶段没有 Duration（汇总里的 Active Time 也只是 ">60s"），不做对照
+                tally.duration_ms = match (tally.duration_ms, r.duration_ms) {
+                    (Some(sum), Some(d)) => Some(sum + d),
+                    _ => None,
+                };
+                let arcs = match r.arcs {
+                    Some(a) => a as i64,
+                    None => {
+                        tally.incomplete = true;
+                        tally.last_live = None;
+                        return warnings;
+                    }
+                };
+                tally.arcs += arcs;
+
+                // 只认同一阶段的 Live（没有 Live 行的日志不核对）
+                let live = tally
+                    .last_live
+                    .take()
+                    .filter(|l| l.stage.is_none() || r.stage_id.is_none() || l.stage == r.stage_id);
+                if let Some(live) = live {
+                    if let Some(count) = live.count {
+                        check(ConsistencyCheck::StageCount, r.stage_id, count as i64, arcs);
+                    }
+                    if let (true, false, Some(total)) =
+                        (tally.from_start, tally.incomplete, live.total)
+                    {
+                        check(
+                            ConsistencyCheck::LiveTotal,
+                            r.stage_id,
+                            tally.arcs,
+                            total as i64,
+                        );
+                    }
+                }
+            }
+            CoreEventKind::TotalSummary(s) => {
+                if tally.from_start && !tally.incomplete {
+                    if let Some(total) = s.grand_total {
+                        check(ConsistencyCheck::GrandTotal, None, tally.arcs, total as i64);
+                    }
+                    if let Some(stages) = s.total_stages {
+                        check(
+                            ConsistencyCheck::StageTally,
+                            None,
+                            tally.stages,
+                            stages as i64,
+                        );
+                    }
+                }
+                // 汇总之后 MCU 从 0 重新计数
+                *tally = SessionTally::fresh();
+            }
+            _ => {}
+        }
+        warnings
+    }
+}
+
+/// 去掉控制字符、日志回放时的时间戳前缀（"[HH:MM:SS]" 等）和乱码空格（Â + NBSP），连续空白合并
+///
+/// 按字符处理（process_line 里的 clean 是按字节拼的，中文和 NBSP 会被拆坏）
+pub fn normalize_line(raw: &str) -> String {
+    let clean: String = raw
+        .chars()
+        .map(|c| {
+            if c == '\u{c2}' || c == '\u{a0}' {
+                ' '
+            } else {
+                c
+            }
+        })
+        .filter(|c| *c == '\t' || !c.is_control())
+        .collect();
+
+    let s = strip_log_timestamp(clean.trim());
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 整行都是 '-' 或 '='（报告块结束）
+pub fn is_separator(text: &str) -> bool {
+    text.len() >= 8 && (text.chars().all(|c| c == '-') || text.chars().all(|c| c == '='))
+}
+
+/// "Stage ID : 1" -> ("stage id", "1")
+pub fn split_field(text: &str) -> Option<(String, String)> {
+    let (key, value) = text.split_once(':')?;
+    let key = key.trim();
+    if key.is_empty() || key.starts_with('[') {
+        return None;
+    }
+    Some((key.to_lowercase(), value.trim().to_string()))
+}
+
 // ----------------- 工具函数 -----------------
 
 fn find_int_after(src: &str, key: &str) -> Option<i32> {
@@ -190,3 +650,157 @@ fn find_double_after(src: &str, key: &str) -> Option<f64> {
         s.parse().ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events_of(lines: &[&str]) -> Vec<CoreEvent> {
+        let mut core = CoreState::new();
+        for line in lines {
+            core.process_line(line);
+        }
+        core.flush();
+        core.take_events()
+    }
+
+    #[test]
+    fn error_message_drops_log_prefix() {
+        let raw = "[10:58:16] [ERROR] 连接 TCP 127.0.0.1:5000 失败:\x1b  Os { code: 10061 }";
+        let events = events_of(&[raw]);
+        assert_eq!(events.len(), 1);
+        match &events[0].kind {
+            CoreEventKind::Error(msg) => {
+                assert_eq!(msg, "[ERROR] 连接 TCP 127.0.0.1:5000 失败: Os { code: 10061 }")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(events[0].raw, [raw]);
+    }
+
+    fn warnings_of(lines: &[String]) -> Vec<(ConsistencyCheck, i64, i64)> {
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        events_of(&lines)
+            .into_iter()
+            .filter_map(|e| match e.kind {
+                CoreEventKind::ConsistencyWarning(w) => Some((w.check, w.expected, w.actual)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn live(stage: i32, count: i32, total: i32) -> String {
+        format!("[Live] Stage:{} | Count:{} | Total:{} | Wait:0 ms", stage, count, total)
+    }
+
+    fn stage_report(stage: i32, arcs: i32) -> Vec<String> {
+        vec![
+            "---------- [STAGE REPORT] ----------".to_string(),
+            format!(" Stage ID      : {}", stage),
+            format!(" Total Arcs    : {}", arcs),
+            " Duration      : 1000 ms".to_string(),
+            "------------------------------------".to_string(),
+        ]
+    }
+
+    fn summary(stages: i32, grand_total: i32) -> Vec<String> {
+        vec![
+            "========== [TOTAL SUMMARY] ==========".to_string(),
+            " Status        : Session Closed (Timeout)".to_string(),
+            " Active Time   : 2.000 s".to_string(),
+            format!(" Total Stages  : {}", stages),
+            format!(" Grand Total   : {} pulses", grand_total),
+            "=====================================".to_string(),
+        ]
+    }
+
+    // 启动横幅 + 两个阶段（3 个、2 个脉冲），阶段报告前都有 Live
+    fn session() -> Vec<String> {
+        let mut lines = vec!["SYSTEM IS RUNNING".to_string()];
+        lines.extend((1..=3).map(|c| live(1, c, c)));
+        lines.extend(stage_report(1, 3));
+        lines.extend((1..=2).map(|c| live(2, c, 3 + c)));
+        lines.extend(stage_report(2, 2));
+        lines.extend(summary(2, 5));
+        lines
+    }
+
+    #[test]
+    fn consistent_session_has_no_warnings() {
+        assert!(warnings_of(&session()).is_empty());
+    }
+
+    #[test]
+    fn fixture_logs_have_no_warnings() {
+        for name in ["2025-12-11.txt", "2025-12-12.txt"] {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("logs")
+                .join(name);
+            let text = String::from_utf8_lossy(&std::fs::read(path).unwrap()).into_owned();
+            let lines: Vec<String> = text.lines().map(str::to_string).collect();
+            assert!(warnings_of(&lines).is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn mismatched_grand_total_warns_once() {
+        let mut lines = session();
+        let n = lines.len();
+        lines[n - 2] = " Grand Total   : 6 pulses".to_string();
+        assert_eq!(warnings_of(&lines), [(ConsistencyCheck::GrandTotal, 5, 6)]);
+    }
+
+    #[test]
+    fn dropped_stage_report_is_caught_by_the_tally() {
+        // 第 2 阶段的报告整块丢失
+        let mut lines = vec!["SYSTEM IS RUNNING".to_string()];
+        lines.extend((1..=3).map(|c| live(1, c, c)));
+        lines.extend(stage_report(1, 3));
+        lines.extend((1..=2).map(|c| live(2, c, 3 + c)));
+        lines.extend(summary(2, 5));
+        assert_eq!(
+            warnings_of(&lines),
+            [
+                (ConsistencyCheck::GrandTotal, 3, 5),
+                (ConsistencyCheck::StageTally, 1, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn stage_arcs_checked_against_last_live() {
+        // 阶段 1 报告少了一个脉冲：Count 和累计的 Total 都对不上
+        let mut lines = vec!["SYSTEM IS RUNNING".to_string()];
+        lines.extend((1..=3).map(|c| live(1, c, c)));
+        lines.extend(stage_report(1, 2));
+        assert_eq!(
+            warnings_of(&lines),
+            [
+                (ConsistencyCheck::StageCount, 3, 2),
+                (ConsistencyCheck::LiveTotal, 2, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn joined_mid_session_skips_totals() {
+        // 没看到横幅（中途连上）：只核对阶段自己的 Count，不核对累计
+        let lines: Vec<String> = session().into_iter().skip(1).collect();
+        assert!(warnings_of(&lines).is_empty());
+
+        let mut lines: Vec<String> = session().into_iter().skip(1).collect();
+        let n = lines.len();
+        lines[n - 2] = " Grand Total   : 99 pulses".to_string();
+        assert!(warnings_of(&lines).is_empty());
+    }
+
+    #[test]
+    fn report_without_arcs_suppresses_totals() {
+        // 阶段 1 报告缺了 Total Arcs：累计不可靠，汇总不再核对
+        let mut lines = session();
+        lines.retain(|l| l != " Total Arcs    : 3");
+        let n = lines.len();
+        lines[n - 2] = " Grand Total   : 99 pulses".to_string();
+        assert!(warnings_of(&lines).is_empty());
+    }
+}
@@ -0,0 +1,409 @@
+// src/dhjc_export.rs
+//
+// CSV 导出：
+// - 运行中维护 logs/stages.csv 与 logs/sessions.csv，
+//   每个 [STAGE REPORT] / [TOTAL SUMMARY] 追加一行
+// - 从已有的文本日志（YYYY-MM-DD[.N].txt[.gz]）按日期范围重新解析导出
+
+use crate::dhjc_clock::{minus_millis, parse_log_timestamp};
+use crate::dhjc_core::{CoreEvent, CoreEventKind, CoreState};
+use crate::dhjc_intervals::IntervalStats;
+use crate::dhjc_log::{list_log_files, open_log_reader};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub const STAGES_CSV: &str = "stages.csv";
+pub const SESSIONS_CSV: &str = "sessions.csv";
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const STAGES_HEADER: &[&str] = &[
+    "stage_start",
+    "report_time",
+    "device",
+    "session_start",
+    "stage_id",
+    "status",
+    "arcs",
+    "duration_ms",
+    "min_interval_ms",
+    "max_interval_ms",
+    "single_pulse",
+    "complete",
+    "host_intervals",
+    "host_mean_ms",
+    "host_std_ms",
+    "host_median_ms",
+    "host_p95_ms",
+    "host_p99_ms",
+    "host_min_ms",
+    "host_max_ms",
+    "host_histogram",
+];
+
+const SESSIONS_HEADER: &[&str] = &[
+    "session_start",
+    "summary_time",
+    "device",
+    "status",
+    "active_time_s",
+    "total_stages",
+    "grand_total",
+    "avg_frequency_hz",
+    "complete",
+    "summed_duration_s",
+    "active_time_drift_s",
+];
+
+/// 把事件流写成 stages / sessions 两个 CSV，并记住当前 session 的开始时间
+pub struct CsvRecorder {
+    stages: Option<File>,
+    sessions: Option<File>,
+    device_id: String,
+    session_start: Option<NaiveDateTime>,
+
+    pub stage_rows: usize,
+    pub session_rows: usize,
+}
+
+impl CsvRecorder {
+    /// 运行中使用：追加到 folder 下的 stages.csv / sessions.csv
+    pub fn append(folder: &str, device_id: &str) -> Self {
+        let dir = Path::new(folder);
+        Self::open(
+            &dir.join(STAGES_CSV),
+            &dir.join(SESSIONS_CSV),
+            device_id,
+            false,
+        )
+    }
+
+    fn open(stages: &Path, sessions: &Path, device_id: &str, truncate: bool) -> Self {
+        Self {
+            stages: open_csv(stages, STAGES_HEADER, truncate),
+            sessions: open_csv(sessions, SESSIONS_HEADER, truncate),
+            device_id: device_id.to_string(),
+            session_start: None,
+            stage_rows: 0,
+            session_rows: 0,
+        }
+    }
+
+    /// at 为事件发生的主机时间
+    pub fn record(&mut self, at: NaiveDateTime, event: &CoreEvent) {
+        self.record_at(Some(at), event);
+    }
+
+    // 旧日志没有时间前缀时 at 为 None，对应列留空
+    fn record_at(&mut self, at: Option<NaiveDateTime>, event: &CoreEvent) {
+        match &event.kind {
+            CoreEventKind::SystemReset => self.session_start = at,
+            CoreEventKind::Banner(_) => {
+                if self.session_start.is_none() {
+                    self.session_start = at;
+                }
+            }
+            CoreEventKind::StageReport(r) => {
+                // 报告在阶段结束时输出，开始时间按 Duration 倒推
+                let stage_start = at
+                    .zip(r.duration_ms)
+                    .and_then(|(at, ms)| minus_millis(at, ms));
+                let host = r.host_intervals.as_ref();
+                let ms = |f: fn(&IntervalStats) -> f64| {
+                    host.map(|h| format!("{:.1}", f(h))).unwrap_or_default()
+                };
+                let row = [
+                    fmt_time(stage_start),
+                    fmt_time(at),
+                    self.device_id.clone(),
+                    fmt_time(self.session_start),
+                    fmt_opt(r.stage_id),
+                    r.status.clone().unwrap_or_default(),
+                    fmt_opt(r.arcs),
+                    fmt_opt(r.duration_ms),
+                    fmt_opt(r.min_interval_ms),
+                    fmt_opt(r.max_interval_ms),
+                    r.single_pulse.to_string(),
+                    r.complete.to_string(),
+                    fmt_opt(host.map(|h| h.count)),
+                    ms(|h| h.mean_ms),
+                    ms(|h| h.std_ms),
+                    ms(|h| h.median_ms),
+                    ms(|h| h.p95_ms),
+                    ms(|h| h.p99_ms),
+                    ms(|h| h.min_ms),
+                    ms(|h| h.max_ms),
+                    host.map(|h| h.histogram_text()).unwrap_or_default(),
+                ];
+                if write_row(self.stages.as_mut(), &row) {
+                    self.stage_rows += 1;
+                }
+            }
+            CoreEventKind::TotalSummary(s) => {
+                let row = [
+                    fmt_time(self.session_start),
+                    fmt_time(at),
+                    self.device_id.clone(),
+                    s.status.clone().unwrap_or_default(),
+                    fmt_opt(s.active_time_s),
+                    fmt_opt(s.total_stages),
+                    fmt_opt(s.grand_total),
+                    fmt_opt(s.avg_frequency_hz),
+                    s.complete.to_string(),
+                    fmt_opt(s.summed_duration_s.map(|v| format!("{:.3}", v))),
+                    fmt_opt(s.active_time_drift_s().map(|v| format!("{:.3}", v))),
+                ];
+                if write_row(self.sessions.as_mut(), &row) {
+                    self.session_rows += 1;
+                }
+                self.session_start = None;
+            }
+            CoreEventKind::Live(_)
+            | CoreEventKind::Error(_)
+            | CoreEventKind::ConsistencyWarning(_) => {}
+        }
+    }
+}
+
+// ----------------- 从历史日志导出 -----------------
+
+pub struct ExportResult {
+    pub stages_path: PathBuf,
+    pub sessions_path: PathBuf,
+    pub files: usize,
+    pub stage_rows: usize,
+    pub session_rows: usize,
+}
+
+/// 日志目录中有文本日志的日期（升序）
+pub fn list_log_dates(folder: &str) -> Vec<NaiveDate> {
+    let mut dates: Vec<NaiveDate> = list_log_files(folder)
+        .into_iter()
+        .filter(|(name, _)| name.ext == "txt")
+        .map(|(name, _)| name.date)
+        .collect();
+    dates.dedup();
+    dates
+}
+
+/// 把 [from, to] 范围内的日志重新解析，写到 out_dir 下的
+/// stages_<from>_<to>.csv 与 sessions_<from>_<to>.csv（覆盖已有文件）
+pub fn export_logs(
+    log_folder: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    out_dir: &Path,
+    device_id: &str,
+) -> Result<ExportResult, String> {
+    if from > to {
+        return Err(format!("起始日期 {} 晚于结束日期 {}", from, to));
+    }
+    create_dir_all(out_dir)
+        .map_err(|e| format!("创建导出目录 {} 失败: {}", out_dir.to_string_lossy(), e))?;
+
+    let suffix = format!("{}_{}", from.format("%Y%m%d"), to.format("%Y%m%d"));
+    let stages_path = out_dir.join(format!("stages_{}.csv", suffix));
+    let sessions_path = out_dir.join(format!("sessions_{}.csv", suffix));
+    let mut recorder = CsvRecorder::open(&stages_path, &sessions_path, device_id, true);
+    if recorder.stages.is_none() || recorder.sessions.is_none() {
+        return Err(format!("无法写入 {}", out_dir.to_string_lossy()));
+    }
+
+    let files: Vec<(NaiveDate, PathBuf)> = list_log_files(log_folder)
+        .into_iter()
+        .filter(|(name, _)| name.ext == "txt" && (from..=to).contains(&name.date))
+        .map(|(name, path)| (name.date, path))
+        .collect();
+
+    // 同一天的分段接着解析；没有时间前缀的行沿用上一行的时间
+    let mut core = CoreState::new();
+    let mut current_date = None;
+    let mut now = None;
+    for (date, path) in &files {
+        if current_date != Some(*date) {
+            // 前一天的文件在报告块中间结束：作为不完整的块导出
+            core.flush();
+            for event in core.take_events() {
+                recorder.record_at(now, &event);
+            }
+            core = CoreState::new();
+            current_date = Some(*date);
+            now = None;
+        }
+        let reader = open_log_reader(path)
+            .map_err(|e| format!("打开 {} 失败: {}", path.to_string_lossy(), e))?;
+
+        for raw in reader.split(b'\n') {
+            let raw = raw.map_err(|e| format!("读取 {} 失败: {}", path.to_string_lossy(), e))?;
+            let line = String::from_utf8_lossy(&raw).trim_end().to_string();
+            if let Some((t, _)) = parse_log_timestamp(&line, *date) {
+                now = Some(t);
+            }
+            core.process_line(&line);
+            for event in core.take_events() {
+                recorder.record_at(now, &event);
+            }
+        }
+    }
+    core.flush();
+    for event in core.take_events() {
+        recorder.record_at(now, &event);
+    }
+
+    Ok(ExportResult {
+        stages_path,
+        sessions_path,
+        files: files.len(),
+        stage_rows: recorder.stage_rows,
+        session_rows: recorder.session_rows,
+    })
+}
+
+// ----------------- CSV 工具 -----------------
+
+fn open_csv(path: &Path, header: &[&str], truncate: bool) -> Option<File> {
+    if let Some(dir) = path.parent() {
+        if let Err(e) = create_dir_all(dir) {
+            eprintln!("[CSV] 创建目录失败: {:?}", e);
+            return None;
+        }
+    }
+
+    if !truncate {
+        keep_old_header(path, header);
+    }
+
+    let mut opts = OpenOptions::new();
+    if truncate {
+        opts.write(true).create(true).truncate(true);
+    } else {
+        opts.create(true).append(true);
+    }
+
+    let mut file = match opts.open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("[CSV] 打开 {} 失败: {:?}", path.to_string_lossy(), e);
+            return None;
+        }
+    };
+
+    // 新文件先写表头
+    let empty = file.metadata().map(|m| m.len() == 0).unwrap_or(false);
+    if empty {
+        let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+        write_row(Some(&mut file), &header);
+    }
+    Some(file)
+}
+
+// 追加模式下已有文件的表头和当前列不同（旧版本写的），改名保留，另起新文件
+fn keep_old_header(path: &Path, header: &[&str]) {
+    let first = match File::open(path) {
+        Ok(f) => {
+            let mut line = String::new();
+            let _ = BufReader::new(f).read_line(&mut line);
+            line
+        }
+        Err(_) => return,
+    };
+    if first.is_empty() || first.trim_end() == header.join(",") {
+        return;
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut n = 1;
+    let old = loop {
+        let p = path.with_file_name(format!("{}.old{}.csv", stem, n));
+        if !p.exists() {
+            break p;
+        }
+        n += 1;
+    };
+    match fs::rename(path, &old) {
+        Ok(()) => eprintln!(
+            "[CSV] {} 的列已变化，旧文件改名为 {}",
+            path.to_string_lossy(),
+            old.to_string_lossy()
+        ),
+        Err(e) => eprintln!("[CSV] 改名 {} 失败: {:?}", path.to_string_lossy(), e),
+    }
+}
+
+fn write_row(file: Option<&mut File>, fields: &[String]) -> bool {
+    let file = match file {
+        Some(f) => f,
+        None => return false,
+    };
+
+    match file.write_all(csv_line(fields).as_bytes()) {
+        Ok(()) => {
+            let _ = file.flush();
+            true
+        }
+        Err(e) => {
+            eprintln!("[CSV] 写入失败: {:?}", e);
+            false
+        }
+    }
+}
+
+/// 一行 CSV（含结尾的 \r\n）
+pub fn csv_line(fields: &[String]) -> String {
+    let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    format!("{}\r\n", line.join(","))
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn fmt_opt<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn fmt_time(t: Option<NaiveDateTime>) -> String {
+    t.map(|t| t.format(TIME_FORMAT).to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_report_at_end_of_day_is_exported() {
+        let dir = std::env::temp_dir().join(format!("dhjc_export_{}", std::process::id()));
+        let logs = dir.join("logs");
+        create_dir_all(&logs).unwrap();
+        // 两天的文件都停在阶段报告中间（没有结束分隔线）
+        let day = "SYSTEM IS RUNNING\n\
+                   ---------- [STAGE REPORT] ----------\n\
+                   [13:33:21]  Stage ID      : 1\n\
+                   [13:33:21]  Total Arcs    : 507\n\
+                   [13:33:21]  Duration      : 18740 ms\n";
+        fs::write(logs.join("2025-12-11.txt"), day).unwrap();
+        fs::write(logs.join("2025-12-12.txt"), day).unwrap();
+
+        let d = |day| NaiveDate::from_ymd_opt(2025, 12, day).unwrap();
+        let out = dir.join("out");
+        let r = export_logs(&logs.to_string_lossy(), d(11), d(12), &out, "test").unwrap();
+        assert_eq!(r.files, 2);
+        assert_eq!(r.stage_rows, 2);
+
+        let csv = fs::read_to_string(&r.stages_path).unwrap();
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("2025-12-11 13:33:02,2025-12-11 13:33:21,"));
+        assert!(rows[1].starts_with("2025-12-12 13:33:02,2025-12-12 13:33:21,"));
+        assert!(rows.iter().all(|r| r.contains(",false,false,")));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,263 @@
+// src/dhjc_history.rs
+//
+// 历史日志浏览：
+// - 列出 log_folder 中的日期（含分段、.gz）和 session 文件
+// - 用独立的 CoreState 在后台线程重新解析，得到阶段报告、汇总和总数曲线
+// - 用户注释（"[NOTE] ..." 行）按同一横轴取出，画成竖线
+// 只读，不影响实时连接
+
+use crate::dhjc_clock::{minus_millis, parse_log_timestamp};
+use crate::dhjc_core::{CoreEventKind, CoreState, StageReport, TotalSummary};
+use crate::dhjc_log::{list_log_files, open_log_reader};
+use chrono::{NaiveDate, NaiveDateTime};
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// 列表中的一项：某天的日志（所有分段）或一个 session 文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryItem {
+    pub date: NaiveDate,
+    pub label: String,
+    pub session: bool,
+    pub files: Vec<PathBuf>,
+}
+
+/// 带时间的解析结果；旧日志没有时间前缀时 at 为 None
+pub struct Timed<T> {
+    pub at: Option<NaiveDateTime>,
+    pub value: T,
+}
+
+pub struct HistoryData {
+    pub stages: Vec<Timed<StageReport>>,
+    pub summaries: Vec<Timed<TotalSummary>>,
+    /// [x, total]；有时间戳时 x 为距第一行的秒数，否则为行号；
+    /// session 之间以 y = NaN 断开
+    pub curve: Vec<[f64; 2]>,
+    pub curve_in_seconds: bool,
+    /// 第一行的时间（curve_in_seconds 时即 x = 0）
+    pub start: Option<NaiveDateTime>,
+    /// [x, 注释]，x 与 curve 相同
+    pub notes: Vec<(f64, String)>,
+    pub lines: usize,
+}
+
+/// 日期倒序；同一天先列日志本身，再列 session
+pub fn list_history(folder: &str) -> Vec<HistoryItem> {
+    let mut items: Vec<HistoryItem> = Vec::new();
+
+    for (name, path) in list_log_files(folder) {
+        if name.ext != "txt" {
+            continue;
+        }
+        if name.session {
+            let stem = path.file_name().unwrap_or_default().to_string_lossy();
+            // 2025-12-12_133155_session.txt -> 13:31:55
+            let time = stem.get(11..17).unwrap_or("");
+            let label = format!(
+                "{}:{}:{} session",
+                time.get(0..2).unwrap_or(""),
+                time.get(2..4).unwrap_or(""),
+                time.get(4..6).unwrap_or("")
+            );
+            items.push(HistoryItem {
+                date: name.date,
+                label,
+                session: true,
+                files: vec![path],
+            });
+            continue;
+        }
+
+        match items.iter_mut().find(|i| !i.session && i.date == name.date) {
+            Some(day) => day.files.push(path),
+            None => items.push(HistoryItem {
+                date: name.date,
+                label: name.date.format("%Y-%m-%d").to_string(),
+                session: false,
+                files: vec![path],
+            }),
+        }
+    }
+
+    // 同一天内：日志本身在前，session 按时间
+    items.sort_by(|a, b| {
+        b.date
+            .cmp(&a.date)
+            .then(a.session.cmp(&b.session))
+            .then(a.label.cmp(&b.label))
+    });
+    items
+}
+
+/// 后台解析，结果从返回的通道取
+pub fn spawn_load(item: &HistoryItem) -> Receiver<Result<HistoryData, String>> {
+    let (tx, rx) = mpsc::channel();
+    let item = item.clone();
+    thread::spawn(move || {
+        let _ = tx.send(load_history(&item));
+    });
+    rx
+}
+
+/// (行号, 时间, Total)
+type CurvePoint = (usize, Option<NaiveDateTime>, i32);
+
+// 一个 session 内的曲线点：有 Live 用 Live 的 Total，否则用阶段报告累加
+#[derive(Default)]
+struct SessionCurve {
+    live: Vec<CurvePoint>,
+    stages: Vec<CurvePoint>,
+    stage_total: i32,
+}
+
+impl SessionCurve {
+    // session 结束：选出曲线点追加到 points（前面有点时先断线），然后清空
+    fn finish(&mut self, points: &mut Vec<Option<CurvePoint>>) {
+        let session = std::mem::take(self);
+        let chosen = if session.live.is_empty() {
+            session.stages
+        } else {
+            session.live
+        };
+        if chosen.is_empty() {
+            return;
+        }
+        if !points.is_empty() {
+            points.push(None);
+        }
+        points.extend(chosen.into_iter().map(Some));
+    }
+}
+
+pub fn load_history(item: &HistoryItem) -> Result<HistoryData, String> {
+    let mut data = HistoryData {
+        stages: Vec::new(),
+        summaries: Vec::new(),
+        curve: Vec::new(),
+        curve_in_seconds: false,
+        start: None,
+        notes: Vec::new(),
+        lines: 0,
+    };
+
+    let mut core = CoreState::new();
+    let mut now: Option<NaiveDateTime> = None;
+    let mut first: Option<NaiveDateTime> = None;
+    // 先按行号记录，最后有时间戳的话换成秒；None 为 session 之间的断线
+    let mut points: Vec<Option<CurvePoint>> = Vec::new();
+    // 当前 session 的 Live Total；新日志不记录 Live 行，
+    // 这种 session 改用阶段报告的脉冲数累加
+    let mut session = SessionCurve::default();
+    let mut notes: Vec<(usize, Option<NaiveDateTime>, String)> = Vec::new();
+
+    for path in &item.files {
+        let reader = open_log_reader(path)
+            .map_err(|e| format!("打开 {} 失败: {}", path.to_string_lossy(), e))?;
+
+        for raw in reader.split(b'\n') {
+            let raw = raw.map_err(|e| format!("读取 {} 失败: {}", path.to_string_lossy(), e))?;
+            let line = String::from_utf8_lossy(&raw).trim_end().to_string();
+            data.lines += 1;
+
+            if let Some((t, rest)) = parse_log_timestamp(&line, item.date) {
+                now = Some(t);
+                first.get_or_insert(t);
+                if let Some(note) = rest.trim_start().strip_prefix("[NOTE]") {
+                    notes.push((data.lines, now, note.trim().to_string()));
+                }
+            }
+
+            core.process_line(&line);
+
+            for event in core.take_events() {
+                match event.kind {
+                    CoreEventKind::Live(l) => {
+                        if let Some(total) = l.total {
+                            session.live.push((data.lines, now, total));
+                        }
+                    }
+                    CoreEventKind::StageReport(r) => {
+                        // 阶段开始（报告时间减 Duration）处补一个点，曲线在阶段持续时间内上升
+                        let began = match (now, r.duration_ms) {
+                            (Some(t), Some(ms)) => minus_millis(t, ms),
+                            _ => None,
+                        };
+                        if began.is_some() {
+                            session
+                                .stages
+                                .push((data.lines, began, session.stage_total));
+                        }
+                        session.stage_total += r.arcs.unwrap_or(0);
+                        session.stages.push((data.lines, now, session.stage_total));
+                        data.stages.push(Timed { at: now, value: r });
+                    }
+                    CoreEventKind::TotalSummary(s) => {
+                        session.finish(&mut points);
+                        data.summaries.push(Timed { at: now, value: s });
+                    }
+                    CoreEventKind::SystemReset | CoreEventKind::Banner(_) => {
+                        session.finish(&mut points);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    session.finish(&mut points);
+
+    data.curve_in_seconds = first.is_some() && points.iter().flatten().all(|(_, t, _)| t.is_some());
+    data.start = first;
+    let x_of = |line: usize, t: Option<NaiveDateTime>| match (data.curve_in_seconds, t, first) {
+        (true, Some(t), Some(f)) => (t - f).num_milliseconds() as f64 / 1000.0,
+        _ => line as f64,
+    };
+    let mut last_x = 0.0;
+    data.curve = points
+        .iter()
+        .map(|p| match p {
+            Some((line, t, total)) => {
+                last_x = x_of(*line, *t);
+                [last_x, *total as f64]
+            }
+            None => [last_x, f64::NAN],
+        })
+        .collect();
+    data.notes = notes
+        .into_iter()
+        .map(|(line, t, text)| (x_of(line, t), text))
+        .collect();
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn curve_rises_within_each_session() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("logs/2025-12-12.txt");
+        let item = HistoryItem {
+            date: NaiveDate::from_ymd_opt(2025, 12, 12).unwrap(),
+            label: "2025-12-12".to_string(),
+            session: false,
+            files: vec![path],
+        };
+        let data = load_history(&item).unwrap();
+        assert!(data.curve_in_seconds);
+        assert!(data.curve.iter().any(|p| p[1].is_finite()));
+
+        // 没有 Live 行：每个 session 由阶段报告累加，段内只增不减，横轴也不回退
+        for run in data.curve.split(|p| p[1].is_nan()) {
+            assert!(!run.is_empty());
+            for w in run.windows(2) {
+                assert!(w[1][1] >= w[0][1], "total fell: {:?} -> {:?}", w[0], w[1]);
+                assert!(w[1][0] >= w[0][0], "x went back: {:?} -> {:?}", w[0], w[1]);
+            }
+        }
+    }
+}
@@ -0,0 +1,476 @@
+// src/dhjc_import.rs
+//
+// 把已有的文本日志（LogWriter 写的 YYYY-MM-DD[.N].txt[.gz]）解析回结构化事件：
+// - 日期取自文件名，行首只有时分秒；跨过午夜时自动进到下一天
+// - 没有时间前缀的行（横幅、分隔线、旧日志）沿用上一行的时间
+// - 清掉终端转义残留（ESC[2J ESC[H，或 ESC 已丢失的 "[2J[H"）和 Â + NBSP 乱码
+// - 认不出的行、报告块外的字段行、不完整的报告块都带行号报告出来
+// 命令行：dhjc_rust_gui import [--date YYYY-MM-DD] <file>...，事件以 JSON Lines 输出到 stdout
+
+use crate::dhjc_clock::parse_log_timestamp;
+use crate::dhjc_core::{
+    is_separator, normalize_line, split_field, CoreEvent, CoreEventKind, CoreState,
+};
+use crate::dhjc_log::{event_fields, open_log_reader, LogFileName};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde_json::json;
+use std::io::{BufRead, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// MCU 输出中不构成事件、但属于正常内容的行
+const KNOWN_MESSAGES: &[&str] = &[
+    "LED Self-Test",
+    "Waiting for signal pulses",
+    // 主机侧在复位时写的标记 "===== SYSTEM RESET ====="
+    "SYSTEM RESET",
+];
+
+/// 时间比上一行早这么多，认为跨过了午夜
+const MIDNIGHT_BACKSTEP_HOURS: i64 = 12;
+
+/// 一个解析出的事件；line 为事件第一行的行号（从 1 开始）
+pub struct ImportedEvent {
+    pub line: usize,
+    /// 事件最后一行的时间；文件开头还没出现时间前缀时为 None
+    pub at: Option<NaiveDateTime>,
+    pub event: CoreEvent,
+}
+
+/// 无法解析的行或有问题的报告块
+#[derive(Debug, Clone)]
+pub struct ImportIssue {
+    pub line: usize,
+    pub message: String,
+    pub text: String,
+}
+
+pub struct ImportedLog {
+    pub path: PathBuf,
+    pub date: NaiveDate,
+    pub lines: usize,
+    pub events: Vec<ImportedEvent>,
+    pub issues: Vec<ImportIssue>,
+}
+
+/// 解析一个日志文件；date 为 None 时从文件名取日期
+pub fn import_file(path: &Path, date: Option<NaiveDate>) -> Result<ImportedLog, String> {
+    let date = match date {
+        Some(d) => d,
+        None => path
+            .file_name()
+            .and_then(|n| LogFileName::parse(&n.to_string_lossy()))
+            .map(|n| n.date)
+            .ok_or_else(|| {
+                format!(
+                    "无法从文件名 {} 得到日期，请用 --date 指定",
+                    path.to_string_lossy()
+                )
+            })?,
+    };
+
+    let reader = open_log_reader(path)
+        .map_err(|e| format!("打开 {} 失败: {}", path.to_string_lossy(), e))?;
+    let mut log = import_reader(reader, date)
+        .map_err(|e| format!("读取 {} 失败: {}", path.to_string_lossy(), e))?;
+    log.path = path.to_path_buf();
+    Ok(log)
+}
+
+/// 从任意输入解析（path 留空）
+pub fn import_reader<R: BufRead>(reader: R, date: NaiveDate) -> std::io::Result<ImportedLog> {
+    let mut importer = Importer::new(date);
+    for raw in reader.split(b'\n') {
+        importer.push(&raw?);
+    }
+    Ok(importer.finish())
+}
+
+struct Importer {
+    date: NaiveDate,
+    core: CoreState,
+    // 跨过午夜的天数
+    day_offset: i64,
+    now: Option<NaiveDateTime>,
+    line_no: usize,
+    // 当前报告块的起始行和最后一行的时间
+    block_line: usize,
+    block_at: Option<NaiveDateTime>,
+    log: ImportedLog,
+}
+
+impl Importer {
+    fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            core: CoreState::new(),
+            day_offset: 0,
+            now: None,
+            line_no: 0,
+            block_line: 0,
+            block_at: None,
+            log: ImportedLog {
+                path: PathBuf::new(),
+                date,
+                lines: 0,
+                events: Vec::new(),
+                issues: Vec::new(),
+            },
+        }
+    }
+
+    fn push(&mut self, raw: &[u8]) {
+        self.line_no += 1;
+        let decoded = String::from_utf8_lossy(raw);
+        if decoded.contains('\u{fffd}') {
+            self.issue("含有非 UTF-8 字节", &decoded);
+        }
+        let line = strip_terminal_escapes(decoded.trim_end());
+
+        if let Some(t) = self.parse_time(&line) {
+            self.now = Some(t);
+        }
+
+        let was_in_block = self.core.in_block();
+        let text = normalize_line(&line);
+        self.core.process_line(&line);
+
+        let mut own_event = false;
+        for event in self.core.take_events() {
+            // 一致性警告紧跟在对应的报告块后面
+            let block = matches!(
+                event.kind,
+                CoreEventKind::StageReport(_)
+                    | CoreEventKind::TotalSummary(_)
+                    | CoreEventKind::ConsistencyWarning(_)
+            );
+            if block {
+                self.block_event(event);
+            } else {
+                own_event = true;
+                self.log.events.push(ImportedEvent {
+                    line: self.line_no,
+                    at: self.now,
+                    event,
+                });
+            }
+        }
+
+        if self.core.in_block() {
+            if !was_in_block || text.contains("[STAGE REPORT]") || text.contains("[TOTAL SUMMARY]")
+            {
+                self.block_line = self.line_no;
+            }
+            self.block_at = self.now;
+            return;
+        }
+
+        let consumed = own_event
+            || text.is_empty()
+            || (was_in_block && (is_separator(&text) || split_field(&text).is_some()));
+        if !consumed {
+            self.classify(&text, &line);
+        }
+    }
+
+    fn finish(mut self) -> ImportedLog {
+        // 文件在报告块中间结束
+        self.core.flush();
+        for event in self.core.take_events() {
+            self.block_event(event);
+        }
+        self.log.lines = self.line_no;
+        self.log
+    }
+
+    // 只有时分秒的前缀按文件日期（+ 跨午夜天数）补全；ISO 前缀自带日期
+    fn parse_time(&mut self, line: &str) -> Option<NaiveDateTime> {
+        let date = self.date + Duration::days(self.day_offset);
+        let (mut t, _) = parse_log_timestamp(line, date)?;
+        if let Some(prev) = self.now {
+            if t < prev - Duration::hours(MIDNIGHT_BACKSTEP_HOURS) {
+                self.day_offset += 1;
+                t += Duration::days(1);
+            }
+        }
+        Some(t)
+    }
+
+    fn block_event(&mut self, event: CoreEvent) {
+        let complete = match &event.kind {
+            CoreEventKind::StageReport(r) => r.complete,
+            CoreEventKind::TotalSummary(s) => s.complete,
+            _ => true,
+        };
+        if !complete {
+            let title = event
+                .raw
+                .first()
+                .map(|s| normalize_line(s))
+                .unwrap_or_default();
+            self.log.issues.push(ImportIssue {
+                line: self.block_line,
+                message: "报告块没有结束分隔线（可能丢行）".to_string(),
+                text: title,
+            });
+        }
+        self.log.events.push(ImportedEvent {
+            line: self.block_line,
+            at: self.block_at.or(self.now),
+            event,
+        });
+    }
+
+    // 不产生事件的行：装饰、已知提示、主机侧标注，其余报告为无法解析
+    fn classify(&mut self, text: &str, line: &str) {
+        let decoration =
+            !text.is_empty() && (is_separator(text) || text.chars().all(|c| c == '*' || c == ' '));
+        if decoration || KNOWN_MESSAGES.iter().any(|m| text.contains(m)) || is_host_note(text) {
+            return;
+        }
+        if split_field(text).is_some() {
+            self.issue("字段行不在报告块内", line);
+        } else {
+            self.issue("无法识别的行", line);
+        }
+    }
+
+    fn issue(&mut self, message: &str, text: &str) {
+        self.log.issues.push(ImportIssue {
+            line: self.line_no,
+            message: message.to_string(),
+            text: text.to_string(),
+        });
+    }
+}
+
+/// 主机侧写入的 "[CFG] ..."、"[DB] ..." 等（标签全是大写字母）
+fn is_host_note(text: &str) -> bool {
+    let tag = match text.strip_prefix('[').and_then(|s| s.split_once(']')) {
+        Some((tag, _)) => tag,
+        None => return false,
+    };
+    !tag.is_empty() && tag.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// 去掉 ANSI 转义序列（ESC [ 参数 字母），以及 ESC 已被过滤后残留的 "[2J" / "[H"
+fn strip_terminal_escapes(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    loop {
+        let esc = rest.strip_prefix('\u{1b}');
+        let body = esc.unwrap_or(rest);
+        if let Some(len) = csi_len(body) {
+            // 没有 ESC 时只认行首的清屏 / 光标复位，避免误删 "[Live]" 之类
+            let code = &body[..len];
+            if esc.is_some() || code == "[2J" || code == "[H" {
+                rest = &body[len..];
+                continue;
+            }
+        }
+        break;
+    }
+    for c in rest.chars() {
+        if c != '\u{1b}' {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// "[2J..." -> Some(3)：'[' + 数字/分号 + 一个字母
+fn csi_len(s: &str) -> Option<usize> {
+    let body = s.strip_prefix('[')?;
+    let params = body
+        .bytes()
+        .take_while(|b| b.is_ascii_digit() || *b == b';')
+        .count();
+    let last = body.as_bytes().get(params)?;
+    if last.is_ascii_alphabetic() {
+        Some(params + 2)
+    } else {
+        None
+    }
+}
+
+// ----------------- 命令行 -----------------
+
+pub const IMPORT_USAGE: &str = "\
+用法: dhjc_rust_gui import [--date YYYY-MM-DD] <日志文件>...
+
+把文本日志解析为 JSON Lines 事件输出到 stdout，无法解析的行（带行号）输出到 stderr。
+日期默认取自文件名 YYYY-MM-DD[.N].txt[.gz]。";
+
+/// import 子命令；返回进程退出码
+pub fn run_cli(args: &[String]) -> i32 {
+    let mut date = None;
+    let mut files = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", IMPORT_USAGE);
+                return 0;
+            }
+            "--date" => {
+                let v = it.next().map(String::as_str).unwrap_or("");
+                match NaiveDate::parse_from_str(v, "%Y-%m-%d") {
+                    Ok(d) => date = Some(d),
+                    Err(_) => {
+                        eprintln!("[IMPORT] 无效的日期: {}\n\n{}", v, IMPORT_USAGE);
+                        return 2;
+                    }
+                }
+            }
+            other if other.starts_with("--") => {
+                eprintln!("[IMPORT] 未知参数: {}\n\n{}", other, IMPORT_USAGE);
+                return 2;
+            }
+            path => files.push(PathBuf::from(path)),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", IMPORT_USAGE);
+        return 2;
+    }
+
+    // 输出常接 head 等管道：对方先退出（BrokenPipe）时安静地结束
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let mut code = 0;
+    for path in &files {
+        let log = match import_file(path, date) {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("[IMPORT] {}", e);
+                code = 1;
+                continue;
+            }
+        };
+
+        let file = path.to_string_lossy();
+        for e in &log.events {
+            let record = json!({
+                "ts": e.at.map(|t| t.format("%Y-%m-%dT%H:%M:%S%.3f").to_string()),
+                "file": file,
+                "line": e.line,
+                "type": e.event.type_name(),
+                "fields": event_fields(&e.event),
+                "raw": e.event.raw,
+            });
+            match writeln!(out, "{}", record) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => return code,
+                Err(e) => {
+                    eprintln!("[IMPORT] 写 stdout 失败: {}", e);
+                    return 1;
+                }
+            }
+        }
+        for issue in &log.issues {
+            eprintln!("{}:{}: {}: {}", file, issue.line, issue.message, issue.text);
+        }
+        eprintln!(
+            "[IMPORT] {} ({}): {} 行，{} 个事件，{} 个问题",
+            file,
+            log.date,
+            log.lines,
+            log.events.len(),
+            log.issues.len()
+        );
+    }
+    match out.flush() {
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+            eprintln!("[IMPORT] 写 stdout 失败: {}", e);
+            1
+        }
+        _ => code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> ImportedLog {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("logs").join(name);
+        import_file(&path, None).unwrap()
+    }
+
+    fn inline(text: &str) -> ImportedLog {
+        let date = NaiveDate::from_ymd_opt(2025, 12, 31).unwrap();
+        import_reader(text.as_bytes(), date).unwrap()
+    }
+
+    fn at(log: &ImportedLog, line: usize) -> (String, &'static str) {
+        let e = log.events.iter().find(|e| e.line == line).unwrap();
+        let ts = e.at.map(|t| t.to_string()).unwrap_or_default();
+        (ts, e.event.type_name())
+    }
+
+    #[test]
+    fn fixtures_import_without_issues() {
+        for name in ["2025-12-11.txt", "2025-12-12.txt"] {
+            let log = fixture(name);
+            assert!(log.issues.is_empty(), "{}: {:?}", name, log.issues);
+            assert!(!log.events.is_empty());
+        }
+    }
+
+    #[test]
+    fn clear_screen_before_reset_is_stripped() {
+        // 第 424 行为 ESC[2J ESC[H SYSTEM RESET OK.，没有时间前缀
+        let log = fixture("2025-12-11.txt");
+        assert_eq!(
+            at(&log, 424),
+            ("2025-12-11 13:35:45".to_string(), "system_reset")
+        );
+
+        // ESC 已被过滤掉的残留同样去掉
+        let log = inline("[10:00:00] go\n\u{1b}[2J\u{1b}[HSYSTEM RESET OK.\n[2J[HSYSTEM RESET OK.\n");
+        let resets = log
+            .events
+            .iter()
+            .filter(|e| matches!(e.event.kind, CoreEventKind::SystemReset))
+            .count();
+        assert_eq!(resets, 2);
+    }
+
+    #[test]
+    fn unstamped_lines_inherit_previous_time() {
+        // 第 7 行 "SYSTEM IS RUNNING" 横幅（上下是 * 分隔线）没有时间前缀
+        let log = fixture("2025-12-12.txt");
+        assert_eq!(at(&log, 5), ("2025-12-12 11:16:52".to_string(), "error"));
+        assert_eq!(at(&log, 7), ("2025-12-12 11:16:52".to_string(), "banner"));
+    }
+
+    #[test]
+    fn midnight_rolls_date_over() {
+        let log = inline(
+            "[23:59:58] [Live] Stage: 1 | Count: 1 | Total: 1\n\
+             [00:00:01] [Live] Stage: 1 | Count: 2 | Total: 2\n\
+             **********************************\n\
+             SYSTEM IS RUNNING\n",
+        );
+        assert!(log.issues.is_empty(), "{:?}", log.issues);
+        assert_eq!(at(&log, 1), ("2025-12-31 23:59:58".to_string(), "live"));
+        assert_eq!(at(&log, 2), ("2026-01-01 00:00:01".to_string(), "live"));
+        assert_eq!(at(&log, 4), ("2026-01-01 00:00:01".to_string(), "banner"));
+    }
+
+    #[test]
+    fn unparseable_lines_report_line_numbers() {
+        let log = inline(
+            "[10:00:00] LED Self-Test Start......\n\
+             [10:00:01] garbled ### line\n\
+             [10:00:02]  Stage ID      : 3\n",
+        );
+        let issues: Vec<_> = log
+            .issues
+            .iter()
+            .map(|i| (i.line, i.message.as_str()))
+            .collect();
+        assert_eq!(issues, [(2, "无法识别的行"), (3, "字段行不在报告块内")]);
+        assert_eq!(log.issues[0].text, "[10:00:01] garbled ### line");
+        assert!(log.events.is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+// src/dhjc_intervals.rs
+//
+// 主机侧的脉冲间隔统计（每个阶段一份）：
+// - 从 [Live] 的 Count 增量推出脉冲时刻：收到行的时间 - Wait（距上个脉冲的时间）
+// - 两条 Live 之间来了多个脉冲时只知道首尾，中间按等间隔摊开；
+//   摊开的间隔相同，按 (间隔, 个数) 存一份，内存和排序量随 Live 行数而不是脉冲数增长
+// - 阶段报告到来时给出 均值 / 标准差 / 中位数 / P95 / P99 / 直方图，和 MCU 的 Min/Max 对照
+// - 箱线图用的四分位数（须为 1.5 倍四分位距，限制在数据范围内）
+
+use crate::dhjc_core::LiveSample;
+use serde::Serialize;
+
+/// 直方图各档的上界（毫秒），最后一档为 >= 最后一个上界
+pub const HISTOGRAM_EDGES_MS: &[f64] = &[
+    10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0,
+];
+
+/// (间隔毫秒, 个数)
+pub type IntervalRun = (f64, u32);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntervalStats {
+    /// 参与统计的间隔个数
+    pub count: usize,
+    pub mean_ms: f64,
+    pub std_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    /// 长度为 HISTOGRAM_EDGES_MS.len() + 1
+    pub histogram: Vec<u32>,
+}
+
+impl IntervalStats {
+    /// 间隔为空时返回 None
+    pub fn from_runs(runs: &[IntervalRun]) -> Option<Self> {
+        let (sorted, count) = sort_runs(runs)?;
+
+        let n = count as f64;
+        let mean = sorted.iter().map(|(v, c)| v * *c as f64).sum::<f64>() / n;
+        let var = sorted
+            .iter()
+            .map(|(v, c)| (v - mean).powi(2) * *c as f64)
+            .sum::<f64>()
+            / n;
+
+        let mut histogram = vec![0u32; HISTOGRAM_EDGES_MS.len() + 1];
+        for (v, c) in &sorted {
+            let bin = HISTOGRAM_EDGES_MS
+                .iter()
+                .position(|edge| v < edge)
+                .unwrap_or(HISTOGRAM_EDGES_MS.len());
+            histogram[bin] += c;
+        }
+
+        Some(Self {
+            count: count as usize,
+            mean_ms: mean,
+            std_ms: var.sqrt(),
+            median_ms: percentile(&sorted, count, 50.0),
+            p95_ms: percentile(&sorted, count, 95.0),
+            p99_ms: percentile(&sorted, count, 99.0),
+            min_ms: sorted[0].0,
+            max_ms: sorted[sorted.len() - 1].0,
+            histogram,
+        })
+    }
+
+    /// "<10ms:5 <20ms:3 ... >=5000ms:0"（CSV 用）
+    pub fn histogram_text(&self) -> String {
+        let mut parts = Vec::with_capacity(self.histogram.len());
+        for (i, count) in self.histogram.iter().enumerate() {
+            parts.push(format!("{}:{}", histogram_label(i), count));
+        }
+        parts.join(" ")
+    }
+}
+
+/// 第 i 档的名称，如 "<10ms"、">=5000ms"
+pub fn histogram_label(i: usize) -> String {
+    match HISTOGRAM_EDGES_MS.get(i) {
+        Some(edge) => format!("<{}ms", edge),
+        None => format!(">={}ms", HISTOGRAM_EDGES_MS[HISTOGRAM_EDGES_MS.len() - 1]),
+    }
+}
+
+/// 箱线图的五个值（毫秒）
+#[derive(Debug, Clone, Copy)]
+pub struct BoxSummary {
+    pub lower_whisker: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub upper_whisker: f64,
+}
+
+impl BoxSummary {
+    /// 间隔为空时返回 None
+    pub fn from_runs(runs: &[IntervalRun]) -> Option<Self> {
+        let (sorted, count) = sort_runs(runs)?;
+
+        let q1 = percentile(&sorted, count, 25.0);
+        let q3 = percentile(&sorted, count, 75.0);
+        let reach = 1.5 * (q3 - q1);
+        // 须取落在 [q1 - reach, q3 + reach] 内的最远数据点
+        let lower_whisker = sorted
+            .iter()
+            .map(|(v, _)| *v)
+            .find(|v| *v >= q1 - reach)
+            .unwrap_or(q1);
+        let upper_whisker = sorted
+            .iter()
+            .rev()
+            .map(|(v, _)| *v)
+            .find(|v| *v <= q3 + reach)
+            .unwrap_or(q3);
+
+        Some(Self {
+            lower_whisker,
+            q1,
+            median: percentile(&sorted, count, 50.0),
+            q3,
+            upper_whisker,
+        })
+    }
+}
+
+/// 间隔总个数
+pub fn run_count(runs: &[IntervalRun]) -> usize {
+    runs.iter().map(|(_, c)| *c as usize).sum()
+}
+
+// 按间隔排序并去掉个数为 0 的；没有间隔时为 None
+fn sort_runs(runs: &[IntervalRun]) -> Option<(Vec<IntervalRun>, u64)> {
+    let mut sorted: Vec<IntervalRun> = runs.iter().copied().filter(|(_, c)| *c > 0).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let count = sorted.iter().map(|(_, c)| *c as u64).sum();
+    Some((sorted, count))
+}
+
+// 最近秩法；sorted 非空，count 为总个数
+fn percentile(sorted: &[IntervalRun], count: u64, p: f64) -> f64 {
+    let rank = ((p / 100.0 * count as f64).ceil() as u64).clamp(1, count);
+    let mut seen = 0u64;
+    for (v, c) in sorted {
+        seen += *c as u64;
+        if seen >= rank {
+            return *v;
+        }
+    }
+    sorted[sorted.len() - 1].0
+}
+
+/// 跟踪当前阶段的脉冲时刻
+#[derive(Debug, Clone, Default)]
+pub struct IntervalTracker {
+    stage: Option<i32>,
+    last_count: i32,
+    last_pulse_s: Option<f64>,
+    runs: Vec<IntervalRun>,
+}
+
+impl IntervalTracker {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// t 为收到这条 Live 的时间（秒，任意起点）
+    pub fn on_live(&mut self, t: f64, live: &LiveSample) {
+        let count = match live.count {
+            Some(c) => c,
+            None => return,
+        };
+        // 换阶段或计数回退：重新开始
+        if live.stage != self.stage || count < self.last_count {
+            self.reset();
+            self.stage = live.stage;
+        }
+        if count == self.last_count {
+            return;
+        }
+
+        let pulse_s = t - live.wait_ms.unwrap_or(0.0) / 1000.0;
+        let k = count - self.last_count;
+        if let Some(prev) = self.last_pulse_s {
+            // 取到微秒，免得等分后的浮点误差把整数间隔分到相邻两档
+            let each_ms = ((pulse_s - prev) * 1_000_000.0 / k as f64).round() / 1000.0;
+            if each_ms >= 0.0 {
+                match self.runs.last_mut() {
+                    Some((v, c)) if *v == each_ms => *c += k as u32,
+                    _ => self.runs.push((each_ms, k as u32)),
+                }
+            }
+        }
+        // 阶段的第一条 Live 之前的脉冲没有起点，不计入
+        self.last_pulse_s = Some(pulse_s);
+        self.last_count = count;
+    }
+
+    /// 当前阶段（还没出报告）的阶段号
+    pub fn stage(&self) -> Option<i32> {
+        self.stage
+    }
+
+    /// 当前阶段到目前为止的间隔
+    pub fn runs(&self) -> &[IntervalRun] {
+        &self.runs
+    }
+
+    /// 阶段报告到来：给出统计并清空；阶段号对不上（中途连上等）时返回 None
+    pub fn finish_stage(&mut self, stage_id: Option<i32>) -> Option<IntervalStats> {
+        let matches = stage_id.is_none() || self.stage.is_none() || stage_id == self.stage;
+        let stats = if matches {
+            IntervalStats::from_runs(&self.runs)
+        } else {
+            None
+        };
+        self.reset();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn live(stage: i32, count: i32, wait_ms: f64) -> LiveSample {
+        LiveSample {
+            stage: Some(stage),
+            count: Some(count),
+            total: Some(count),
+            wait_ms: Some(wait_ms),
+        }
+    }
+
+    #[test]
+    fn stats_from_weighted_runs() {
+        let s = IntervalStats::from_runs(&[(20.0, 3), (10.0, 1)]).unwrap();
+        assert_eq!(s.count, 4);
+        assert!(close(s.mean_ms, 17.5));
+        // 方差 (7.5² × 1 + 2.5² × 3) / 4 = 18.75
+        assert!(close(s.std_ms, 18.75f64.sqrt()));
+        assert_eq!(s.median_ms, 20.0);
+        assert_eq!(s.p95_ms, 20.0);
+        assert_eq!(s.p99_ms, 20.0);
+        assert_eq!(s.min_ms, 10.0);
+        assert_eq!(s.max_ms, 20.0);
+        // 10 落在 [10, 20)，20 落在 [20, 50)
+        assert_eq!(s.histogram, [0, 1, 3, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            s.histogram_text(),
+            "<10ms:0 <20ms:1 <50ms:3 <100ms:0 <200ms:0 <500ms:0 <1000ms:0 <2000ms:0 <5000ms:0 >=5000ms:0"
+        );
+    }
+
+    #[test]
+    fn empty_runs_have_no_stats() {
+        assert!(IntervalStats::from_runs(&[]).is_none());
+        assert!(IntervalStats::from_runs(&[(10.0, 0)]).is_none());
+        assert!(BoxSummary::from_runs(&[]).is_none());
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        // 1..=100 各一个：第 p 个
+        let runs: Vec<IntervalRun> = (1..=100).map(|v| (v as f64, 1)).collect();
+        let s = IntervalStats::from_runs(&runs).unwrap();
+        assert_eq!(s.median_ms, 50.0);
+        assert_eq!(s.p95_ms, 95.0);
+        assert_eq!(s.p99_ms, 99.0);
+
+        // 秩向上取整：5 个里 P50 是第 3 个，P95 / P99 是第 5 个
+        let runs = [(1.0, 1), (2.0, 1), (3.0, 1), (4.0, 1), (5.0, 1)];
+        let (sorted, count) = sort_runs(&runs).unwrap();
+        assert_eq!(percentile(&sorted, count, 50.0), 3.0);
+        assert_eq!(percentile(&sorted, count, 95.0), 5.0);
+        assert_eq!(percentile(&sorted, count, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, count, 100.0), 5.0);
+    }
+
+    #[test]
+    fn histogram_edges_are_lower_inclusive() {
+        let runs = [
+            (9.999, 1),
+            (10.0, 1),
+            (19.999, 1),
+            (4999.999, 1),
+            (5000.0, 1),
+            (60000.0, 1),
+        ];
+        let s = IntervalStats::from_runs(&runs).unwrap();
+        assert_eq!(s.histogram, [1, 2, 0, 0, 0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn box_summary_whiskers_stay_on_data() {
+        let b = BoxSummary::from_runs(&[(10.0, 1), (20.0, 3)]).unwrap();
+        assert_eq!((b.q1, b.median, b.q3), (10.0, 20.0, 20.0));
+        assert_eq!((b.lower_whisker, b.upper_whisker), (10.0, 20.0));
+
+        // 离群值不算进须
+        let b = BoxSummary::from_runs(&[(10.0, 1), (20.0, 3), (1000.0, 1)]).unwrap();
+        assert_eq!((b.q1, b.median, b.q3), (20.0, 20.0, 20.0));
+        assert_eq!((b.lower_whisker, b.upper_whisker), (20.0, 20.0));
+    }
+
+    #[test]
+    fn tracker_spreads_multi_pulse_increments() {
+        let mut t = IntervalTracker::default();
+        // 第一条 Live 只给起点
+        t.on_live(1.0, &live(1, 1, 0.0));
+        assert!(t.runs().is_empty());
+        // 0.5 s 内来了 5 个：摊成 5 个 100 ms
+        t.on_live(1.5, &live(1, 6, 0.0));
+        assert_eq!(t.runs(), [(100.0, 5)]);
+        // 脉冲时刻 = 收到时间 - Wait；同样的间隔并进同一个 run
+        t.on_live(1.7, &live(1, 7, 100.0));
+        assert_eq!(t.runs(), [(100.0, 6)]);
+        // 计数没变：不是新脉冲
+        t.on_live(2.0, &live(1, 7, 400.0));
+        t.on_live(2.0, &live(1, 8, 0.0));
+        assert_eq!(t.runs(), [(100.0, 6), (400.0, 1)]);
+        assert_eq!(run_count(t.runs()), 7);
+
+        let s = t.finish_stage(Some(1)).unwrap();
+        assert_eq!(s.count, 7);
+        assert!(t.runs().is_empty());
+    }
+
+    #[test]
+    fn tracker_restarts_on_new_stage_or_count_going_back() {
+        let mut t = IntervalTracker::default();
+        t.on_live(1.0, &live(1, 1, 0.0));
+        t.on_live(1.1, &live(1, 2, 0.0));
+        assert_eq!(t.runs().len(), 1);
+
+        t.on_live(2.0, &live(2, 1, 0.0));
+        assert_eq!(t.stage(), Some(2));
+        assert!(t.runs().is_empty());
+
+        t.on_live(2.2, &live(2, 3, 0.0));
+        assert_eq!(t.runs(), [(100.0, 2)]);
+        // 计数回退（复位）：重新开始，这条只作为起点
+        t.on_live(3.0, &live(2, 1, 0.0));
+        assert!(t.runs().is_empty());
+
+        // 阶段号对不上的报告不给统计
+        t.on_live(3.5, &live(2, 2, 0.0));
+        assert!(t.finish_stage(Some(5)).is_none());
+        assert!(t.runs().is_empty());
+    }
+}
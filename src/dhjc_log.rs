@@ -0,0 +1,909 @@
+// src/dhjc_log.rs
+//
+// 日志输出：
+// - LogWriter：人看的文本日志（logs/YYYY-MM-DD.txt），由后台线程批量写盘；
+//   超过大小上限时切到 YYYY-MM-DD.1.txt、.2.txt ...
+//   可选按 session 分文件（YYYY-MM-DD_HHMMSS_session.txt），并维护 sessions_index.csv
+// - EventLogWriter：可选的 JSON Lines 事件日志（logs/YYYY-MM-DD.jsonl），
+//   每行一个解析后的事件，方便 pandas 等工具直接读取
+// - 保留策略：往日文件压缩成 .gz，按天数 / 总大小从最早的日期开始删除
+
+use crate::dhjc_clock::{Clock, TimestampFormat};
+use crate::dhjc_config::{AppConfig, LogRetention};
+use crate::dhjc_core::{CoreEvent, CoreEventKind, TotalSummary};
+use crate::dhjc_export::csv_line;
+use chrono::{DateTime, Days, Local, NaiveDate, SecondsFormat};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// 日志队列容量；写盘跟不上时新行直接丢弃并计数，不阻塞 GUI
+const LOG_QUEUE_CAPACITY: usize = 4096;
+
+/// 两次 fsync 之间的最长间隔
+const SYNC_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 事件日志线程正开着的文件。事件日志只在下一条事件到来时才换日期，
+/// 整理日志时要跳过这些文件，不能压缩 / 删除还开着的文件
+static OPEN_EVENT_LOGS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn open_event_logs() -> MutexGuard<'static, Vec<PathBuf>> {
+    OPEN_EVENT_LOGS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// 只去掉一条：替换写入器时新旧两个线程可能短暂开着同一个文件
+fn unregister_event_log(open: &mut Vec<PathBuf>, path: Option<&PathBuf>) {
+    if let Some(i) = open.iter().position(|p| Some(p) == path) {
+        open.swap_remove(i);
+    }
+}
+
+/// 按 session 分文件时的索引：每个 session 一行
+pub const SESSION_INDEX_FILE: &str = "sessions_index.csv";
+
+const SESSION_INDEX_HEADER: &[&str] = &[
+    "session_start",
+    "session_end",
+    "file",
+    "status",
+    "total_stages",
+    "grand_total",
+    "active_time_s",
+    "avg_frequency_hz",
+];
+
+enum LogMsg {
+    /// plain：标题行 / 分隔线（不带时间戳的行）
+    Line {
+        date: String,
+        text: String,
+        plain: bool,
+    },
+    SessionStart(DateTime<Local>),
+    SessionEnd(DateTime<Local>, TotalSummary),
+    Shutdown,
+}
+
+/// 文本日志：logs/YYYY-MM-DD.txt，按日期切换
+/// 时间戳在调用时生成，实际写盘在后台线程，Drop 时写完队列并 fsync
+pub struct LogWriter {
+    tx: SyncSender<LogMsg>,
+    dropped: Arc<AtomicU64>,
+    notice_rx: Receiver<String>,
+    per_session: bool,
+    /// GUI 这边认为 session 已开始（用于 note_activity）
+    session_open: bool,
+    /// 队列满时暂存的 session 边界，之后按顺序补发；补发完之前新行一律丢弃，保证先后不乱
+    pending_control: VecDeque<LogMsg>,
+    clock: Clock,
+    timestamp: TimestampFormat,
+    monotonic: bool,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl LogWriter {
+    /// clock 与 CoreState 共用，连接时由 GUI 标记连接时刻
+    pub fn new(cfg: &AppConfig, clock: Clock) -> Self {
+        let base_folder = cfg.log_folder.as_str();
+        let retention = cfg.log_retention;
+        let per_session = cfg.log_per_session;
+        let (tx, rx) = mpsc::sync_channel(LOG_QUEUE_CAPACITY);
+        let (notice_tx, notice_rx) = mpsc::channel();
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let mut sink = LogFileSink {
+            base_folder: base_folder.to_string(),
+            retention,
+            current_date: String::new(),
+            current_part: 0,
+            current_size: 0,
+            file: None,
+            per_session,
+            session: None,
+            pending: Vec::new(),
+            dirty: false,
+            last_sync: Instant::now(),
+            dropped: dropped.clone(),
+            dropped_reported: 0,
+            notice_tx,
+            maintaining: Arc::new(AtomicBool::new(false)),
+            clock: clock.clone(),
+            timestamp: cfg.log_timestamp,
+            monotonic: cfg.log_monotonic,
+        };
+        let handle = thread::spawn(move || sink.run(rx));
+
+        Self {
+            tx,
+            dropped,
+            notice_rx,
+            per_session,
+            session_open: false,
+            pending_control: VecDeque::new(),
+            clock,
+            timestamp: cfg.log_timestamp,
+            monotonic: cfg.log_monotonic,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn write_line(&mut self, content: &str) {
+        let now = self.clock.now();
+
+        // 标题行 / 分隔线不加时间戳
+        let mut no_ts = false;
+        if content.is_empty() {
+            no_ts = true;
+        } else {
+            let mut chars = content.chars();
+            if let Some(c0) = chars.next() {
+                if c0 == '*' || c0 == '-' || c0 == '=' {
+                    no_ts = true;
+                }
+            }
+            if content.contains("SYSTEM") {
+                no_ts = true;
+            }
+        }
+
+        let line_to_write = if no_ts {
+            format!("{}\r\n", content)
+        } else {
+            let ts = now.log_prefix(self.timestamp, self.monotonic);
+            format!("[{}] {}\r\n", ts, content)
+        };
+
+        let msg = LogMsg::Line {
+            date: now.wall.format("%Y-%m-%d").to_string(),
+            text: line_to_write,
+            plain: no_ts,
+        };
+        if !self.flush_control() || self.tx.try_send(msg).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 系统复位 / 启动信息：按 session 分文件时开始新文件
+    /// session 边界不能丢：队列满时先暂存，由 flush_control 补发，不阻塞 GUI
+    pub fn start_session(&mut self) {
+        if self.per_session {
+            self.session_open = true;
+            let msg = LogMsg::SessionStart(self.clock.now().wall);
+            self.send_control(msg);
+        }
+    }
+
+    /// 有脉冲数据：上一个 session 超时结束后 MCU 不一定再发启动信息，
+    /// 这时以第一条数据作为新 session 的开始
+    pub fn note_activity(&mut self) {
+        if !self.session_open {
+            self.start_session();
+        }
+    }
+
+    /// [TOTAL SUMMARY]：结束当前 session 文件并写索引
+    pub fn end_session(&mut self, summary: &TotalSummary) {
+        if self.per_session {
+            self.session_open = false;
+            let msg = LogMsg::SessionEnd(self.clock.now().wall, summary.clone());
+            self.send_control(msg);
+        }
+    }
+
+    /// 补发暂存的 session 边界（GUI 每帧调用）；全部发出时返回 true
+    pub fn flush_control(&mut self) -> bool {
+        while let Some(msg) = self.pending_control.pop_front() {
+            match self.tx.try_send(msg) {
+                Ok(()) => {}
+                Err(TrySendError::Full(msg)) => {
+                    self.pending_control.push_front(msg);
+                    return false;
+                }
+                Err(TrySendError::Disconnected(_)) => self.pending_control.clear(),
+            }
+        }
+        true
+    }
+
+    fn send_control(&mut self, msg: LogMsg) {
+        self.pending_control.push_back(msg);
+        self.flush_control();
+    }
+
+    /// 因队列满而丢弃的行数（累计）
+    pub fn dropped_lines(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 压缩 / 删除旧日志的记录（由 GUI 放进 Event Log）
+    pub fn take_notices(&self) -> Vec<String> {
+        self.notice_rx.try_iter().collect()
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        // 阻塞发送：队列满时等后台线程腾出位置，保证退出前写完（包括暂存的 session 边界）
+        for msg in self.pending_control.drain(..) {
+            let _ = self.tx.send(msg);
+        }
+        let _ = self.tx.send(LogMsg::Shutdown);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+// 后台线程持有的文件状态
+struct LogFileSink {
+    base_folder: String,
+    retention: LogRetention,
+    current_date: String,
+    current_part: u32,
+    current_size: u64,
+    file: Option<BufWriter<File>>,
+    dirty: bool,
+    last_sync: Instant,
+    dropped: Arc<AtomicU64>,
+    dropped_reported: u64,
+    notice_tx: Sender<String>,
+    /// 整理线程正在运行
+    maintaining: Arc<AtomicBool>,
+    /// 自己写的行（丢行提示）也按配置的格式、用共用的时钟打时间戳
+    clock: Clock,
+    timestamp: TimestampFormat,
+    monotonic: bool,
+
+    per_session: bool,
+    /// 打开着的 session 文件；此时 file 指向它，不按日期 / 大小切换
+    session: Option<SessionInfo>,
+    /// 暂存的标题行 / 分隔线：复位、启动横幅要跟随后面的 session 开始
+    pending: Vec<(String, String)>,
+}
+
+struct SessionInfo {
+    path: PathBuf,
+    started: DateTime<Local>,
+    /// 除横幅 / 分隔线以外是否写过内容；没有的话新的开始信号不再开新文件
+    has_data: bool,
+}
+
+impl LogFileSink {
+    fn run(&mut self, rx: Receiver<LogMsg>) {
+        self.spawn_maintenance();
+        loop {
+            // 空闲时也定期醒来，把未 fsync 的内容落盘
+            let first = match rx.recv_timeout(SYNC_INTERVAL) {
+                Ok(m) => Some(m),
+                Err(RecvTimeoutError::Timeout) => {
+                    self.flush_pending();
+                    None
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            let mut shutdown = false;
+            for msg in first.into_iter().chain(rx.try_iter()) {
+                match msg {
+                    LogMsg::Line { date, text, plain } => {
+                        if plain && self.per_session {
+                            self.pending.push((date, text));
+                        } else {
+                            self.flush_pending();
+                            self.write(&date, &text);
+                            if let Some(s) = self.session.as_mut() {
+                                s.has_data = true;
+                            }
+                        }
+                    }
+                    LogMsg::SessionStart(at) => self.start_session(at),
+                    LogMsg::SessionEnd(at, summary) => {
+                        self.flush_pending();
+                        self.close_session(at, Some(&summary));
+                    }
+                    LogMsg::Shutdown => shutdown = true,
+                }
+            }
+            self.report_dropped();
+
+            // 每批写完都交给系统（进程崩溃不丢），fsync 按间隔做（防断电）
+            self.flush();
+            if shutdown || self.last_sync.elapsed() >= SYNC_INTERVAL {
+                self.sync();
+            }
+            if shutdown {
+                break;
+            }
+        }
+        self.flush_pending();
+        if self.session.is_some() {
+            self.close_session(self.clock.now().wall, None);
+        }
+        self.flush();
+        self.sync();
+    }
+
+    fn flush_pending(&mut self) {
+        for (date, text) in std::mem::take(&mut self.pending) {
+            self.write(&date, &text);
+        }
+    }
+
+    fn start_session(&mut self, at: DateTime<Local>) {
+        // 复位后紧跟的启动横幅属于同一个 session
+        if self.session.as_ref().is_some_and(|s| !s.has_data) {
+            self.flush_pending();
+            return;
+        }
+        if self.session.is_some() {
+            self.close_session(at, None);
+        }
+
+        self.flush();
+        self.sync();
+        // 同一秒内多次复位时顺延到下一秒，文件名保持可排序
+        let mut name_time = at;
+        let mut name = format!("{}_session.txt", name_time.format("%Y-%m-%d_%H%M%S"));
+        while Path::new(&self.base_folder).join(&name).exists() {
+            name_time += chrono::Duration::seconds(1);
+            name = format!("{}_session.txt", name_time.format("%Y-%m-%d_%H%M%S"));
+        }
+        self.file = open_in_folder(&self.base_folder, &name).map(BufWriter::new);
+        // session 结束后重新打开当天的文件
+        self.current_date.clear();
+        self.session = Some(SessionInfo {
+            path: Path::new(&self.base_folder).join(&name),
+            started: at,
+            has_data: false,
+        });
+        self.flush_pending();
+    }
+
+    // summary 为 None：没等到 [TOTAL SUMMARY] 就开始了新的 session 或程序退出
+    fn close_session(&mut self, at: DateTime<Local>, summary: Option<&TotalSummary>) {
+        let info = match self.session.take() {
+            Some(s) => s,
+            None => return,
+        };
+        self.flush();
+        self.sync();
+        self.file = None;
+
+        let fmt = |t: &DateTime<Local>| t.format("%Y-%m-%d %H:%M:%S").to_string();
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        let row = [
+            fmt(&info.started),
+            fmt(&at),
+            info.path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            summary.and_then(|s| s.status.clone()).unwrap_or_default(),
+            opt(summary.and_then(|s| s.total_stages).map(|v| v.to_string())),
+            opt(summary.and_then(|s| s.grand_total).map(|v| v.to_string())),
+            opt(summary.and_then(|s| s.active_time_s).map(|v| v.to_string())),
+            opt(summary
+                .and_then(|s| s.avg_frequency_hz)
+                .map(|v| v.to_string())),
+        ];
+        if let Err(e) = append_index_row(&self.base_folder, &row) {
+            eprintln!("[LOG] 写入 session 索引失败: {:?}", e);
+        }
+    }
+
+    fn write(&mut self, date: &str, text: &str) {
+        if self.session.is_some() {
+            // session 文件不按日期 / 大小切换
+        } else if date != self.current_date {
+            let new_day = !self.current_date.is_empty();
+            self.current_date = date.to_string();
+            // 接着写当天最后一个分段（程序重启的情况）
+            let part = list_log_files(&self.base_folder)
+                .into_iter()
+                .filter(|(n, _)| n.date_str() == date && n.ext == "txt" && !n.gz && !n.session)
+                .map(|(n, _)| n.part)
+                .max()
+                .unwrap_or(0);
+            self.open_part(part);
+            if new_day {
+                self.spawn_maintenance();
+            }
+        } else if self.retention.max_file_mb > 0
+            && self.current_size >= mb_to_bytes(self.retention.max_file_mb)
+        {
+            self.open_part(self.current_part + 1);
+            self.spawn_maintenance();
+        }
+
+        let file = match self.file.as_mut() {
+            Some(f) => f,
+            None => return,
+        };
+        if let Err(e) = file.write_all(text.as_bytes()) {
+            eprintln!("[LOG] 写入日志失败: {:?}", e);
+        } else {
+            self.dirty = true;
+            self.current_size += text.len() as u64;
+        }
+    }
+
+    fn open_part(&mut self, part: u32) {
+        self.flush();
+        self.sync();
+        let name = log_file_name(&self.current_date, part, "txt");
+        self.file = open_in_folder(&self.base_folder, &name).map(BufWriter::new);
+        self.current_part = part;
+        self.current_size = self
+            .file
+            .as_ref()
+            .and_then(|f| f.get_ref().metadata().ok())
+            .map(|m| m.len())
+            .unwrap_or(0);
+    }
+
+    // 压缩和删除可能比较慢，放到单独的线程，不耽误写日志
+    fn spawn_maintenance(&self) {
+        if self.maintaining.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let folder = self.base_folder.clone();
+        let retention = self.retention;
+        let today = self.clock.now().wall.date_naive();
+        let notice_tx = self.notice_tx.clone();
+        let maintaining = self.maintaining.clone();
+        let open_session = self.session.as_ref().map(|s| s.path.clone());
+        thread::spawn(move || {
+            for msg in maintain_logs(&folder, retention, today, open_session.as_deref()) {
+                let _ = notice_tx.send(msg);
+            }
+            maintaining.store(false, Ordering::Release);
+        });
+    }
+
+    // 丢行的情况也记进日志文件，方便事后知道哪里不完整
+    fn report_dropped(&mut self) {
+        let total = self.dropped.load(Ordering::Relaxed);
+        if total > self.dropped_reported && self.file.is_some() {
+            let now = self.clock.now();
+            let text = format!(
+                "[{}] [LOG] 日志队列已满，丢弃 {} 行\r\n",
+                now.log_prefix(self.timestamp, self.monotonic),
+                total - self.dropped_reported
+            );
+            let date = now.wall.format("%Y-%m-%d").to_string();
+            self.write(&date, &text);
+            self.dropped_reported = total;
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(f) = self.file.as_mut() {
+            if let Err(e) = f.flush() {
+                eprintln!("[LOG] 写入日志失败: {:?}", e);
+            }
+        }
+    }
+
+    fn sync(&mut self) {
+        if self.dirty {
+            if let Some(f) = self.file.as_mut() {
+                let _ = f.get_ref().sync_data();
+            }
+            self.dirty = false;
+        }
+        self.last_sync = Instant::now();
+    }
+}
+
+/// JSON Lines 事件日志，与文本日志放在同一目录，同样按日期切换
+/// 和 LogWriter 一样：时间戳取自共用的 Clock，序列化后交给后台线程写盘，队列满时丢弃并计数
+pub struct EventLogWriter {
+    tx: SyncSender<EventLogMsg>,
+    device_id: String,
+    clock: Clock,
+    dropped: Arc<AtomicU64>,
+    handle: Option<JoinHandle<()>>,
+}
+
+enum EventLogMsg {
+    Record { date: String, line: String },
+    Shutdown,
+}
+
+impl EventLogWriter {
+    pub fn new(base_folder: &str, device_id: &str, clock: Clock) -> Self {
+        let (tx, rx) = mpsc::sync_channel(LOG_QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let base_folder = base_folder.to_string();
+        let handle = thread::spawn(move || write_event_log(&base_folder, rx));
+
+        Self {
+            tx,
+            device_id: device_id.to_string(),
+            clock,
+            dropped,
+            handle: Some(handle),
+        }
+    }
+
+    /// 写一条事件：
+    /// {"ts": ISO-8601 毫秒, "device": ..., "type": ..., "fields": {...}, "raw": [...]}
+    pub fn write_event(&self, event: &CoreEvent) {
+        let now = self.clock.now().wall;
+        let record = json!({
+            "ts": now.to_rfc3339_opts(SecondsFormat::Millis, false),
+            "device": self.device_id,
+            "type": event.type_name(),
+            "fields": event_fields(event),
+            "raw": event.raw,
+        });
+        let msg = EventLogMsg::Record {
+            date: now.format("%Y-%m-%d").to_string(),
+            line: format!("{}\n", record),
+        };
+        if self.tx.try_send(msg).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 因队列满而丢弃的事件数（累计）；每行都是 JSON，丢弃的条数不写进文件，由 GUI 提示
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for EventLogWriter {
+    fn drop(&mut self) {
+        let _ = self.tx.send(EventLogMsg::Shutdown);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+// 事件日志的后台线程：按日期换文件，每批写完 flush 一次
+fn write_event_log(base_folder: &str, rx: Receiver<EventLogMsg>) {
+    let mut current_date = String::new();
+    let mut current_path: Option<PathBuf> = None;
+    let mut file: Option<BufWriter<File>> = None;
+
+    while let Ok(first) = rx.recv() {
+        let mut shutdown = false;
+        for msg in std::iter::once(first).chain(rx.try_iter()) {
+            let (date, line) = match msg {
+                EventLogMsg::Record { date, line } => (date, line),
+                EventLogMsg::Shutdown => {
+                    shutdown = true;
+                    continue;
+                }
+            };
+            if date != current_date {
+                if let Some(f) = file.as_mut() {
+                    let _ = f.flush();
+                }
+                // 换文件和登记在同一把锁里：整理线程不会在中间压缩它
+                let name = format!("{}.jsonl", date);
+                let path = Path::new(base_folder).join(&name);
+                let mut open = open_event_logs();
+                drop(file.take());
+                unregister_event_log(&mut open, current_path.as_ref());
+                file = open_in_folder(base_folder, &name).map(BufWriter::new);
+                if file.is_some() {
+                    open.push(path.clone());
+                }
+                current_path = Some(path);
+                current_date = date;
+            }
+            if let Some(f) = file.as_mut() {
+                if let Err(e) = f.write_all(line.as_bytes()) {
+                    eprintln!("[LOG] 写入事件日志失败: {:?}", e);
+                }
+            }
+        }
+        if let Some(f) = file.as_mut() {
+            if let Err(e) = f.flush() {
+                eprintln!("[LOG] 写入事件日志失败: {:?}", e);
+            }
+        }
+
+        if shutdown {
+            break;
+        }
+    }
+
+    drop(file);
+    unregister_event_log(&mut open_event_logs(), current_path.as_ref());
+}
+
+/// 事件日志里的 "fields"（导入工具输出 JSON 时也用这个）
+pub fn event_fields(event: &CoreEvent) -> serde_json::Value {
+    match &event.kind {
+        CoreEventKind::SystemReset => json!({}),
+        CoreEventKind::Banner(text) => json!({ "text": text }),
+        CoreEventKind::Live(s) => serde_json::to_value(s).unwrap_or_default(),
+        CoreEventKind::StageReport(r) => serde_json::to_value(r).unwrap_or_default(),
+        CoreEventKind::TotalSummary(s) => serde_json::to_value(s).unwrap_or_default(),
+        CoreEventKind::Error(msg) => json!({ "message": msg }),
+        CoreEventKind::ConsistencyWarning(w) => {
+            let mut v = serde_json::to_value(w).unwrap_or_default();
+            v["message"] = json!(w.message());
+            v
+        }
+    }
+}
+
+// ----------------- 文件名 / 保留策略 -----------------
+
+/// 日志目录中的文件名：YYYY-MM-DD[.N].{txt,jsonl}[.gz]
+/// 或 session 文件 YYYY-MM-DD_HHMMSS_session.txt[.gz]
+pub struct LogFileName {
+    pub date: NaiveDate,
+    /// 当天第几个分段，0 表示没有分段号
+    pub part: u32,
+    pub ext: String,
+    pub gz: bool,
+    pub session: bool,
+}
+
+impl LogFileName {
+    pub fn parse(name: &str) -> Option<Self> {
+        let (name, gz) = match name.strip_suffix(".gz") {
+            Some(n) => (n, true),
+            None => (name, false),
+        };
+        let (stem, ext) = name.rsplit_once('.')?;
+        if ext != "txt" && ext != "jsonl" {
+            return None;
+        }
+        if let Some(s) = stem.strip_suffix("_session") {
+            let (date, time) = s.split_once('_')?;
+            if time.len() != 6 || !time.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            return Some(Self {
+                date: NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+                part: 0,
+                ext: ext.to_string(),
+                gz,
+                session: true,
+            });
+        }
+        let (date, part) = match stem.split_once('.') {
+            Some((d, p)) => (d, p.parse().ok()?),
+            None => (stem, 0),
+        };
+        Some(Self {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+            part,
+            ext: ext.to_string(),
+            gz,
+            session: false,
+        })
+    }
+
+    fn date_str(&self) -> String {
+        self.date.format("%Y-%m-%d").to_string()
+    }
+}
+
+fn log_file_name(date: &str, part: u32, ext: &str) -> String {
+    if part == 0 {
+        format!("{}.{}", date, ext)
+    } else {
+        format!("{}.{}.{}", date, part, ext)
+    }
+}
+
+/// 日志目录中所有按日期命名的日志，按 (日期, 分段) 排序
+pub fn list_log_files(folder: &str) -> Vec<(LogFileName, PathBuf)> {
+    let mut files: Vec<(LogFileName, PathBuf)> = match fs::read_dir(folder) {
+        Ok(rd) => rd
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = LogFileName::parse(&e.file_name().to_string_lossy())?;
+                Some((name, e.path()))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort_by(|(a, pa), (b, pb)| (a.date, a.part, pa).cmp(&(b.date, b.part, pb)));
+    files
+}
+
+/// 逐行读取日志，.gz 自动解压
+pub fn open_log_reader(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    if path.extension().is_some_and(|e| e == "gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// 按保留策略整理日志目录，返回做过的操作（写进 Event Log）
+/// 当天的文件、正在写的 session 文件和事件日志开着的文件既不压缩也不删除
+fn maintain_logs(
+    folder: &str,
+    retention: LogRetention,
+    today: NaiveDate,
+    open_session: Option<&Path>,
+) -> Vec<String> {
+    let mut notes = Vec::new();
+    let list = || {
+        let mut files = list_log_files(folder);
+        let open_events = open_event_logs();
+        files.retain(|(_, path)| {
+            Some(path.as_path()) != open_session && !open_events.contains(path)
+        });
+        files
+    };
+
+    if retention.compress {
+        for (name, path) in list() {
+            if name.date < today && !name.gz {
+                // 持锁压缩：事件日志线程此时不能重新打开这个文件
+                let open_events = open_event_logs();
+                if open_events.contains(&path) {
+                    continue;
+                }
+                match gzip_file(&path) {
+                    Ok(gz) => notes.push(format!(
+                        "[LOG] 已压缩 {}",
+                        gz.file_name().unwrap_or_default().to_string_lossy()
+                    )),
+                    Err(e) => {
+                        notes.push(format!("[LOG] 压缩 {} 失败: {}", path.to_string_lossy(), e))
+                    }
+                }
+            }
+        }
+    }
+
+    let mut files = list();
+
+    if retention.max_age_days > 0 {
+        if let Some(cutoff) = today.checked_sub_days(Days::new(retention.max_age_days)) {
+            files.retain(|(name, path)| {
+                if name.date >= cutoff {
+                    return true;
+                }
+                remove_log(path, "超过保留天数", &mut notes);
+                false
+            });
+        }
+    }
+
+    if retention.max_total_mb > 0 {
+        let limit = mb_to_bytes(retention.max_total_mb);
+        let size = |p: &PathBuf| fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+        let mut total: u64 = files.iter().map(|(_, p)| size(p)).sum();
+        for (name, path) in &files {
+            if total <= limit || name.date >= today {
+                break;
+            }
+            let len = size(path);
+            if remove_log(path, "超过总大小上限", &mut notes) {
+                total -= len;
+            }
+        }
+    }
+
+    notes
+}
+
+// 配置里的 MB 只检查了非负，很大的值按上限处理，不能溢出绕成很小的限额
+fn mb_to_bytes(mb: u64) -> u64 {
+    mb.saturating_mul(1024 * 1024)
+}
+
+fn remove_log(path: &Path, reason: &str, notes: &mut Vec<String>) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    match fs::remove_file(path) {
+        Ok(()) => {
+            notes.push(format!("[LOG] 已删除 {}（{}）", name, reason));
+            true
+        }
+        Err(e) => {
+            notes.push(format!("[LOG] 删除 {} 失败: {}", name, e));
+            false
+        }
+    }
+}
+
+// 先写到 .gz.tmp，完整落盘后再改名并删除原文件
+fn gzip_file(path: &Path) -> io::Result<PathBuf> {
+    let mut gz_name = path.as_os_str().to_owned();
+    gz_name.push(".gz");
+    let gz_path = PathBuf::from(gz_name);
+    let mut tmp_name = gz_path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let result = (|| {
+        let mut input = File::open(path)?;
+        let mut encoder = GzEncoder::new(File::create(&tmp_path)?, Compression::default());
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        fs::rename(&tmp_path, &gz_path)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    // 原文件还被占用（比如 Windows 上被别的程序打开）时保留原文件，下次再压
+    if let Err(e) = fs::remove_file(path) {
+        let _ = fs::remove_file(&gz_path);
+        return Err(e);
+    }
+    Ok(gz_path)
+}
+
+fn append_index_row(base_folder: &str, row: &[String]) -> io::Result<()> {
+    let path = Path::new(base_folder).join(SESSION_INDEX_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        let header: Vec<String> = SESSION_INDEX_HEADER.iter().map(|h| h.to_string()).collect();
+        file.write_all(csv_line(&header).as_bytes())?;
+    }
+    file.write_all(csv_line(row).as_bytes())?;
+    file.sync_data()
+}
+
+fn open_in_folder(base_folder: &str, filename: &str) -> Option<File> {
+    let log_dir = Path::new(base_folder);
+    if let Err(e) = create_dir_all(log_dir) {
+        eprintln!("[LOG] 创建日志目录失败: {:?}", e);
+        return None;
+    }
+
+    let path = log_dir.join(filename);
+
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(f) => {
+            println!("[LOG] 当前日志文件: {}", path.to_string_lossy());
+            Some(f)
+        }
+        Err(e) => {
+            eprintln!("[LOG] 打开日志文件失败: {:?}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_size_limit_keeps_old_logs() {
+        let dir = std::env::temp_dir().join(format!("dhjc_log_limit_{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        fs::write(dir.join("2025-12-11.txt"), "[13:35:45] old\r\n").unwrap();
+        let folder = dir.to_string_lossy().to_string();
+
+        let retention = LogRetention {
+            max_age_days: 0,
+            max_total_mb: u64::MAX,
+            max_file_mb: u64::MAX,
+            compress: false,
+        };
+        let today = NaiveDate::from_ymd_opt(2025, 12, 12).unwrap();
+        let notes = maintain_logs(&folder, retention, today, None);
+        assert!(notes.is_empty(), "{:?}", notes);
+        assert!(dir.join("2025-12-11.txt").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,998 @@
+// src/dhjc_plot_export.rs
+//
+// 曲线导出（质量报告用）：
+// - PNG / SVG：同一套布局（坐标轴、网格、标记竖线、图例），分别画到 tiny-skia 位图和 SVG 文本
+//   PNG 不依赖窗口，命令行下也能渲染；字体用 egui 自带的 Ubuntu-Light（没有中文字形，缺字跳过）
+// - CSV：每行一个点，带本地时间戳
+// - 命令行：dhjc_rust_gui plot <日志文件>...，把历史日志的总数曲线渲染成图片
+
+use crate::dhjc_export::csv_line;
+use crate::dhjc_history::{load_history, HistoryItem};
+use crate::dhjc_log::LogFileName;
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta, Timelike};
+use std::fmt::Write as _;
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+use tiny_skia::{
+    Paint, PathBuilder, Pixmap, PremultipliedColorU8, Rect, Stroke, StrokeDash, Transform,
+};
+
+/// 时钟横轴可选的刻度间隔（秒），按整秒 / 整分 / 整点对齐
+pub const CLOCK_STEPS_S: &[f64] = &[
+    1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0, 3600.0, 7200.0,
+    10800.0, 21600.0, 43200.0, 86400.0,
+];
+
+/// 图片宽高的上限（像素）；4096x4096 的位图已有 64 MiB
+pub const MAX_IMAGE_SIDE: u32 = 4096;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+// 绘图区到图片边缘的距离（像素）
+const MARGIN_LEFT: f32 = 70.0;
+const MARGIN_RIGHT: f32 = 20.0;
+const MARGIN_TOP: f32 = 40.0;
+const MARGIN_BOTTOM: f32 = 50.0;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const FRAME: [u8; 3] = [120, 120, 120];
+const GRID: [u8; 3] = [225, 225, 225];
+const TEXT: [u8; 3] = [40, 40, 40];
+
+pub struct ExportSeries {
+    pub name: String,
+    pub color: [u8; 3],
+    /// [x, y]，y 为 NaN 表示断线
+    pub points: Vec<[f64; 2]>,
+}
+
+pub struct ExportMarker {
+    pub x: f64,
+    pub label: String,
+    pub color: [u8; 3],
+}
+
+pub struct PlotExport {
+    pub title: String,
+    pub y_label: String,
+    /// x = 0 对应的本地时间；None 时横轴按数值显示（如行号）
+    pub start: Option<NaiveDateTime>,
+    pub x_label: String,
+    pub series: Vec<ExportSeries>,
+    pub markers: Vec<ExportMarker>,
+    /// 只导出这一段（当前视图）；None 为全部数据
+    pub x_range: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportFormats {
+    pub png: bool,
+    pub svg: bool,
+    pub csv: bool,
+}
+
+impl PlotExport {
+    /// 按 formats 写出 <stem>.png / .svg / .csv，返回写出的文件
+    pub fn save(
+        &self,
+        out_dir: &Path,
+        stem: &str,
+        formats: ExportFormats,
+        size: (u32, u32),
+    ) -> Result<Vec<PathBuf>, String> {
+        create_dir_all(out_dir)
+            .map_err(|e| format!("创建导出目录 {} 失败: {}", out_dir.to_string_lossy(), e))?;
+
+        let mut written = Vec::new();
+        let mut write = |ext: &str, bytes: &[u8]| -> Result<(), String> {
+            let path = out_dir.join(format!("{}.{}", stem, ext));
+            fs::write(&path, bytes)
+                .map_err(|e| format!("写入 {} 失败: {}", path.to_string_lossy(), e))?;
+            written.push(path);
+            Ok(())
+        };
+        if formats.png {
+            write("png", &self.render_png(size.0, size.1)?)?;
+        }
+        if formats.svg {
+            write("svg", self.render_svg(size.0, size.1).as_bytes())?;
+        }
+        if formats.csv {
+            write("csv", self.to_csv().as_bytes())?;
+        }
+        Ok(written)
+    }
+
+    pub fn render_svg(&self, width: u32, height: u32) -> String {
+        let mut canvas = SvgCanvas::new(width, height);
+        self.draw(&mut canvas, width as f32, height as f32);
+        canvas.finish()
+    }
+
+    pub fn render_png(&self, width: u32, height: u32) -> Result<Vec<u8>, String> {
+        let pixmap = Pixmap::new(width.max(1), height.max(1))
+            .ok_or_else(|| format!("图片尺寸 {}x{} 无效", width, height))?;
+        let font = FontRef::try_from_slice(epaint_default_fonts::UBUNTU_LIGHT)
+            .map_err(|e| format!("加载字体失败: {}", e))?;
+        let mut canvas = PngCanvas { pixmap, font };
+        self.draw(&mut canvas, width as f32, height as f32);
+
+        let mut out = Vec::new();
+        let mut encoder =
+            png::Encoder::new(&mut out, canvas.pixmap.width(), canvas.pixmap.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| format!("PNG 编码失败: {}", e))?;
+        // 背景不透明，预乘与否结果相同
+        writer
+            .write_image_data(canvas.pixmap.data())
+            .map_err(|e| format!("PNG 编码失败: {}", e))?;
+        writer
+            .finish()
+            .map_err(|e| format!("PNG 编码失败: {}", e))?;
+        Ok(out)
+    }
+
+    /// timestamp,elapsed_s,series,value；只含 x_range 内的点，断线点不输出
+    pub fn to_csv(&self) -> String {
+        let mut out = csv_line(&[
+            "timestamp".to_string(),
+            "elapsed_s".to_string(),
+            "series".to_string(),
+            "value".to_string(),
+        ]);
+        for s in &self.series {
+            for p in &s.points {
+                if !p[1].is_finite() || !self.in_range(p[0]) {
+                    continue;
+                }
+                let ts = self
+                    .wall_at(p[0])
+                    .map(|t| t.format(TIMESTAMP_FORMAT).to_string())
+                    .unwrap_or_default();
+                let value = if p[1].fract() == 0.0 {
+                    format!("{:.0}", p[1])
+                } else {
+                    format!("{:.3}", p[1])
+                };
+                out.push_str(&csv_line(&[
+                    ts,
+                    format!("{:.3}", p[0]),
+                    s.name.clone(),
+                    value,
+                ]));
+            }
+        }
+        out
+    }
+
+    fn in_range(&self, x: f64) -> bool {
+        self.x_range.is_none_or(|(lo, hi)| x >= lo && x <= hi)
+    }
+
+    // 超出 chrono 范围（视图缩得很小）时为 None
+    fn wall_at(&self, x: f64) -> Option<NaiveDateTime> {
+        let ms = (x * 1000.0).round();
+        if !ms.is_finite() {
+            return None;
+        }
+        let delta = TimeDelta::try_milliseconds(ms as i64)?;
+        self.start?.checked_add_signed(delta)
+    }
+
+    // 横轴：x_range 或全部数据；纵轴：范围内的数据，含 0，上下留 5%
+    fn bounds(&self) -> Option<((f64, f64), (f64, f64))> {
+        let finite = || {
+            self.series
+                .iter()
+                .flat_map(|s| s.points.iter())
+                .filter(|p| p[1].is_finite())
+        };
+        let (x0, x1) = match self.x_range {
+            Some(r) => r,
+            None => finite().fold(None, |acc: Option<(f64, f64)>, p| match acc {
+                Some((lo, hi)) => Some((lo.min(p[0]), hi.max(p[0]))),
+                None => Some((p[0], p[0])),
+            })?,
+        };
+        let (y0, y1) = finite()
+            .filter(|p| p[0] >= x0 && p[0] <= x1)
+            .fold((0.0f64, 0.0f64), |(lo, hi), p| (lo.min(p[1]), hi.max(p[1])));
+
+        let x1 = if x1 > x0 { x1 } else { x0 + 1.0 };
+        let pad = ((y1 - y0) * 0.05).max(0.5);
+        let y0 = if y0 < 0.0 { y0 - pad } else { y0 };
+        Some(((x0, x1), (y0, y1 + pad)))
+    }
+
+    fn draw(&self, c: &mut dyn Canvas, w: f32, h: f32) {
+        c.rect(0.0, 0.0, w, h, BACKGROUND);
+        c.text(MARGIN_LEFT, 22.0, &self.title, 16.0, TEXT, Anchor::Start);
+
+        let (left, right) = (MARGIN_LEFT, (w - MARGIN_RIGHT).max(MARGIN_LEFT + 1.0));
+        let (top, bottom) = (MARGIN_TOP, (h - MARGIN_BOTTOM).max(MARGIN_TOP + 1.0));
+        let ((x0, x1), (y0, y1)) = match self.bounds() {
+            Some(b) => b,
+            None => {
+                c.text(w / 2.0, h / 2.0, "no data", 14.0, TEXT, Anchor::Middle);
+                return;
+            }
+        };
+        let sx = |x: f64| left + ((x - x0) / (x1 - x0)) as f32 * (right - left);
+        let sy = |y: f64| bottom - ((y - y0) / (y1 - y0)) as f32 * (bottom - top);
+
+        // 网格与刻度
+        for v in nice_ticks(y0, y1, 6) {
+            let y = sy(v);
+            c.line(&[(left, y), (right, y)], GRID, 1.0, false);
+            c.text(
+                left - 6.0,
+                y + 4.0,
+                &format_number(v, y1 - y0),
+                11.0,
+                TEXT,
+                Anchor::End,
+            );
+        }
+        let target = ((right - left) / 110.0).max(2.0) as usize;
+        let x_ticks: Vec<(f64, String)> = match self.start {
+            Some(start) => clock_ticks(start, x0, x1, target)
+                .into_iter()
+                .map(|v| {
+                    (
+                        v,
+                        self.wall_at(v)
+                            .map(|t| t.format("%H:%M:%S").to_string())
+                            .unwrap_or_default(),
+                    )
+                })
+                .collect(),
+            None => nice_ticks(x0, x1, target)
+                .into_iter()
+                .map(|v| (v, format_number(v, x1 - x0)))
+                .collect(),
+        };
+        for (v, label) in &x_ticks {
+            let x = sx(*v);
+            c.line(&[(x, top), (x, bottom)], GRID, 1.0, false);
+            c.text(x, bottom + 16.0, label, 11.0, TEXT, Anchor::Middle);
+        }
+        c.text(
+            (left + right) / 2.0,
+            h - 10.0,
+            &self.x_label,
+            12.0,
+            TEXT,
+            Anchor::Middle,
+        );
+        c.text(
+            left - 6.0,
+            top - 6.0,
+            &self.y_label,
+            12.0,
+            TEXT,
+            Anchor::End,
+        );
+
+        // 标记
+        for m in self.markers.iter().filter(|m| m.x >= x0 && m.x <= x1) {
+            let x = sx(m.x);
+            c.line(&[(x, top), (x, bottom)], m.color, 1.0, true);
+            c.text(x + 3.0, top + 12.0, &m.label, 10.0, m.color, Anchor::Start);
+        }
+
+        // 曲线：按断线拆段，裁到绘图区内
+        let rect = (left, top, right, bottom);
+        for s in &self.series {
+            for run in s.points.split(|p| !p[1].is_finite()) {
+                let screen: Vec<(f32, f32)> = run.iter().map(|p| (sx(p[0]), sy(p[1]))).collect();
+                for part in clip_polyline(&screen, rect) {
+                    c.line(&part, s.color, 2.0, false);
+                }
+            }
+        }
+
+        c.line(
+            &[
+                (left, top),
+                (right, top),
+                (right, bottom),
+                (left, bottom),
+                (left, top),
+            ],
+            FRAME,
+            1.0,
+            false,
+        );
+
+        // 图例（右上角，绘图区内）
+        let mut y = top + 16.0;
+        for s in &self.series {
+            c.line(
+                &[(right - 130.0, y - 4.0), (right - 110.0, y - 4.0)],
+                s.color,
+                2.0,
+                false,
+            );
+            c.text(right - 104.0, y, &s.name, 11.0, TEXT, Anchor::Start);
+            y += 16.0;
+        }
+    }
+}
+
+// ----------------- 刻度 -----------------
+
+// 1 / 2 / 5 x 10^k 的整齐刻度
+fn nice_ticks(min: f64, max: f64, target: usize) -> Vec<f64> {
+    let span = max - min;
+    if span <= 0.0 || !span.is_finite() {
+        return vec![min];
+    }
+    let raw = span / target.max(1) as f64;
+    let mag = 10f64.powf(raw.log10().floor());
+    let step = match raw / mag {
+        n if n <= 1.0 => 1.0,
+        n if n <= 2.0 => 2.0,
+        n if n <= 5.0 => 5.0,
+        _ => 10.0,
+    } * mag;
+    let first = (min / step).ceil() as i64;
+    let last = (max / step).floor() as i64;
+    (first..=last).map(|k| k as f64 * step).collect()
+}
+
+// 按本地时间整秒 / 整分对齐的刻度；返回的是 x 值
+// 一天一个刻度还嫌多时退回 1 / 2 / 5 刻度，刻度数始终在 target 附近
+fn clock_ticks(start: NaiveDateTime, x0: f64, x1: f64, target: usize) -> Vec<f64> {
+    let offset =
+        start.time().num_seconds_from_midnight() as f64 + start.time().nanosecond() as f64 / 1e9;
+    let span = x1 - x0;
+    let step = match CLOCK_STEPS_S
+        .iter()
+        .copied()
+        .find(|s| span / s <= target as f64)
+    {
+        Some(step) => step,
+        None => return nice_ticks(x0, x1, target),
+    };
+    let first = ((x0 + offset) / step).ceil() as i64;
+    let last = ((x1 + offset) / step).floor() as i64;
+    (first..=last).map(|k| k as f64 * step - offset).collect()
+}
+
+// 小数位数随可见范围变化
+fn format_number(v: f64, span: f64) -> String {
+    let decimals = if span >= 10.0 {
+        0
+    } else {
+        (1.0 - span.log10().floor()).clamp(0.0, 6.0) as usize
+    };
+    format!("{:.*}", decimals, v)
+}
+
+// 折线裁到矩形内（逐段 Liang-Barsky），可能拆成几段
+fn clip_polyline(points: &[(f32, f32)], rect: (f32, f32, f32, f32)) -> Vec<Vec<(f32, f32)>> {
+    let mut parts: Vec<Vec<(f32, f32)>> = Vec::new();
+    let mut current: Vec<(f32, f32)> = Vec::new();
+    if points.len() == 1 {
+        let (x, y) = points[0];
+        if x >= rect.0 && x <= rect.2 && y >= rect.1 && y <= rect.3 {
+            parts.push(vec![(x, y), (x + 1.0, y)]);
+        }
+        return parts;
+    }
+    for w in points.windows(2) {
+        match clip_segment(w[0], w[1], rect) {
+            Some((a, b)) => {
+                if current.last() != Some(&a) {
+                    if current.len() >= 2 {
+                        parts.push(std::mem::take(&mut current));
+                    }
+                    current.clear();
+                    current.push(a);
+                }
+                current.push(b);
+            }
+            None => {
+                if current.len() >= 2 {
+                    parts.push(std::mem::take(&mut current));
+                }
+                current.clear();
+            }
+        }
+    }
+    if current.len() >= 2 {
+        parts.push(current);
+    }
+    parts
+}
+
+fn clip_segment(
+    a: (f32, f32),
+    b: (f32, f32),
+    (left, top, right, bottom): (f32, f32, f32, f32),
+) -> Option<((f32, f32), (f32, f32))> {
+    // NaN 端点（断线）不画，折线在这里断开
+    if ![a.0, a.1, b.0, b.1].iter().all(|v| v.is_finite()) {
+        return None;
+    }
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for (p, q) in [
+        (-dx, a.0 - left),
+        (dx, right - a.0),
+        (-dy, a.1 - top),
+        (dy, bottom - a.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let r = q / p;
+            if p < 0.0 {
+                t0 = t0.max(r);
+            } else {
+                t1 = t1.min(r);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let at = |t: f32| (a.0 + t * dx, a.1 + t * dy);
+    Some((at(t0), at(t1)))
+}
+
+// ----------------- 画布 -----------------
+
+#[derive(Clone, Copy)]
+enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+// PNG 和 SVG 共用的绘图操作；text 的 y 为基线
+trait Canvas {
+    fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [u8; 3]);
+    fn line(&mut self, points: &[(f32, f32)], color: [u8; 3], width: f32, dashed: bool);
+    fn text(&mut self, x: f32, y: f32, text: &str, size: f32, color: [u8; 3], anchor: Anchor);
+}
+
+struct SvgCanvas {
+    out: String,
+}
+
+impl SvgCanvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            out: format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+                w = width,
+                h = height
+            ),
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("</svg>\n");
+        self.out
+    }
+}
+
+fn svg_color(c: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+
+fn svg_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Canvas for SvgCanvas {
+    fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [u8; 3]) {
+        let _ = writeln!(
+            self.out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            x,
+            y,
+            w,
+            h,
+            svg_color(color)
+        );
+    }
+
+    fn line(&mut self, points: &[(f32, f32)], color: [u8; 3], width: f32, dashed: bool) {
+        let coords: Vec<String> = points
+            .iter()
+            .map(|(x, y)| format!("{:.2},{:.2}", x, y))
+            .collect();
+        let dash = if dashed {
+            " stroke-dasharray=\"6 4\""
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            self.out,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"{}/>",
+            coords.join(" "),
+            svg_color(color),
+            width,
+            dash
+        );
+    }
+
+    fn text(&mut self, x: f32, y: f32, text: &str, size: f32, color: [u8; 3], anchor: Anchor) {
+        let anchor = match anchor {
+            Anchor::Start => "start",
+            Anchor::Middle => "middle",
+            Anchor::End => "end",
+        };
+        let _ = writeln!(
+            self.out,
+            "<text x=\"{:.2}\" y=\"{:.2}\" font-family=\"sans-serif\" font-size=\"{}\" fill=\"{}\" text-anchor=\"{}\">{}</text>",
+            x,
+            y,
+            size,
+            svg_color(color),
+            anchor,
+            svg_escape(text)
+        );
+    }
+}
+
+struct PngCanvas<'f> {
+    pixmap: Pixmap,
+    font: FontRef<'f>,
+}
+
+fn paint(color: [u8; 3]) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color[0], color[1], color[2], 255);
+    paint.anti_alias = true;
+    paint
+}
+
+impl Canvas for PngCanvas<'_> {
+    fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [u8; 3]) {
+        if let Some(r) = Rect::from_xywh(x, y, w, h) {
+            self.pixmap
+                .fill_rect(r, &paint(color), Transform::identity(), None);
+        }
+    }
+
+    fn line(&mut self, points: &[(f32, f32)], color: [u8; 3], width: f32, dashed: bool) {
+        let mut pb = PathBuilder::new();
+        for (i, (x, y)) in points.iter().enumerate() {
+            if i == 0 {
+                pb.move_to(*x, *y);
+            } else {
+                pb.line_to(*x, *y);
+            }
+        }
+        let path = match pb.finish() {
+            Some(p) => p,
+            None => return,
+        };
+        let stroke = Stroke {
+            width,
+            dash: if dashed {
+                StrokeDash::new(vec![6.0, 4.0], 0.0)
+            } else {
+                None
+            },
+            ..Stroke::default()
+        };
+        self.pixmap
+            .stroke_path(&path, &paint(color), &stroke, Transform::identity(), None);
+    }
+
+    // 按覆盖率把字形混合到（不透明的）背景上；字体里没有的字（如中文）跳过
+    fn text(&mut self, x: f32, y: f32, text: &str, size: f32, color: [u8; 3], anchor: Anchor) {
+        let scale = PxScale::from(size * 1.3);
+        let scaled = self.font.as_scaled(scale);
+        let width: f32 = text
+            .chars()
+            .map(|ch| scaled.h_advance(self.font.glyph_id(ch)))
+            .sum();
+        let mut caret = match anchor {
+            Anchor::Start => x,
+            Anchor::Middle => x - width / 2.0,
+            Anchor::End => x - width,
+        };
+
+        let (pw, ph) = (self.pixmap.width() as i32, self.pixmap.height() as i32);
+        let pixels = self.pixmap.pixels_mut();
+        for ch in text.chars() {
+            let id = self.font.glyph_id(ch);
+            let advance = scaled.h_advance(id);
+            if id.0 != 0 {
+                let glyph = id.with_scale_and_position(scale, point(caret, y));
+                if let Some(outlined) = self.font.outline_glyph(glyph) {
+                    let b = outlined.px_bounds();
+                    outlined.draw(|gx, gy, coverage| {
+                        let px = b.min.x as i32 + gx as i32;
+                        let py = b.min.y as i32 + gy as i32;
+                        if px < 0 || py < 0 || px >= pw || py >= ph {
+                            return;
+                        }
+                        let p = &mut pixels[(py * pw + px) as usize];
+                        let a = coverage.clamp(0.0, 1.0);
+                        let mix = |bg: u8, fg: u8| (bg as f32 * (1.0 - a) + fg as f32 * a) as u8;
+                        if let Some(c) = PremultipliedColorU8::from_rgba(
+                            mix(p.red(), color[0]),
+                            mix(p.green(), color[1]),
+                            mix(p.blue(), color[2]),
+                            255,
+                        ) {
+                            *p = c;
+                        }
+                    });
+                }
+            }
+            caret += advance;
+        }
+    }
+}
+
+// ----------------- 命令行 -----------------
+
+pub const PLOT_USAGE: &str = "\
+用法: dhjc_rust_gui plot [--date YYYY-MM-DD] [--out <目录>] [--size <宽>x<高>] [--format png,svg,csv] <日志文件>...
+
+把文本日志里的总数曲线（带阶段 / 汇总标记和注释）渲染成图片，不需要图形界面。
+每个文件单独出图，日期取自各自的文件名（--date 对所有文件生效）。
+默认输出 PNG 到当前目录，尺寸 1600x900（每边 16 ~ 4096），文件名与日志文件相同（去掉扩展名）。";
+
+const STAGE_COLOR: [u8; 3] = [60, 160, 90];
+const SUMMARY_COLOR: [u8; 3] = [140, 90, 200];
+const TIMEOUT_COLOR: [u8; 3] = [230, 120, 40];
+const NOTE_COLOR: [u8; 3] = [190, 160, 20];
+const TOTAL_COLOR: [u8; 3] = [40, 110, 220];
+
+/// plot 子命令；返回进程退出码
+pub fn run_cli(args: &[String]) -> i32 {
+    let mut date = None;
+    let mut out_dir = PathBuf::from(".");
+    let mut size = (1600u32, 900u32);
+    let mut formats = ExportFormats {
+        png: true,
+        svg: false,
+        csv: false,
+    };
+    let mut files = Vec::new();
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().map(String::as_str).unwrap_or("");
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", PLOT_USAGE);
+                return 0;
+            }
+            "--date" => {
+                let v = value();
+                match NaiveDate::parse_from_str(v, "%Y-%m-%d") {
+                    Ok(d) => date = Some(d),
+                    Err(_) => return usage_error(&format!("无效的日期: {}", v)),
+                }
+            }
+            "--out" => out_dir = PathBuf::from(value()),
+            "--size" => {
+                let v = value();
+                match parse_size(v) {
+                    Some(s) => size = s,
+                    None => return usage_error(&format!("无效的尺寸: {}", v)),
+                }
+            }
+            "--format" => {
+                let v = value();
+                match parse_formats(v) {
+                    Some(f) => formats = f,
+                    None => return usage_error(&format!("无效的格式: {}", v)),
+                }
+            }
+            other if other.starts_with("--") => {
+                return usage_error(&format!("未知参数: {}", other));
+            }
+            path => files.push(PathBuf::from(path)),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", PLOT_USAGE);
+        return 2;
+    }
+
+    // 先确定每个文件的日期，有问题时一个都不写
+    let mut jobs = Vec::new();
+    for path in files {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let date = match date.or_else(|| LogFileName::parse(&name).map(|n| n.date)) {
+            Some(d) => d,
+            None => {
+                return usage_error(&format!("无法从文件名 {} 得到日期，请用 --date 指定", name))
+            }
+        };
+        // 2025-12-12.1.txt.gz -> 2025-12-12.1
+        let base = name.strip_suffix(".gz").unwrap_or(&name);
+        let stem = base
+            .rsplit_once('.')
+            .map(|(s, _)| s)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("plot")
+            .to_string();
+        jobs.push((path, date, stem));
+    }
+
+    let mut code = 0;
+    for (path, date, stem) in jobs {
+        if let Err(e) = plot_file(path, date, &stem, &out_dir, formats, size) {
+            eprintln!("[PLOT] {}", e);
+            code = 1;
+        }
+    }
+    code
+}
+
+// 一个日志文件 -> <stem>.png / .svg / .csv
+fn plot_file(
+    path: PathBuf,
+    date: NaiveDate,
+    stem: &str,
+    out_dir: &Path,
+    formats: ExportFormats,
+    size: (u32, u32),
+) -> Result<(), String> {
+    let item = HistoryItem {
+        date,
+        label: stem.to_string(),
+        session: false,
+        files: vec![path],
+    };
+    let data = load_history(&item)?;
+
+    // 阶段 / 汇总的时间只有在横轴为秒时才能换算成 x
+    let mut markers = Vec::new();
+    if let (true, Some(start)) = (data.curve_in_seconds, data.start) {
+        let x_of = |t: NaiveDateTime| (t - start).num_milliseconds() as f64 / 1000.0;
+        for s in &data.stages {
+            if let (Some(at), Some(id)) = (s.at, s.value.stage_id) {
+                markers.push(ExportMarker {
+                    x: x_of(at),
+                    label: format!("S{}", id),
+                    color: STAGE_COLOR,
+                });
+            }
+        }
+        for s in &data.summaries {
+            if let Some(at) = s.at {
+                let timeout = s.value.status.as_deref().unwrap_or("").contains("Timeout");
+                markers.push(ExportMarker {
+                    x: x_of(at),
+                    label: if timeout { "Timeout" } else { "Summary" }.to_string(),
+                    color: if timeout {
+                        TIMEOUT_COLOR
+                    } else {
+                        SUMMARY_COLOR
+                    },
+                });
+            }
+        }
+    }
+    for (x, text) in &data.notes {
+        markers.push(ExportMarker {
+            x: *x,
+            label: text.clone(),
+            color: NOTE_COLOR,
+        });
+    }
+
+    let export = PlotExport {
+        title: format!("DHJC Total - {}", stem),
+        y_label: "Total".to_string(),
+        start: data.start.filter(|_| data.curve_in_seconds),
+        x_label: if data.curve_in_seconds {
+            "Time"
+        } else {
+            "Line"
+        }
+        .to_string(),
+        series: vec![ExportSeries {
+            name: "Total".to_string(),
+            color: TOTAL_COLOR,
+            points: data.curve,
+        }],
+        markers,
+        x_range: None,
+    };
+    for p in export.save(out_dir, stem, formats, size)? {
+        eprintln!("[PLOT] {}", p.to_string_lossy());
+    }
+    Ok(())
+}
+
+fn usage_error(msg: &str) -> i32 {
+    eprintln!("[PLOT] {}\n\n{}", msg, PLOT_USAGE);
+    2
+}
+
+/// "1600x900"
+pub fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.split_once(['x', 'X'])?;
+    let (w, h) = (w.trim().parse().ok()?, h.trim().parse().ok()?);
+    let valid = 16..=MAX_IMAGE_SIDE;
+    (valid.contains(&w) && valid.contains(&h)).then_some((w, h))
+}
+
+/// "png,svg,csv" 的任意组合
+fn parse_formats(s: &str) -> Option<ExportFormats> {
+    let mut f = ExportFormats {
+        png: false,
+        svg: false,
+        csv: false,
+    };
+    for part in s.split(',').map(str::trim) {
+        match part {
+            "png" => f.png = true,
+            "svg" => f.svg = true,
+            "csv" => f.csv = true,
+            _ => return None,
+        }
+    }
+    Some(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECT: (f32, f32, f32, f32) = (0.0, 0.0, 100.0, 100.0);
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").unwrap()
+    }
+
+    fn export(points: Vec<[f64; 2]>) -> PlotExport {
+        PlotExport {
+            title: "Total".to_string(),
+            y_label: "pulses".to_string(),
+            start: Some(at("2025-12-12 13:00:00")),
+            x_label: "time".to_string(),
+            series: vec![ExportSeries {
+                name: "Total".to_string(),
+                color: TOTAL_COLOR,
+                points,
+            }],
+            markers: vec![ExportMarker {
+                x: 1.0,
+                label: "Stage 1".to_string(),
+                color: STAGE_COLOR,
+            }],
+            x_range: None,
+        }
+    }
+
+    #[test]
+    fn clip_segment_crossing_and_outside() {
+        // 横穿矩形：裁到左右边
+        assert_eq!(
+            clip_segment((-50.0, 50.0), (150.0, 50.0), RECT),
+            Some(((0.0, 50.0), (100.0, 50.0)))
+        );
+        // 从内部穿出右下角所在的边
+        assert_eq!(
+            clip_segment((50.0, 50.0), (150.0, 150.0), RECT),
+            Some(((50.0, 50.0), (100.0, 100.0)))
+        );
+        // 完全在外（平行于边 / 斜着擦过角外）
+        assert_eq!(clip_segment((-10.0, -5.0), (-10.0, 50.0), RECT), None);
+        assert_eq!(clip_segment((110.0, -20.0), (130.0, 10.0), RECT), None);
+        assert_eq!(clip_segment((f32::NAN, 10.0), (20.0, 20.0), RECT), None);
+    }
+
+    #[test]
+    fn clip_polyline_splits_at_exits_and_nan() {
+        // 中间一段跑到矩形外：拆成两段
+        let parts = clip_polyline(
+            &[(10.0, 10.0), (50.0, 50.0), (50.0, 150.0), (60.0, 50.0), (90.0, 90.0)],
+            RECT,
+        );
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0], [(10.0, 10.0), (50.0, 50.0), (50.0, 100.0)]);
+        assert_eq!(parts[1], [(55.0, 100.0), (60.0, 50.0), (90.0, 90.0)]);
+
+        // NaN 点：前后各成一段，不画穿过它的线
+        let parts = clip_polyline(
+            &[(10.0, 10.0), (20.0, 20.0), (f32::NAN, f32::NAN), (30.0, 30.0), (40.0, 40.0)],
+            RECT,
+        );
+        assert_eq!(parts, [vec![(10.0, 10.0), (20.0, 20.0)], vec![(30.0, 30.0), (40.0, 40.0)]]);
+
+        assert!(clip_polyline(&[(-10.0, -10.0), (-20.0, 5.0)], RECT).is_empty());
+    }
+
+    #[test]
+    fn clock_ticks_align_to_whole_minutes() {
+        let start = at("2025-12-12 10:00:30.500");
+        let ticks = clock_ticks(start, 0.0, 600.0, 10);
+        assert_eq!(ticks.len(), 10);
+        assert_eq!(ticks[0], 29.5);
+        let export = PlotExport {
+            start: Some(start),
+            ..export(Vec::new())
+        };
+        for x in &ticks {
+            let t = export.wall_at(*x).unwrap();
+            assert_eq!((t.second(), t.nanosecond()), (0, 0), "{}", t);
+        }
+        assert_eq!(
+            export.wall_at(ticks[9]).unwrap(),
+            at("2025-12-12 10:10:00")
+        );
+    }
+
+    #[test]
+    fn size_and_format_arguments() {
+        assert_eq!(parse_size("1600x900"), Some((1600, 900)));
+        assert_eq!(parse_size(" 800 X 600 "), Some((800, 600)));
+        assert_eq!(parse_size("1600"), None);
+        assert_eq!(parse_size("1600x"), None);
+        assert_eq!(parse_size("-1x900"), None);
+        assert_eq!(parse_size("8x900"), None);
+        assert_eq!(parse_size("5000x900"), None);
+
+        assert_eq!(
+            parse_formats("png, csv"),
+            Some(ExportFormats {
+                png: true,
+                svg: false,
+                csv: true
+            })
+        );
+        assert_eq!(parse_formats("png,jpg"), None);
+        assert_eq!(parse_formats(""), None);
+    }
+
+    #[test]
+    fn csv_respects_range_and_skips_gaps() {
+        let mut e = export(vec![
+            [0.0, 1.0],
+            [1.5, 2.0],
+            [2.0, f64::NAN],
+            [3.0, 2.5],
+            [10.0, 4.0],
+        ]);
+        e.x_range = Some((1.0, 5.0));
+        let csv = e.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "timestamp,elapsed_s,series,value",
+                "2025-12-12 13:00:01.500,1.500,Total,2",
+                "2025-12-12 13:00:03.000,3.000,Total,2.500",
+            ]
+        );
+    }
+
+    #[test]
+    fn render_png_smoke() {
+        let e = export(vec![[0.0, 0.0], [1.0, 5.0], [2.0, f64::NAN], [3.0, 8.0]]);
+        let bytes = e.render_png(320, 200).unwrap();
+        let decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (320, 200));
+
+        let svg = e.render_svg(320, 200);
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+    }
+}
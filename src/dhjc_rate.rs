@@ -0,0 +1,292 @@
+// src/dhjc_rate.rs
+//
+// 脉冲速率估计：
+// - 输入是 Total 的增量（时间 + 当前总数），不再用 Live 里的 Wait（那是距上个脉冲的时间，不是间隔）
+// - window：滑动时间窗口内的脉冲数 / 窗口长度，停止放电后随窗口滑出逐渐归零
+// - interval：最近两次增量之间的瞬时间隔；久无脉冲时按已等待的时间往下压
+// - 可选 EMA 平滑（按时间常数，帧率不同结果一致）
+// 时间由调用方传入（秒），回放和离线分析也能用
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateMode {
+    Window,
+    Interval,
+}
+
+impl RateMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "window" => Some(Self::Window),
+            "interval" => Some(Self::Interval),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateSettings {
+    pub mode: RateMode,
+    /// 滑动窗口长度（秒）
+    pub window_s: f64,
+    /// EMA 时间常数（秒），0 表示不平滑
+    pub smoothing_s: f64,
+}
+
+impl Default for RateSettings {
+    fn default() -> Self {
+        Self {
+            mode: RateMode::Window,
+            window_s: 5.0,
+            smoothing_s: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateEstimator {
+    settings: RateSettings,
+    // (时间, 总数)，按时间递增；窗口模式下保留一个窗口起点之前的样本作基准
+    samples: VecDeque<(f64, i64)>,
+    smoothed: Option<f64>,
+    last_update: Option<f64>,
+}
+
+impl RateEstimator {
+    pub fn new(settings: RateSettings) -> Self {
+        Self {
+            settings,
+            samples: VecDeque::new(),
+            smoothed: None,
+            last_update: None,
+        }
+    }
+
+    pub fn settings(&self) -> RateSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: RateSettings) {
+        if settings != self.settings {
+            self.settings = settings;
+            self.smoothed = None;
+            self.last_update = None;
+        }
+    }
+
+    /// 新 session / 复位：清空历史
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.smoothed = None;
+        self.last_update = None;
+    }
+
+    /// 记录一次 Total 变化；总数回退时视为新 session
+    pub fn record(&mut self, t: f64, total: i64) {
+        if let Some(&(last_t, last_total)) = self.samples.back() {
+            if total < last_total || t < last_t {
+                self.reset();
+            } else if total == last_total {
+                return;
+            }
+        }
+        self.samples.push_back((t, total));
+        self.prune(t);
+    }
+
+    /// 按当前时间更新（每帧调用一次），返回平滑后的速率（脉冲/秒）
+    pub fn update(&mut self, now: f64) -> f64 {
+        self.prune(now);
+        let raw = self.raw_rate(now);
+
+        let tau = self.settings.smoothing_s;
+        let value = match (self.smoothed, self.last_update) {
+            (Some(prev), Some(last)) if tau > 0.0 => {
+                let dt = (now - last).max(0.0);
+                let alpha = 1.0 - (-dt / tau).exp();
+                prev + alpha * (raw - prev)
+            }
+            _ => raw,
+        };
+        self.smoothed = Some(value);
+        self.last_update = Some(now);
+        value
+    }
+
+    /// 上次 update 的结果
+    pub fn value(&self) -> f64 {
+        self.smoothed.unwrap_or(0.0)
+    }
+
+    /// 未平滑的速率
+    pub fn raw_rate(&self, now: f64) -> f64 {
+        match self.settings.mode {
+            RateMode::Window => self.window_rate(now),
+            RateMode::Interval => self.interval_rate(now),
+        }
+    }
+
+    // 窗口内的增量 / 覆盖的时长；历史不足一个窗口时按第一个样本以来的时长
+    fn window_rate(&self, now: f64) -> f64 {
+        let (last_t, last_total) = match self.samples.back() {
+            Some(&s) => s,
+            None => return 0.0,
+        };
+        let start = now - self.settings.window_s;
+        let (base_t, base_total) = self
+            .samples
+            .iter()
+            .rev()
+            .find(|(t, _)| *t <= start)
+            .copied()
+            .unwrap_or(self.samples[0]);
+
+        let pulses = if last_t <= start {
+            0
+        } else {
+            last_total - base_total
+        };
+        let span = now - base_t.max(start);
+        if pulses <= 0 || span <= 0.0 {
+            0.0
+        } else {
+            pulses as f64 / span
+        }
+    }
+
+    // 最近一次增量的平均间隔；已经等得更久时用等待时间
+    fn interval_rate(&self, now: f64) -> f64 {
+        let n = self.samples.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let (t1, c1) = self.samples[n - 2];
+        let (t2, c2) = self.samples[n - 1];
+        let interval = (t2 - t1) / (c2 - c1) as f64;
+        let interval = interval.max(now - t2);
+        if interval > 0.0 {
+            1.0 / interval
+        } else {
+            0.0
+        }
+    }
+
+    fn prune(&mut self, now: f64) {
+        let keep_from = match self.settings.mode {
+            RateMode::Window => now - self.settings.window_s,
+            // 瞬时模式只需要最后两个样本
+            RateMode::Interval => f64::INFINITY,
+        };
+        while self.samples.len() > 2 && self.samples[1].0 <= keep_from {
+            self.samples.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimator(mode: RateMode, window_s: f64, smoothing_s: f64) -> RateEstimator {
+        RateEstimator::new(RateSettings {
+            mode,
+            window_s,
+            smoothing_s,
+        })
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    // 10 Hz：每 0.1 s 一个脉冲，t 从 from 到 to（含）
+    fn pulse_train(r: &mut RateEstimator, from: i64, to: i64) {
+        for i in from..=to {
+            r.record(i as f64 * 0.1, i);
+        }
+    }
+
+    #[test]
+    fn window_rate_steady_train() {
+        let mut r = estimator(RateMode::Window, 5.0, 0.0);
+        pulse_train(&mut r, 0, 200);
+        assert!(close(r.update(20.0), 10.0));
+    }
+
+    #[test]
+    fn window_rate_partial_window() {
+        // 刚开始不足一个窗口：按第一个样本以来的时长计算
+        let mut r = estimator(RateMode::Window, 5.0, 0.0);
+        pulse_train(&mut r, 0, 20);
+        assert!(close(r.update(2.0), 10.0));
+    }
+
+    #[test]
+    fn window_rate_decays_after_pulses_stop() {
+        let mut r = estimator(RateMode::Window, 5.0, 0.0);
+        pulse_train(&mut r, 0, 200);
+        // 停止 2 s 后窗口内只剩 3 s 的脉冲
+        assert!(close(r.update(22.0), 6.0));
+        // 整个窗口内都没有脉冲
+        assert_eq!(r.update(25.5), 0.0);
+        assert_eq!(r.update(60.0), 0.0);
+    }
+
+    #[test]
+    fn interval_rate_spreads_multi_pulse_increments() {
+        // 一次加了 5 个：间隔按 0.5 s / 5 计
+        let mut r = estimator(RateMode::Interval, 5.0, 0.0);
+        r.record(1.0, 10);
+        r.record(1.5, 15);
+        assert!(close(r.update(1.5), 10.0));
+    }
+
+    #[test]
+    fn interval_rate_clamps_to_time_since_last_pulse() {
+        // 上一个脉冲之后已经过去 1 s：不再是 10 Hz，而是至多 1 Hz
+        let mut r = estimator(RateMode::Interval, 5.0, 0.0);
+        r.record(1.0, 10);
+        r.record(1.5, 15);
+        assert!(close(r.update(2.5), 1.0));
+        assert!(close(r.update(5.5), 0.25));
+    }
+
+    #[test]
+    fn ema_time_constant_response() {
+        let mut r = estimator(RateMode::Interval, 5.0, 1.0);
+        r.record(0.0, 0);
+        assert_eq!(r.update(0.0), 0.0);
+
+        // 原始速率阶跃到 10 Hz，经过一个时间常数达到 1 - 1/e
+        r.record(0.9, 9);
+        r.record(1.0, 10);
+        let expected = 10.0 * (1.0 - (-1.0f64).exp());
+        assert!(close(r.update(1.0), expected));
+        assert!(close(r.value(), expected));
+
+        // 之后每步 dt = 0.1 s，持续 10 个时间常数后基本收敛
+        for i in 11..=110 {
+            r.record(i as f64 * 0.1, i);
+            r.update(i as f64 * 0.1);
+        }
+        assert!((r.value() - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn record_resets_when_total_goes_back() {
+        let mut r = estimator(RateMode::Window, 5.0, 0.0);
+        pulse_train(&mut r, 0, 30);
+        assert!(r.update(3.0) > 0.0);
+
+        // 总数回退（新 session）：之前的样本作废，不出现负速率
+        r.record(3.1, 2);
+        assert_eq!(r.update(3.1), 0.0);
+        r.record(3.2, 3);
+        assert!(close(r.update(3.2), 10.0));
+
+        // 时间回退同样重新开始
+        r.record(1.0, 4);
+        assert_eq!(r.update(1.0), 0.0);
+    }
+}
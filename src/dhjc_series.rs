@@ -0,0 +1,191 @@
+// src/dhjc_series.rs
+//
+// 曲线数据的多分辨率存储（长时间运行不丢早期历史）：
+// - 最近 recent_cap 个点保持原始分辨率
+// - 更早的点按 min/max 抽稀后放进 archive（每段保留最小和最大值，尖峰不会被抹掉）；
+//   archive 超过上限时再整体抽稀一半，内存有上界，越老的数据越粗
+// - 显示时按当前可见范围取点，再抽稀到给定点数以内
+// y 为 NaN 的点是断线标记：连续的只留一个；分段可以跨过断线，含断线的段留一个 NaN，
+// 频繁断线时也照样能抽稀
+
+/// 移入 archive 时的抽稀倍数
+const ARCHIVE_FACTOR: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct PlotSeries {
+    /// 更早的数据（已抽稀），按 x 递增
+    archive: Vec<[f64; 2]>,
+    /// 最近的原始数据，按 x 递增
+    recent: Vec<[f64; 2]>,
+    recent_cap: usize,
+    archive_cap: usize,
+}
+
+impl PlotSeries {
+    pub fn new(recent_cap: usize, archive_cap: usize) -> Self {
+        Self {
+            archive: Vec::new(),
+            recent: Vec::new(),
+            recent_cap: recent_cap.max(2),
+            archive_cap: archive_cap.max(2),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.archive.clear();
+        self.recent.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archive.is_empty() && self.recent.is_empty()
+    }
+
+    /// x 须不小于上一个点
+    pub fn push(&mut self, x: f64, y: f64) {
+        self.recent.push([x, y]);
+        if self.recent.len() > self.recent_cap {
+            self.compact();
+        }
+    }
+
+    /// 断线：前后两段不连起来
+    pub fn push_gap(&mut self, x: f64) {
+        let last = self.recent.last().or(self.archive.last());
+        if !last.is_some_and(|p| p[1].is_nan()) {
+            self.push(x, f64::NAN);
+        }
+    }
+
+    /// 显示用：range 内的点（两端各多带一个，线能画到边上），不超过约 max_points 个；
+    /// range 为 None 时取全部
+    pub fn view(&self, range: Option<(f64, f64)>, max_points: usize) -> Vec<[f64; 2]> {
+        let mut points = Vec::new();
+        for part in [&self.archive, &self.recent] {
+            let (lo, hi) = match range {
+                Some((min, max)) => (
+                    part.partition_point(|p| p[0] < min).saturating_sub(1),
+                    (part.partition_point(|p| p[0] <= max) + 1).min(part.len()),
+                ),
+                None => (0, part.len()),
+            };
+            if lo < hi {
+                points.extend_from_slice(&part[lo..hi]);
+            }
+        }
+        decimate(&points, max_points)
+    }
+
+    // 最老的一半原始数据抽稀后移入 archive
+    fn compact(&mut self) {
+        let moved: Vec<[f64; 2]> = self.recent.drain(..self.recent_cap / 2).collect();
+        let target = (moved.len() / ARCHIVE_FACTOR).max(2);
+        self.archive.extend(decimate(&moved, target));
+
+        if self.archive.len() > self.archive_cap {
+            self.archive = decimate(&self.archive, self.archive_cap / 2);
+        }
+    }
+}
+
+/// min/max 抽稀：每段保留 y 最小和最大的点（按 x 顺序），结果约为 target 个点；
+/// 含断线的段另外保留一个 NaN 点（最多为 target 的 1.5 倍），相邻的 NaN 合并为一个
+pub fn decimate(points: &[[f64; 2]], target: usize) -> Vec<[f64; 2]> {
+    if points.len() <= target || target < 2 {
+        return points.to_vec();
+    }
+    // 每段出 2 个点（有断线时 3 个）
+    let chunk = points.len().div_ceil(target / 2);
+    let mut out: Vec<[f64; 2]> = Vec::with_capacity(target + target / 2 + 1);
+
+    for seg in points.chunks(chunk) {
+        let (mut lo, mut hi, mut gap) = (None, None, None);
+        for (i, p) in seg.iter().enumerate() {
+            if p[1].is_nan() {
+                gap.get_or_insert(i);
+                continue;
+            }
+            if lo.is_none_or(|l: usize| p[1] < seg[l][1]) {
+                lo = Some(i);
+            }
+            if hi.is_none_or(|h: usize| p[1] > seg[h][1]) {
+                hi = Some(i);
+            }
+        }
+        let mut picks = [lo, hi, gap];
+        picks.sort();
+        let mut prev = None;
+        for i in picks.into_iter().flatten() {
+            if prev == Some(i) {
+                continue;
+            }
+            prev = Some(i);
+            let p = seg[i];
+            if p[1].is_nan() && out.last().is_some_and(|q| q[1].is_nan()) {
+                continue;
+            }
+            out.push(p);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 点和断线交替：p, NaN, p, NaN ...
+    fn sparse(n: usize) -> Vec<[f64; 2]> {
+        (0..n)
+            .map(|i| {
+                let y = if i % 2 == 0 { (i % 7) as f64 } else { f64::NAN };
+                [i as f64, y]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decimate_shrinks_gap_heavy_series() {
+        let points = sparse(10_000);
+        let out = decimate(&points, 100);
+        assert!(out.len() <= 150, "{} points", out.len());
+        // 断线仍在，但没有连续的 NaN
+        assert!(out.iter().any(|p| p[1].is_nan()));
+        assert!(out
+            .windows(2)
+            .all(|w| !(w[0][1].is_nan() && w[1][1].is_nan())));
+        // x 保持递增，极值保留
+        assert!(out.windows(2).all(|w| w[0][0] < w[1][0]));
+        assert_eq!(out.iter().map(|p| p[1]).fold(f64::MIN, f64::max), 6.0);
+        assert_eq!(out.iter().map(|p| p[1]).fold(f64::MAX, f64::min), 0.0);
+    }
+
+    #[test]
+    fn archive_stays_bounded_with_frequent_gaps() {
+        let mut series = PlotSeries::new(200, 400);
+        for i in 0..200_000 {
+            let x = i as f64;
+            if i % 2 == 0 {
+                series.push(x, (i % 13) as f64);
+            } else {
+                series.push_gap(x);
+            }
+        }
+        assert!(
+            series.archive.len() <= 400,
+            "{} points",
+            series.archive.len()
+        );
+        assert!(series.recent.len() <= 200);
+    }
+
+    #[test]
+    fn push_gap_collapses_repeated_gaps() {
+        let mut series = PlotSeries::new(100, 100);
+        series.push(0.0, 1.0);
+        series.push_gap(1.0);
+        series.push_gap(2.0);
+        series.push(3.0, 2.0);
+        let view = series.view(None, 100);
+        assert_eq!(view.iter().filter(|p| p[1].is_nan()).count(), 1);
+    }
+}
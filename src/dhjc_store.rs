@@ -0,0 +1,483 @@
+// src/dhjc_store.rs
+//
+// SQLite 存储（logs/dhjc.sqlite3）：
+// - sessions：每次复位 / 启动一行，收到 [TOTAL SUMMARY] 后补上汇总
+// - stages：每个 [STAGE REPORT] 一行，附主机侧的间隔统计
+// - live_totals：Live 总数，每秒最多一行
+// - annotations：用户在界面上加的注释，挂在当时的 session 上
+// 写库在后台线程进行，GUI 只负责把事件丢进通道
+// 表结构版本记在 PRAGMA user_version，启动时按 MIGRATIONS 逐级升级
+
+use crate::dhjc_clock::Clock;
+use crate::dhjc_core::{CoreEvent, CoreEventKind, StageReport, TotalSummary};
+use chrono::{DateTime, Local, SecondsFormat};
+use rusqlite::{params, Connection, Transaction};
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+pub const DB_FILE_NAME: &str = "dhjc.sqlite3";
+
+/// Live 总数的最小记录间隔
+const LIVE_SAMPLE_INTERVAL_MS: i64 = 1000;
+
+/// 第 i 项把库从版本 i 升到 i + 1；只能追加，不能改已发布的项
+const MIGRATIONS: &[&str] = &[
+    // v1
+    "CREATE TABLE sessions (
+        id               INTEGER PRIMARY KEY,
+        device           TEXT NOT NULL,
+        started_at       TEXT,
+        ended_at         TEXT,
+        status           TEXT,
+        active_time_s    REAL,
+        total_stages     INTEGER,
+        grand_total      INTEGER,
+        avg_frequency_hz REAL,
+        complete         INTEGER
+    );
+    CREATE TABLE stages (
+        id              INTEGER PRIMARY KEY,
+        session_id      INTEGER NOT NULL REFERENCES sessions(id),
+        reported_at     TEXT NOT NULL,
+        stage_id        INTEGER,
+        status          TEXT,
+        arcs            INTEGER,
+        duration_ms     REAL,
+        min_interval_ms REAL,
+        max_interval_ms REAL,
+        single_pulse    INTEGER NOT NULL,
+        complete        INTEGER NOT NULL
+    );
+    CREATE INDEX stages_reported_at ON stages(reported_at);
+    CREATE INDEX stages_session ON stages(session_id);
+    CREATE TABLE live_totals (
+        id         INTEGER PRIMARY KEY,
+        session_id INTEGER NOT NULL REFERENCES sessions(id),
+        at         TEXT NOT NULL,
+        stage      INTEGER,
+        total      INTEGER NOT NULL
+    );
+    CREATE INDEX live_totals_session ON live_totals(session_id);",
+    // v2：主机侧的 Duration 之和，和 MCU 的 active_time_s 对照
+    "ALTER TABLE sessions ADD COLUMN summed_duration_s REAL;",
+    // v3：主机侧的间隔统计，histogram 为各档计数的 JSON 数组
+    "ALTER TABLE stages ADD COLUMN host_interval_count INTEGER;
+    ALTER TABLE stages ADD COLUMN host_mean_ms REAL;
+    ALTER TABLE stages ADD COLUMN host_std_ms REAL;
+    ALTER TABLE stages ADD COLUMN host_median_ms REAL;
+    ALTER TABLE stages ADD COLUMN host_p95_ms REAL;
+    ALTER TABLE stages ADD COLUMN host_p99_ms REAL;
+    ALTER TABLE stages ADD COLUMN host_min_ms REAL;
+    ALTER TABLE stages ADD COLUMN host_max_ms REAL;
+    ALTER TABLE stages ADD COLUMN host_histogram TEXT;",
+    // v4：用户注释
+    "CREATE TABLE annotations (
+        id         INTEGER PRIMARY KEY,
+        session_id INTEGER NOT NULL REFERENCES sessions(id),
+        at         TEXT NOT NULL,
+        text       TEXT NOT NULL
+    );
+    CREATE INDEX annotations_session ON annotations(session_id);",
+];
+
+enum StoreMsg {
+    Event(DateTime<Local>, CoreEvent),
+    Annotation(DateTime<Local>, String),
+    Shutdown,
+}
+
+/// 后台写库线程的句柄；Drop 时写完剩余事件再退出
+pub struct Store {
+    tx: Sender<StoreMsg>,
+    /// 与日志共用的时钟，行的时间和日志时间戳一致
+    clock: Clock,
+    err_rx: Receiver<String>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Store {
+    pub fn open(base_folder: &str, device_id: &str, clock: Clock) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let (err_tx, err_rx) = mpsc::channel();
+        let path = Path::new(base_folder).join(DB_FILE_NAME);
+        let device_id = device_id.to_string();
+
+        let handle = thread::spawn(move || {
+            let mut writer = match StoreWriter::open(&path, device_id) {
+                Ok(w) => w,
+                Err(e) => {
+                    let _ =
+                        err_tx.send(format!("打开数据库 {} 失败: {}", path.to_string_lossy(), e));
+                    return;
+                }
+            };
+            writer.run(rx, err_tx);
+        });
+
+        Self {
+            tx,
+            clock,
+            err_rx,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn record(&self, event: CoreEvent) {
+        let _ = self.tx.send(StoreMsg::Event(self.clock.now().wall, event));
+    }
+
+    /// 用户注释：记在当前 session 上（session 已结束时记在最近的一个上）
+    pub fn annotate(&self, text: &str) {
+        let _ = self
+            .tx
+            .send(StoreMsg::Annotation(self.clock.now().wall, text.to_string()));
+    }
+
+    /// 后台线程报告的错误（由 GUI 放进 Event Log）
+    pub fn take_errors(&self) -> Vec<String> {
+        self.err_rx.try_iter().collect()
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        let _ = self.tx.send(StoreMsg::Shutdown);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+// ----------------- 后台线程 -----------------
+
+struct StoreWriter {
+    conn: Connection,
+    device_id: String,
+    session_id: Option<i64>,
+    last_live: Option<DateTime<Local>>,
+}
+
+impl StoreWriter {
+    fn open(path: &Path, device_id: String) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn,
+            device_id,
+            session_id: None,
+            last_live: None,
+        })
+    }
+
+    fn run(&mut self, rx: Receiver<StoreMsg>, err_tx: Sender<String>) {
+        // 阻塞等第一条，再把通道里已有的一起放进同一个事务
+        while let Ok(first) = rx.recv() {
+            let mut batch = vec![first];
+            batch.extend(rx.try_iter());
+
+            let shutdown = batch.iter().any(|m| matches!(m, StoreMsg::Shutdown));
+            if let Err(e) = self.write_batch(batch) {
+                let _ = err_tx.send(format!("写入数据库失败: {}", e));
+            }
+            if shutdown {
+                break;
+            }
+        }
+    }
+
+    fn write_batch(&mut self, batch: Vec<StoreMsg>) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        let mut session_id = self.session_id;
+        let mut last_live = self.last_live;
+
+        for msg in batch {
+            let (at, event) = match msg {
+                StoreMsg::Event(at, e) => (at, e),
+                StoreMsg::Annotation(at, text) => {
+                    let ts = at.to_rfc3339_opts(SecondsFormat::Millis, false);
+                    let sid = match session_id {
+                        Some(id) => id,
+                        None => latest_session(&tx, &self.device_id)?.map_or_else(
+                            || ensure_session(&tx, &mut session_id, &self.device_id, &ts),
+                            Ok,
+                        )?,
+                    };
+                    tx.execute(
+                        "INSERT INTO annotations (session_id, at, text) VALUES (?1, ?2, ?3)",
+                        params![sid, ts, text],
+                    )?;
+                    continue;
+                }
+                StoreMsg::Shutdown => continue,
+            };
+            let ts = at.to_rfc3339_opts(SecondsFormat::Millis, false);
+            match &event.kind {
+                CoreEventKind::SystemReset => {
+                    session_id = Some(insert_session(&tx, &self.device_id, &ts)?);
+                }
+                CoreEventKind::Banner(_) => {
+                    if session_id.is_none() {
+                        session_id = Some(insert_session(&tx, &self.device_id, &ts)?);
+                    }
+                }
+                CoreEventKind::Live(l) => {
+                    let due = last_live
+                        .map(|t| (at - t).num_milliseconds() >= LIVE_SAMPLE_INTERVAL_MS)
+                        .unwrap_or(true);
+                    if let (true, Some(total)) = (due, l.total) {
+                        let sid = ensure_session(&tx, &mut session_id, &self.device_id, &ts)?;
+                        tx.execute(
+                            "INSERT INTO live_totals (session_id, at, stage, total)
+                             VALUES (?1, ?2, ?3, ?4)",
+                            params![sid, ts, l.stage, total],
+                        )?;
+                        last_live = Some(at);
+                    }
+                }
+                CoreEventKind::StageReport(r) => {
+                    let sid = ensure_session(&tx, &mut session_id, &self.device_id, &ts)?;
+                    insert_stage(&tx, sid, &ts, r)?;
+                }
+                CoreEventKind::TotalSummary(s) => {
+                    let sid = ensure_session(&tx, &mut session_id, &self.device_id, &ts)?;
+                    update_summary(&tx, sid, &ts, s)?;
+                    session_id = None;
+                    last_live = None;
+                }
+                CoreEventKind::Error(_) | CoreEventKind::ConsistencyWarning(_) => {}
+            }
+        }
+
+        tx.commit()?;
+        self.session_id = session_id;
+        self.last_live = last_live;
+        Ok(())
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |r| r.get(0))
+        .map_err(|e| e.to_string())?;
+    if version as usize > MIGRATIONS.len() {
+        return Err(format!(
+            "数据库版本 {} 比程序支持的 {} 新",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+
+    // 每一级单独一个事务，失败时停在上一个完整版本
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let step = |conn: &mut Connection| -> rusqlite::Result<()> {
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+            tx.commit()
+        };
+        step(conn).map_err(|e| format!("升级到版本 {} 失败: {}", i + 1, e))?;
+    }
+    Ok(())
+}
+
+fn insert_session(tx: &Transaction, device: &str, started_at: &str) -> rusqlite::Result<i64> {
+    tx.execute(
+        "INSERT INTO sessions (device, started_at) VALUES (?1, ?2)",
+        params![device, started_at],
+    )?;
+    Ok(tx.last_insert_rowid())
+}
+
+// 中途连上时没见到复位 / 启动信息，就以第一条数据的时间开一个 session
+fn ensure_session(
+    tx: &Transaction,
+    session_id: &mut Option<i64>,
+    device: &str,
+    ts: &str,
+) -> rusqlite::Result<i64> {
+    match *session_id {
+        Some(id) => Ok(id),
+        None => {
+            let id = insert_session(tx, device, ts)?;
+            *session_id = Some(id);
+            Ok(id)
+        }
+    }
+}
+
+// 该设备最近的一个 session；当前没有进行中的 session 时，注释挂在它上面
+fn latest_session(tx: &Transaction, device: &str) -> rusqlite::Result<Option<i64>> {
+    tx.query_row(
+        "SELECT MAX(id) FROM sessions WHERE device = ?1",
+        params![device],
+        |r| r.get(0),
+    )
+}
+
+fn insert_stage(
+    tx: &Transaction,
+    session_id: i64,
+    ts: &str,
+    r: &StageReport,
+) -> rusqlite::Result<()> {
+    let h = r.host_intervals.as_ref();
+    let histogram = h.map(|h| serde_json::to_string(&h.histogram).unwrap_or_default());
+    tx.execute(
+        "INSERT INTO stages (session_id, reported_at, stage_id, status, arcs, duration_ms,
+                             min_interval_ms, max_interval_ms, single_pulse, complete,
+                             host_interval_count, host_mean_ms, host_std_ms, host_median_ms,
+                             host_p95_ms, host_p99_ms, host_min_ms, host_max_ms, host_histogram)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+                 ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+        params![
+            session_id,
+            ts,
+            r.stage_id,
+            r.status,
+            r.arcs,
+            r.duration_ms,
+            r.min_interval_ms,
+            r.max_interval_ms,
+            r.single_pulse,
+            r.complete,
+            h.map(|h| h.count as i64),
+            h.map(|h| h.mean_ms),
+            h.map(|h| h.std_ms),
+            h.map(|h| h.median_ms),
+            h.map(|h| h.p95_ms),
+            h.map(|h| h.p99_ms),
+            h.map(|h| h.min_ms),
+            h.map(|h| h.max_ms),
+            histogram
+        ],
+    )?;
+    Ok(())
+}
+
+fn update_summary(
+    tx: &Transaction,
+    session_id: i64,
+    ts: &str,
+    s: &TotalSummary,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "UPDATE sessions SET ended_at = ?2, status = ?3, active_time_s = ?4, total_stages = ?5,
+                             grand_total = ?6, avg_frequency_hz = ?7, complete = ?8,
+                             summed_duration_s = ?9
+         WHERE id = ?1",
+        params![
+            session_id,
+            ts,
+            s.status,
+            s.active_time_s,
+            s.total_stages,
+            s.grand_total,
+            s.avg_frequency_hz,
+            s.complete,
+            s.summed_duration_s
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dhjc_store_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join(DB_FILE_NAME)
+    }
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA user_version", [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_db_is_created_at_latest_version() {
+        let path = temp_db("fresh");
+        let w = StoreWriter::open(&path, "dev".to_string()).unwrap();
+        assert_eq!(user_version(&w.conn), 4);
+        assert_eq!(MIGRATIONS.len(), 4);
+        drop(w);
+
+        // 再次打开不重复升级
+        let w = StoreWriter::open(&path, "dev".to_string()).unwrap();
+        assert_eq!(user_version(&w.conn), 4);
+        drop(w);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn v1_db_migrates_forward_keeping_rows() {
+        let path = temp_db("v1");
+        create_dir_all(path.parent().unwrap()).unwrap();
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(MIGRATIONS[0]).unwrap();
+            conn.pragma_update(None, "user_version", 1).unwrap();
+            conn.execute(
+                "INSERT INTO sessions (device, started_at, ended_at) VALUES ('dev', 't0', 't1')",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO stages (session_id, reported_at, stage_id, arcs, single_pulse, complete)
+                 VALUES (1, 't1', 1, 42, 0, 1)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let mut w = StoreWriter::open(&path, "dev".to_string()).unwrap();
+        assert_eq!(user_version(&w.conn), 4);
+
+        // 旧行还在，新列为空
+        let (arcs, mean): (i64, Option<f64>) = w
+            .conn
+            .query_row("SELECT arcs, host_mean_ms FROM stages", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((arcs, mean), (42, None));
+        let summed: Option<f64> = w
+            .conn
+            .query_row("SELECT summed_duration_s FROM sessions", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(summed, None);
+
+        // 没有进行中的 session 时，注释挂到最近的 session 上
+        w.write_batch(vec![StoreMsg::Annotation(Local::now(), "note".to_string())])
+            .unwrap();
+        let (sid, text): (i64, String) = w
+            .conn
+            .query_row("SELECT session_id, text FROM annotations", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((sid, text.as_str()), (1, "note"));
+
+        drop(w);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn newer_db_is_rejected() {
+        let path = temp_db("newer");
+        create_dir_all(path.parent().unwrap()).unwrap();
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.pragma_update(None, "user_version", 5).unwrap();
+        }
+        assert!(StoreWriter::open(&path, "dev".to_string()).is_err());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
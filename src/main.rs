@@ -3,229 +3,70 @@
 // DHJC ARC MONITOR - Rust GUI
 //
 // 顶部：两行
-//   行1：LOGO (左) | 📌TOP + Logs... (右，Logs 在最右)
-//   行2：左：Mode/Serial/TCP/Port/... + Connect/Reset
+//   行1：LOGO (左) | Save + 📌TOP + Export... + Logs... (右，Logs 在最右)
+//   行2：左：Profile/Mode/Serial/TCP/Port/... + Connect/Reset
 //        右：RUN 小圆灯
 // 中间：左侧 SidePanel：DATA TEMPLATE + 四个卡片（可滚动）
 //       右侧 CentralPanel：Live + Total Timeline（右上角 Rate）+ 曲线
 // 底部：Event Log（不可拖动分隔线）
-
+// Export... 按日期范围把历史日志导出为 CSV
+// History... 在程序内浏览历史日志（只读，不影响当前连接）
+// 子命令 import：把文本日志解析为 JSON Lines（见 dhjc_import.rs）
+// 启动时若配置有问题，弹出 "配置问题" 对话框
+// 运行中配置文件被修改：自动重新加载；连接设置变化时弹出 "重新连接?" 提示
+// [[alarm]] 报警：触发后顶部出现红色横幅（可确认 / 清除），状态变化写入 Event Log
+// 批次模式：左侧 Batch 卡片输入目标弧数，显示进度 / 剩余 / ETA，达到目标时提醒并可发停止命令
+
+mod dhjc_alarm;
+mod dhjc_batch;
+mod dhjc_clock;
+mod dhjc_config;
 mod dhjc_core;
-
-use crate::dhjc_core::{Change, CoreState};
-use chrono::Local;
+mod dhjc_export;
+mod dhjc_history;
+mod dhjc_import;
+mod dhjc_intervals;
+mod dhjc_log;
+mod dhjc_plot_export;
+mod dhjc_rate;
+mod dhjc_series;
+mod dhjc_store;
+
+use crate::dhjc_alarm::{AlarmEngine, AlarmTransition};
+use crate::dhjc_batch::{format_duration, BatchRun};
+use crate::dhjc_clock::{parse_log_timestamp, Clock};
+use crate::dhjc_config::{AppConfig, CliArgs, ConfigIssue, IssueLevel};
+use crate::dhjc_core::{Change, CoreEventKind, CoreState, StageReport, TotalSummary};
+use crate::dhjc_export::{export_logs, list_log_dates, CsvRecorder};
+use crate::dhjc_history::{list_history, spawn_load, HistoryData, HistoryItem};
+use crate::dhjc_intervals::{
+    histogram_label, run_count, BoxSummary, IntervalRun, IntervalStats, IntervalTracker,
+    HISTOGRAM_EDGES_MS,
+};
+use crate::dhjc_log::{open_log_reader, EventLogWriter, LogWriter};
+use crate::dhjc_plot_export::{
+    parse_size, ExportFormats, ExportMarker, ExportSeries, PlotExport, CLOCK_STEPS_S,
+    MAX_IMAGE_SIDE,
+};
+use crate::dhjc_rate::{RateEstimator, RateMode};
+use crate::dhjc_series::PlotSeries;
+use crate::dhjc_store::Store;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeDelta, Timelike};
 use eframe::{egui, NativeOptions};
 use egui::viewport::{ViewportCommand, WindowLevel};
-use egui::{Align, Color32, FontFamily, FontId, Layout, TextStyle};
-use egui_plot::{Legend, Line, Plot, PlotPoints};
-use serde::Deserialize;
-use std::fs::{self, create_dir_all, File, OpenOptions};
-use std::io::{Read, Write};
+use egui::{Align, Color32, FontFamily, FontId, Layout, TextStyle, Vec2b};
+use egui_plot::{
+    Bar, BarChart, BoxElem, BoxPlot, BoxSpread, GridInput, GridMark, Legend, Line, LineStyle, Plot,
+    PlotPoint, PlotPoints, Text, VLine,
+};
+use std::fs;
+use std::io::{BufRead, Read, Write};
 use std::net::TcpStream;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::thread;
-use std::time::{Duration, Instant};
-
-// ================= 配置 =================
-
-#[derive(Debug, Default, Deserialize)]
-struct RawConfig {
-    port_name: Option<String>,
-    baud_rate: Option<u32>,
-    log_folder: Option<String>,
-    use_tcp: Option<bool>,
-    tcp_host: Option<String>,
-    tcp_port: Option<u16>,
-}
-
-#[derive(Debug, Clone)]
-struct AppConfig {
-    port_name: String,
-    baud_rate: u32,
-    log_folder: String,
-    use_tcp: bool,
-    tcp_host: String,
-    tcp_port: u16,
-}
-
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            port_name: "COM3".to_string(),
-            baud_rate: 115_200,
-            log_folder: "logs".to_string(),
-            use_tcp: false,
-            tcp_host: "127.0.0.1".to_string(),
-            tcp_port: 5000,
-        }
-    }
-}
-
-impl AppConfig {
-    fn load() -> Self {
-        let default_cfg = AppConfig::default();
-        let path = Path::new("dhjc_config.toml");
-
-        if !path.exists() {
-            let sample = r#"# DHJC Rust GUI 配置文件
-
-# 串口模式
-port_name = "COM3"
-baud_rate = 115200
-
-# TCP 模式
-use_tcp  = false
-tcp_host = "127.0.0.1"
-tcp_port = 5000
-
-# 日志目录
-log_folder = "logs"
-"#;
-            let _ = fs::write(path, sample);
-            println!("[CFG] 未找到 dhjc_config.toml，已生成示例配置文件，使用默认。");
-            return default_cfg;
-        }
-
-        let content = match fs::read_to_string(path) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("[CFG] 读取 dhjc_config.toml 失败: {:?}，使用默认。", e);
-                return default_cfg;
-            }
-        };
-
-        let raw: RawConfig = match toml::from_str(&content) {
-            Ok(r) => r,
-            Err(e) => {
-                eprintln!("[CFG] 解析 dhjc_config.toml 失败: {:?}，使用默认。", e);
-                return default_cfg;
-            }
-        };
-
-        let mut cfg = default_cfg;
-        if let Some(p) = raw.port_name {
-            cfg.port_name = p;
-        }
-        if let Some(b) = raw.baud_rate {
-            cfg.baud_rate = b;
-        }
-        if let Some(f) = raw.log_folder {
-            cfg.log_folder = f;
-        }
-        if let Some(u) = raw.use_tcp {
-            cfg.use_tcp = u;
-        }
-        if let Some(h) = raw.tcp_host {
-            cfg.tcp_host = h;
-        }
-        if let Some(p) = raw.tcp_port {
-            cfg.tcp_port = p;
-        }
-
-        println!(
-            "[CFG] 使用配置: mode={} port={} baud={} tcp={} log_folder={}",
-            if cfg.use_tcp { "TCP" } else { "Serial" },
-            cfg.port_name,
-            cfg.baud_rate,
-            format!("{}:{}", cfg.tcp_host, cfg.tcp_port),
-            cfg.log_folder
-        );
-        cfg
-    }
-}
-
-// ================= 日志写入 =================
-
-struct LogWriter {
-    base_folder: String,
-    current_date: String,
-    file: Option<File>,
-}
-
-impl LogWriter {
-    fn new(base_folder: &str) -> Self {
-        let now = Local::now();
-        let date = now.format("%Y-%m-%d").to_string();
-        let file = Self::open_file_for_date(base_folder, &date);
-        Self {
-            base_folder: base_folder.to_string(),
-            current_date: date,
-            file,
-        }
-    }
-
-    fn rotate_if_needed(&mut self) {
-        let now = Local::now();
-        let today = now.format("%Y-%m-%d").to_string();
-        if today != self.current_date {
-            self.current_date = today.clone();
-            self.file = Self::open_file_for_date(&self.base_folder, &today);
-        }
-    }
-
-    fn open_file_for_date(base_folder: &str, date_str: &str) -> Option<File> {
-        let log_dir = Path::new(base_folder);
-        if let Err(e) = create_dir_all(log_dir) {
-            eprintln!("[LOG] 创建日志目录失败: {:?}", e);
-            return None;
-        }
-
-        let filename = format!("{}.txt", date_str);
-        let path = log_dir.join(filename);
-
-        match OpenOptions::new().create(true).append(true).open(&path) {
-            Ok(f) => {
-                println!("[LOG] 当前日志文件: {}", path.to_string_lossy());
-                Some(f)
-            }
-            Err(e) => {
-                eprintln!("[LOG] 打开日志文件失败: {:?}", e);
-                None
-            }
-        }
-    }
-
-    fn write_line(&mut self, content: &str) {
-        self.rotate_if_needed();
-
-        let file = match self.file.as_mut() {
-            Some(f) => f,
-            None => return,
-        };
-
-        // 标题行 / 分隔线不加时间戳
-        let mut no_ts = false;
-        if content.is_empty() {
-            no_ts = true;
-        } else {
-            let mut chars = content.chars();
-            if let Some(c0) = chars.next() {
-                if c0 == '*' || c0 == '-' || c0 == '=' {
-                    no_ts = true;
-                }
-            }
-            if content.contains("SYSTEM") {
-                no_ts = true;
-            }
-        }
-
-        let line_to_write = if no_ts {
-            format!("{}\r\n", content)
-        } else {
-            let now = Local::now();
-            let ts = now.format("%H:%M:%S").to_string();
-            format!("[{}] {}\r\n", ts, content)
-        };
-
-        if let Err(e) = file.write_all(line_to_write.as_bytes()) {
-            eprintln!("[LOG] 写入日志失败: {:?}", e);
-        } else {
-            let _ = file.flush();
-        }
-    }
-}
+use std::time::{Duration, Instant, SystemTime};
 
 // ================= IO 线程 =================
 
@@ -353,6 +194,63 @@ fn spawn_tcp_thread(
     });
 }
 
+/// 回放按日志时间戳的间隔送行，加快这么多倍
+const REPLAY_SPEEDUP: f64 = 10.0;
+/// 回放时两行之间最多等这么久（长时间空闲直接跳过）
+const REPLAY_MAX_GAP: Duration = Duration::from_secs(2);
+/// 没有时间前缀（旧日志的 Live 行等）或同一时刻的行之间的间隔
+const REPLAY_LINE_DELAY: Duration = Duration::from_millis(10);
+
+// 回放已有日志文件（.gz 也可以）：逐行送入，和串口/TCP 走同一条处理路径
+fn spawn_replay_thread(path: String, tx_line: Sender<String>, rx_cmd: Receiver<String>) {
+    thread::spawn(move || {
+        let reader = match open_log_reader(Path::new(&path)) {
+            Ok(r) => r,
+            Err(e) => {
+                let _ = tx_line.send(format!("[ERROR] 打开回放文件 {} 失败: {:?}", path, e));
+                return;
+            }
+        };
+
+        let mut last_stamp: Option<NaiveDateTime> = None;
+        for raw in reader.split(b'\n') {
+            // 回放时忽略发往设备的命令（Reset 等）
+            match rx_cmd.try_recv() {
+                Ok(_) | Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return,
+            }
+
+            let raw = match raw {
+                Ok(r) => r,
+                Err(e) => {
+                    let _ = tx_line.send(format!("[ERROR] 回放文件读取失败: {:?}", e));
+                    return;
+                }
+            };
+            let line = String::from_utf8_lossy(&raw).trim_end().to_string();
+            if line.is_empty() {
+                continue;
+            }
+
+            // 按时间戳的间隔（加速后）等待，曲线的形状和原来一致；只用间隔，日期无所谓
+            let mut delay = REPLAY_LINE_DELAY;
+            if let Some((t, _)) = parse_log_timestamp(&line, NaiveDate::MIN) {
+                if let Some(gap) = last_stamp.and_then(|last| (t - last).to_std().ok()) {
+                    delay = delay.max(gap.div_f64(REPLAY_SPEEDUP).min(REPLAY_MAX_GAP));
+                }
+                last_stamp = Some(t);
+            }
+            thread::sleep(delay);
+
+            if tx_line.send(line).is_err() {
+                return;
+            }
+        }
+
+        let _ = tx_line.send(format!("[REPLAY] 回放结束: {}", path));
+    });
+}
+
 // ================= GUI 状态 =================
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -365,11 +263,118 @@ enum ConnectionStatus {
 enum ConnectionMode {
     Serial,
     Tcp,
+    Replay,
+}
+
+struct ExportDialog {
+    from_text: String,
+    to_text: String,
+    out_dir_text: String,
+    running: Option<Receiver<Result<String, String>>>,
+    result: Option<Result<String, String>>,
+}
+
+// 本 session 已结束的一个阶段的间隔；箱线图的五个值在阶段结束时算好
+struct FinishedIntervals {
+    stage: Option<i32>,
+    runs: Vec<IntervalRun>,
+    summary: Option<BoxSummary>,
+}
+
+// 直方图 / 箱线图里要排序才能得到的统计：间隔有变化时才重算，不在每帧做
+#[derive(Default)]
+struct IntervalCharts {
+    stale: bool,
+    session: Option<IntervalStats>,
+    current: Option<BoxSummary>,
+}
+
+// 曲线导出对话框（当前标签页的曲线）
+struct PlotExportDialog {
+    full_session: bool,
+    width_text: String,
+    height_text: String,
+    formats: ExportFormats,
+    out_dir_text: String,
+    // 后台渲染 / 写文件中
+    saving: Option<Receiver<Result<String, String>>>,
+    result: Option<Result<String, String>>,
+}
+
+// 中间绘图区的标签页；Total 和 Rate 共用时间轴
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PlotTab {
+    Total,
+    Rate,
+    Histogram,
+    BoxPlot,
+}
+
+// 曲线数据：保留原始分辨率的点数 / 早期抽稀数据的上限
+const PLOT_RECENT_POINTS: usize = 2000;
+const PLOT_ARCHIVE_POINTS: usize = 20000;
+
+// 速率曲线的采样间隔（秒）
+const RATE_SAMPLE_INTERVAL: f64 = 0.5;
+
+// 时间轴上的竖线标记
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MarkerKind {
+    // 阶段开始（阶段号）
+    Stage(i32),
+    Summary,
+    Timeout,
+    Reset,
+    Note,
+}
+
+impl MarkerKind {
+    // 图例名称：同类标记共用一项，可以整体隐藏
+    fn legend(self) -> &'static str {
+        match self {
+            MarkerKind::Stage(_) => "Stage",
+            MarkerKind::Summary => "Summary",
+            MarkerKind::Timeout => "Timeout",
+            MarkerKind::Reset => "Reset",
+            MarkerKind::Note => "Note",
+        }
+    }
+
+    fn color(self) -> Color32 {
+        match self {
+            MarkerKind::Stage(_) => Color32::from_rgb(110, 200, 140),
+            MarkerKind::Summary => Color32::from_rgb(200, 160, 255),
+            MarkerKind::Timeout => Color32::from_rgb(255, 150, 80),
+            MarkerKind::Reset => Color32::from_rgb(255, 90, 90),
+            MarkerKind::Note => Color32::from_rgb(240, 220, 120),
+        }
+    }
+}
+
+struct PlotMarker {
+    t: f64,
+    kind: MarkerKind,
+    label: String,
+    // 悬停时显示：阶段报告 / 汇总的数字，或注释全文
+    detail: Vec<String>,
+}
+
+// History... 窗口：左侧日期 / session 列表，右侧为选中项的解析结果
+struct HistoryBrowser {
+    items: Vec<HistoryItem>,
+    selected: Option<usize>,
+    loading: Option<Receiver<Result<HistoryData, String>>>,
+    data: Option<Result<HistoryData, String>>,
 }
 
 struct DhjcApp {
     cfg: AppConfig,
     logger: LogWriter,
+    // 日志时间戳和 CoreState 共用的时钟
+    clock: Clock,
+    event_log: Option<EventLogWriter>,
+    csv: CsvRecorder,
+    store: Option<Store>,
     core: CoreState,
 
     status: ConnectionStatus,
@@ -379,6 +384,7 @@ struct DhjcApp {
     serial_baud_text: String,
     tcp_host_text: String,
     tcp_port_text: String,
+    replay_file_text: String,
 
     line_rx: Option<Receiver<String>>,
     cmd_tx: Option<Sender<String>>,
@@ -388,66 +394,98 @@ struct DhjcApp {
     last_live_line: Option<String>, // 单独显示 Live
 
     last_error: Option<String>,
+    config_issues: Vec<ConfigIssue>,
+    show_config_issues: bool,
+    export_dialog: Option<ExportDialog>,
+    plot_export_dialog: Option<PlotExportDialog>,
+    history: Option<HistoryBrowser>,
+    // 上一个汇总的 Active Time 与 Duration 之和相差超过容差时的提示
+    active_drift: Option<String>,
+
+    // 配置热加载
+    config_mtime: Option<SystemTime>,
+    last_config_check: Instant,
+    config_check: Option<Receiver<ConfigCheck>>,
+    pending_reconnect: bool,
 
     start_time: Instant,
-    plot_points: Vec<[f64; 2]>,
-    max_plot_points: usize,
+    // start_time 对应的本地时间：曲线横轴切到时钟时间用
+    start_wall: DateTime<Local>,
+    // 横轴显示 HH:MM:SS（否则为 start_time 以来的秒数）
+    clock_axis: bool,
+    /// Total / Rate 图上一帧的横轴范围（导出当前视图用）
+    timeline_view: Option<(f64, f64)>,
+    // 近期原始分辨率 + 早期抽稀，整个 session 都留着
+    plot_points: PlotSeries,
+    plot_tab: PlotTab,
+    // [t, rate]，每 RATE_SAMPLE_INTERVAL 秒采一次
+    rate_points: PlotSeries,
+    last_rate_sample: f64,
+    markers: Vec<PlotMarker>,
+    max_markers: usize,
+    // 待添加的用户注释
+    note_text: String,
 
     last_pulse_time: Option<Instant>,
     last_stage_for_plot: i32,
     always_on_top: bool,
-    last_wait_ms: Option<f64>,
-    prev_wait_ms: Option<f64>,
+    // 顶部 Rate：由 Total 的增量估计
+    rate: RateEstimator,
+    // 主机侧的脉冲间隔统计，以及最近的阶段报告（带统计）
+    intervals: IntervalTracker,
+    stage_stats: Vec<StageReport>,
+    max_stage_stats: usize,
+    // 本 session 各阶段的原始间隔（直方图 / 箱线图用）
+    stage_intervals: Vec<FinishedIntervals>,
+    interval_charts: IntervalCharts,
+    // [[alarm]] 规则的评估与当前报警；上次响铃的时间
+    alarms: AlarmEngine,
+    last_alarm_beep: Option<Instant>,
+    // 批次模式：目标弧数输入框、进行中 / 刚完成的批次、达到目标时是否发停止命令
+    batch_target_text: String,
+    batch: Option<BatchRun>,
+    batch_send_stop: bool,
     log_filter: String,
+    // 已在 Event Log 中提示过的丢弃行数
+    reported_dropped: u64,
+    // 同上，事件日志（JSON Lines）丢弃的条数
+    reported_dropped_events: u64,
 }
 
 impl DhjcApp {
 
     fn full_reset(&mut self) {
          // ✅ 保留日志内容，不清空 log_lines
-        // ✅ 写入分隔线作为提示
-        self.logger.write_line("===== SYSTEM RESET =====");
-        self.log_lines.push("===== SYSTEM RESET =====".to_string());
+        // ✅ 写入分隔线作为提示（回放时只显示，不写文本日志）
+        self.push_record("===== SYSTEM RESET =====".to_string());
 
         // ✅ 重置内部计数与绘图
-        self.core = CoreState::new();
+        self.core = CoreState::with_clock(self.clock.clone());
         self.plot_points.clear();
+        self.rate_points.clear();
+        self.last_rate_sample = 0.0;
+        self.markers.clear();
 
         // ✅ 清除速率状态
-        self.last_wait_ms = None;
-        self.prev_wait_ms = None;
+        self.rate.reset();
+        self.intervals.reset();
+        self.stage_intervals.clear();
+        self.interval_charts.stale = true;
+        self.alarms.reset();
         self.last_pulse_time = None;
         self.last_stage_for_plot = -1;
 
         // ✅ 重置时间基准
         self.start_time = Instant::now();
+        self.start_wall = self.clock.now().wall;
+        self.add_marker(MarkerKind::Reset, "Reset".to_string(), vec!["手动复位".to_string()]);
 
         // ✅ 清空仅 UI 层的状态
         self.last_live_line = None;
         self.last_error = None;
-
-        // ✅ 日志写分隔线（视觉提示）
-        self.logger.write_line("===== SYSTEM RESET =====");
-        self.log_lines.push("===== SYSTEM RESET =====".to_string());
-
-        //
-        self.prev_wait_ms = None;
-
     }
 
-    fn parse_wait_ms_from_live(line: &str) -> Option<f64> {
-        // 在字符串中找 "Wait:"
-        let tag = "Wait:";
-        let idx = line.find(tag)?;
-        let rest = &line[idx + tag.len()..];
-
-        // 从 "xxxxx ms" 里抠出数字
-        let end = rest.find("ms")?;
-        let num_str = rest[..end].trim();
-        num_str.parse::<f64>().ok()
-    }
-
-    fn new(cc: &eframe::CreationContext<'_>, cfg: AppConfig) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, cfg: AppConfig, issues: Vec<ConfigIssue>) -> Self {
         let ctx = &cc.egui_ctx;
         ctx.set_visuals(egui::Visuals::light());
         ctx.set_pixels_per_point(1.4);
@@ -463,39 +501,87 @@ impl DhjcApp {
         );
         ctx.set_style(style);
 
-        let mode = if cfg.use_tcp {
+        let mode = if cfg.replay_file.is_some() {
+            ConnectionMode::Replay
+        } else if cfg.use_tcp {
             ConnectionMode::Tcp
         } else {
             ConnectionMode::Serial
         };
 
-        Self {
+        let clock = Clock::default();
+        let mut app = Self {
             cfg: cfg.clone(),
-            logger: LogWriter::new(&cfg.log_folder),
-            core: CoreState::new(),
-            prev_wait_ms: None,
+            logger: LogWriter::new(&cfg, clock.clone()),
+            clock: clock.clone(),
+            event_log: open_event_log(&cfg, clock.clone()),
+            csv: CsvRecorder::append(&cfg.log_folder, &cfg.device_id()),
+            store: open_store(&cfg, clock.clone()),
+            core: CoreState::with_clock(clock.clone()),
             status: ConnectionStatus::Disconnected,
             mode,
             serial_port_text: cfg.port_name.clone(),
             serial_baud_text: cfg.baud_rate.to_string(),
             tcp_host_text: cfg.tcp_host.clone(),
             tcp_port_text: cfg.tcp_port.to_string(),
+            replay_file_text: cfg.replay_file.clone().unwrap_or_default(),
             line_rx: None,
             cmd_tx: None,
             log_lines: Vec::new(),
             max_log_lines: 1000,
             last_live_line: None,
             last_error: None,
+            show_config_issues: !issues.is_empty(),
+            config_issues: Vec::new(),
+            export_dialog: None,
+            plot_export_dialog: None,
+            history: None,
+            active_drift: None,
+            config_mtime: file_mtime(&cfg.config_path),
+            last_config_check: Instant::now(),
+            config_check: None,
+            pending_reconnect: false,
             start_time: Instant::now(),
-            plot_points: Vec::new(),
-            max_plot_points: 2000,
+            start_wall: clock.now().wall,
+            clock_axis: cfg.plot_clock_time,
+            timeline_view: None,
+            plot_points: PlotSeries::new(PLOT_RECENT_POINTS, PLOT_ARCHIVE_POINTS),
+            plot_tab: PlotTab::Total,
+            rate_points: PlotSeries::new(PLOT_RECENT_POINTS, PLOT_ARCHIVE_POINTS),
+            last_rate_sample: 0.0,
+            markers: Vec::new(),
+            max_markers: 500,
+            note_text: String::new(),
             last_pulse_time: None,
             last_stage_for_plot: -1,
-            always_on_top: false,
-            last_wait_ms: None,
+            always_on_top: cfg.always_on_top,
+            rate: RateEstimator::new(cfg.rate),
+            intervals: IntervalTracker::default(),
+            stage_stats: Vec::new(),
+            max_stage_stats: 50,
+            stage_intervals: Vec::new(),
+            interval_charts: IntervalCharts::default(),
+            alarms: AlarmEngine::new(cfg.alarms.clone()),
+            last_alarm_beep: None,
+            batch_target_text: String::new(),
+            batch: None,
+            batch_send_stop: true,
 
             log_filter: String::new(),
+            reported_dropped: 0,
+            reported_dropped_events: 0,
+        };
+
+        // 配置问题也进 Event Log，关掉对话框后还能查
+        for issue in &issues {
+            app.push_log(format!("[CFG] {}", issue));
+        }
+        app.config_issues = issues;
+
+        if cfg.auto_connect {
+            app.connect();
         }
+        app
     }
 
     fn connect(&mut self) {
@@ -533,134 +619,1771 @@ impl DhjcApp {
                     .unwrap_or(self.cfg.tcp_port);
                 spawn_tcp_thread(host, port, tx_line, rx_cmd);
             }
+            ConnectionMode::Replay => {
+                let path = self.replay_file_text.trim().to_string();
+                if path.is_empty() {
+                    self.last_error = Some("请先输入回放文件路径".to_string());
+                    return;
+                }
+                spawn_replay_thread(path, tx_line, rx_cmd);
+            }
+        }
+
+        self.line_rx = Some(rx_line);
+        self.cmd_tx = Some(tx_cmd);
+        self.status = ConnectionStatus::Connected;
+        self.last_error = None;
+        self.clock.mark_connected();
+
+        if self.cfg.auto_save_on_connect && self.mode != ConnectionMode::Replay {
+            self.save_settings();
+        }
+    }
+
+    // 按当前配置重新打开文本日志 / 事件日志 / 数据库；换下来的在后台线程关闭
+    fn replace_logger(&mut self) {
+        let new = LogWriter::new(&self.cfg, self.clock.clone());
+        drop_in_background(std::mem::replace(&mut self.logger, new));
+    }
+
+    fn replace_event_log(&mut self) {
+        let new = open_event_log(&self.cfg, self.clock.clone());
+        drop_in_background(std::mem::replace(&mut self.event_log, new));
+    }
+
+    fn reopen_store(&mut self) {
+        drop_in_background(std::mem::replace(&mut self.store, open_store(&self.cfg, self.clock.clone())));
+    }
+
+    // 切换 profile：刷新顶部栏输入框、置顶状态和日志目录
+    fn apply_profile(&mut self, ctx: &egui::Context, name: Option<String>) {
+        let old_log_folder = self.cfg.log_folder.clone();
+        if let Err(e) = self.cfg.select_profile(name.as_deref()) {
+            self.last_error = Some(e);
+            return;
+        }
+
+        self.refresh_connection_fields();
+        self.set_always_on_top(ctx, self.cfg.always_on_top);
+
+        if self.cfg.log_folder != old_log_folder {
+            self.replace_logger();
+        }
+        // 设备标识可能随 profile 变化
+        self.replace_event_log();
+        self.csv = CsvRecorder::append(&self.cfg.log_folder, &self.cfg.device_id());
+        self.reopen_store();
+
+        let msg = format!(
+            "[CFG] 切换到 profile: {}",
+            self.cfg.profile.as_deref().unwrap_or("(default)")
+        );
+        self.push_log(msg);
+    }
+
+    // 用 cfg 刷新顶部栏的连接输入框（回放模式保持不变）
+    fn refresh_connection_fields(&mut self) {
+        if self.mode != ConnectionMode::Replay {
+            self.mode = if self.cfg.use_tcp {
+                ConnectionMode::Tcp
+            } else {
+                ConnectionMode::Serial
+            };
+        }
+        self.serial_port_text = self.cfg.port_name.clone();
+        self.serial_baud_text = self.cfg.baud_rate.to_string();
+        self.tcp_host_text = self.cfg.tcp_host.clone();
+        self.tcp_port_text = self.cfg.tcp_port.to_string();
+    }
+
+    fn set_always_on_top(&mut self, ctx: &egui::Context, on: bool) {
+        if self.always_on_top == on {
+            return;
+        }
+        self.always_on_top = on;
+        let level = if on {
+            WindowLevel::AlwaysOnTop
+        } else {
+            WindowLevel::Normal
+        };
+        ctx.send_viewport_cmd(ViewportCommand::WindowLevel(level));
+    }
+
+    // 定时检查配置文件是否被修改（轮询 mtime）。读 mtime 和重新加载都在后台线程，
+    // 日志目录在网络盘上时不卡界面
+    fn poll_config_file(&mut self, ctx: &egui::Context) {
+        const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+        if let Some(rx) = &self.config_check {
+            match rx.try_recv() {
+                Ok(check) => {
+                    self.config_check = None;
+                    self.apply_config_check(ctx, check);
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => self.config_check = None,
+            }
+        }
+
+        if self.last_config_check.elapsed() < CONFIG_POLL_INTERVAL {
+            return;
+        }
+        self.last_config_check = Instant::now();
+
+        let cfg = self.cfg.clone();
+        let known = self.config_mtime;
+        let ctx = ctx.clone();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mtime = file_mtime(&cfg.config_path);
+            let reload = (mtime.is_some() && mtime != known).then(|| cfg.reload());
+            let _ = tx.send(ConfigCheck {
+                profile: cfg.profile.clone(),
+                mtime,
+                reload,
+            });
+            ctx.request_repaint();
+        });
+        self.config_check = Some(rx);
+    }
+
+    fn apply_config_check(&mut self, ctx: &egui::Context, check: ConfigCheck) {
+        let Some(result) = check.reload else {
+            return;
+        };
+        // 检查期间自己保存过，或切换了 profile（结果按旧 profile 加载），下次再查
+        if check.mtime == self.config_mtime || check.profile != self.cfg.profile {
+            return;
+        }
+        self.config_mtime = check.mtime;
+
+        // 热加载关闭时仍检查文件，只有重新打开 hot_reload 的那次保存才生效
+        let enabled = self.cfg.hot_reload;
+        match result {
+            Ok((new_cfg, _)) if !enabled && !new_cfg.hot_reload => {}
+            Ok((new_cfg, issues)) => {
+                for issue in &issues {
+                    self.push_log(format!("[CFG] {}", issue));
+                }
+                self.apply_reloaded_config(ctx, new_cfg);
+            }
+            Err(_) if !enabled => {}
+            Err(issue) => {
+                // 解析失败：保留旧配置，只提示
+                let msg = format!("[WARN] 配置热加载失败，继续使用原配置: {}", issue);
+                self.last_error = Some(msg.clone());
+                self.push_log(msg);
+            }
+        }
+    }
+
+    // 非连接设置立即生效；连接设置变化且正在连接时，先询问是否重连
+    fn apply_reloaded_config(&mut self, ctx: &egui::Context, new_cfg: AppConfig) {
+        let conn_changed = self.cfg.connection_differs(&new_cfg);
+        let old = std::mem::replace(&mut self.cfg, new_cfg);
+
+        self.rate.set_settings(self.cfg.rate);
+        self.alarms.set_rules(self.cfg.alarms.clone());
+        if self.cfg.plot_clock_time != old.plot_clock_time {
+            self.clock_axis = self.cfg.plot_clock_time;
+        }
+        if self.cfg.text_log_differs(&old) {
+            self.replace_logger();
+        }
+        if self.cfg.json_log != old.json_log
+            || self.cfg.log_folder != old.log_folder
+            || self.cfg.device_id() != old.device_id()
+        {
+            self.replace_event_log();
+        }
+        if self.cfg.log_folder != old.log_folder || self.cfg.device_id() != old.device_id() {
+            self.csv = CsvRecorder::append(&self.cfg.log_folder, &self.cfg.device_id());
+        }
+        if self.cfg.sqlite_log != old.sqlite_log
+            || self.cfg.log_folder != old.log_folder
+            || self.cfg.device_id() != old.device_id()
+        {
+            self.reopen_store();
+        }
+        self.set_always_on_top(ctx, self.cfg.always_on_top);
+
+        self.push_log(format!(
+            "[CFG] 已重新加载 {}",
+            self.cfg.config_path.to_string_lossy()
+        ));
+
+        if conn_changed {
+            self.refresh_connection_fields();
+            if self.status == ConnectionStatus::Connected && self.mode != ConnectionMode::Replay {
+                self.pending_reconnect = true;
+            }
+        }
+    }
+
+    fn ui_reconnect_prompt(&mut self, ctx: &egui::Context) {
+        if !self.pending_reconnect {
+            return;
+        }
+
+        let mut reconnect = false;
+        let mut later = false;
+
+        egui::Window::new("连接设置已变化")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label("配置文件中的连接设置已修改，是否立即用新设置重新连接？");
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Reconnect").clicked() {
+                        reconnect = true;
+                    }
+                    if ui.button("Later").clicked() {
+                        later = true;
+                    }
+                });
+            });
+
+        if reconnect {
+            self.pending_reconnect = false;
+            self.disconnect();
+            self.connect();
+        } else if later {
+            self.pending_reconnect = false;
+            self.push_log("[CFG] 新的连接设置将在下次连接时生效".to_string());
+        }
+    }
+
+    // Event Log + 日志文件各记一行
+    fn push_log(&mut self, line: String) {
+        self.logger.write_line(&line);
+        self.log_lines.push(line);
+    }
+
+    // 回放的数据来自已有日志：只更新界面，不写文本日志 / Event Log 文件 / CSV / 数据库
+    fn replaying(&self) -> bool {
+        self.mode == ConnectionMode::Replay
+    }
+
+    // 由数据引出的记录（报警、批次、注释、偏差警告）：回放时只显示在 Event Log
+    fn push_record(&mut self, line: String) {
+        if self.replaying() {
+            self.log_lines.push(line);
+        } else {
+            self.push_log(line);
+        }
+    }
+
+    // 补发队列满时暂存的 session 边界；日志整理（压缩 / 删除）记进 Event Log；
+    // 日志 / 事件日志队列满时丢掉的条数只在界面上提示，不再往已满的队列里塞
+    fn poll_logger(&mut self) {
+        self.logger.flush_control();
+        for notice in self.logger.take_notices() {
+            self.push_log(notice);
+        }
+
+        let dropped = self.logger.dropped_lines();
+        if dropped < self.reported_dropped {
+            // 换了 LogWriter，计数重新开始
+            self.reported_dropped = 0;
+        }
+        if dropped > self.reported_dropped {
+            self.log_lines.push(format!(
+                "[LOG] 日志写盘跟不上，已丢弃 {} 行",
+                dropped - self.reported_dropped
+            ));
+            self.reported_dropped = dropped;
+        }
+
+        let dropped = self.event_log.as_ref().map_or(0, |w| w.dropped_events());
+        if dropped < self.reported_dropped_events {
+            // 换了或关掉了事件日志，计数重新开始
+            self.reported_dropped_events = 0;
+        }
+        if dropped > self.reported_dropped_events {
+            self.log_lines.push(format!(
+                "[LOG] 事件日志写盘跟不上，已丢弃 {} 条",
+                dropped - self.reported_dropped_events
+            ));
+            self.reported_dropped_events = dropped;
+        }
+    }
+
+    // 顶部栏当前的设置写回 cfg，再保存到配置文件
+    fn save_settings(&mut self) {
+        match self.mode {
+            ConnectionMode::Serial => self.cfg.use_tcp = false,
+            ConnectionMode::Tcp => self.cfg.use_tcp = true,
+            // 回放不是持久设置，保持原来的连接方式
+            ConnectionMode::Replay => {}
+        }
+
+        let port_name = self.serial_port_text.trim();
+        if !port_name.is_empty() {
+            self.cfg.port_name = port_name.to_string();
+        }
+        if let Ok(b) = self.serial_baud_text.trim().parse::<u32>() {
+            self.cfg.baud_rate = b;
+        }
+        let host = self.tcp_host_text.trim();
+        if !host.is_empty() {
+            self.cfg.tcp_host = host.to_string();
+        }
+        if let Ok(p) = self.tcp_port_text.trim().parse::<u16>() {
+            self.cfg.tcp_port = p;
         }
+        self.cfg.always_on_top = self.always_on_top;
+
+        let msg = match self.cfg.save() {
+            Ok(()) => {
+                // 自己写的改动不触发热加载
+                self.config_mtime = file_mtime(&self.cfg.config_path);
+                format!(
+                    "[CFG] 设置已保存到 {}",
+                    self.cfg.config_path.to_string_lossy()
+                )
+            }
+            Err(e) => {
+                let msg = format!("[ERROR] 保存设置失败: {}", e);
+                self.last_error = Some(msg.clone());
+                msg
+            }
+        };
+        self.push_log(msg);
+    }
+
+    fn disconnect(&mut self) {
+        self.status = ConnectionStatus::Disconnected;
+        self.clock.mark_disconnected();
+        self.line_rx = None;
+        self.cmd_tx = None;
+    }
+
+    fn send_reset(&mut self) {
+        if let Some(tx) = &self.cmd_tx {
+            let _ = tx.send("R\n".to_string());
+        }
+    }
+
+    fn open_logs_folder(&self) {
+        open_with_system(&self.cfg.log_folder);
+    }
+
+    fn open_export_dialog(&mut self) {
+        // 默认范围：最早的日志 ~ 今天
+        let today = Local::now().date_naive();
+        let from = list_log_dates(&self.cfg.log_folder)
+            .first()
+            .copied()
+            .unwrap_or(today);
+        self.export_dialog = Some(ExportDialog {
+            from_text: from.format("%Y-%m-%d").to_string(),
+            to_text: today.format("%Y-%m-%d").to_string(),
+            out_dir_text: Path::new(&self.cfg.log_folder)
+                .join("export")
+                .to_string_lossy()
+                .to_string(),
+            running: None,
+            result: None,
+        });
+    }
+
+    // Export... 对话框；日志解析在后台线程进行
+    fn ui_export_dialog(&mut self, ctx: &egui::Context) {
+        let finished = match self.export_dialog.as_ref().and_then(|d| d.running.as_ref()) {
+            Some(rx) => match rx.try_recv() {
+                Ok(result) => Some(result),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => Some(Err("导出线程意外退出".to_string())),
+            },
+            None => None,
+        };
+        if let Some(result) = finished {
+            if let Ok(msg) = &result {
+                self.push_log(format!("[EXPORT] {}", msg.replace('\n', " ")));
+            }
+            if let Some(d) = self.export_dialog.as_mut() {
+                d.running = None;
+                d.result = Some(result);
+            }
+        }
+
+        let dialog = match self.export_dialog.as_mut() {
+            Some(d) => d,
+            None => return,
+        };
+
+        let mut close = false;
+        let mut run = false;
+        let mut open_dir = false;
+
+        egui::Window::new("导出 CSV")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                egui::Grid::new("export_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("From:");
+                    ui.add(egui::TextEdit::singleline(&mut dialog.from_text).desired_width(100.0));
+                    ui.end_row();
+                    ui.label("To:");
+                    ui.add(egui::TextEdit::singleline(&mut dialog.to_text).desired_width(100.0));
+                    ui.end_row();
+                    ui.label("Folder:");
+                    ui.add(egui::TextEdit::singleline(&mut dialog.out_dir_text).desired_width(260.0));
+                    ui.end_row();
+                });
+
+                if dialog.running.is_some() {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("正在导出...");
+                    });
+                } else if let Some(result) = &dialog.result {
+                    ui.separator();
+                    match result {
+                        Ok(msg) => ui.label(msg),
+                        Err(msg) => ui.colored_label(Color32::from_rgb(255, 120, 120), msg),
+                    };
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(dialog.running.is_none(), egui::Button::new("Export"))
+                        .clicked()
+                    {
+                        run = true;
+                    }
+                    if ui.button("Open folder").clicked() {
+                        open_dir = true;
+                    }
+                    if ui.button("Close").clicked() {
+                        close = true;
+                    }
+                });
+            });
+
+        if run {
+            let range = (
+                NaiveDate::parse_from_str(dialog.from_text.trim(), "%Y-%m-%d"),
+                NaiveDate::parse_from_str(dialog.to_text.trim(), "%Y-%m-%d"),
+            );
+            match range {
+                (Ok(from), Ok(to)) => {
+                    dialog.running = Some(spawn_log_export(
+                        self.cfg.log_folder.clone(),
+                        from,
+                        to,
+                        PathBuf::from(dialog.out_dir_text.trim()),
+                        self.cfg.device_id(),
+                    ));
+                }
+                _ => dialog.result = Some(Err("日期格式应为 YYYY-MM-DD".to_string())),
+            }
+        }
+        if open_dir {
+            open_with_system(dialog.out_dir_text.trim());
+        }
+        if close {
+            self.export_dialog = None;
+        }
+    }
+
+    fn open_plot_export_dialog(&mut self) {
+        self.plot_export_dialog = Some(PlotExportDialog {
+            full_session: false,
+            width_text: "1600".to_string(),
+            height_text: "900".to_string(),
+            formats: ExportFormats {
+                png: true,
+                svg: false,
+                csv: true,
+            },
+            out_dir_text: Path::new(&self.cfg.log_folder)
+                .join("export")
+                .to_string_lossy()
+                .to_string(),
+            saving: None,
+            result: None,
+        });
+    }
+
+    // 当前标签页的曲线和标记；CSV 另外带上另一条曲线
+    fn plot_export(&self, full_session: bool) -> (PlotExport, ExportSeries) {
+        let rgb = |c: Color32| [c.r(), c.g(), c.b()];
+        let total = ExportSeries {
+            name: "Total".to_string(),
+            color: [40, 110, 220],
+            points: self.plot_points.view(None, usize::MAX),
+        };
+        let rate = ExportSeries {
+            name: "Rate (pulses/s)".to_string(),
+            color: [230, 140, 30],
+            points: self.rate_points.view(None, usize::MAX),
+        };
+        let (title, shown, other) = match self.plot_tab {
+            PlotTab::Rate => ("DHJC Rate", rate, total),
+            _ => ("DHJC Total", total, rate),
+        };
+        let export = PlotExport {
+            title: format!("{} - {}", title, self.start_wall.format("%Y-%m-%d %H:%M:%S")),
+            y_label: shown.name.clone(),
+            start: Some(self.start_wall.naive_local()),
+            x_label: "Time".to_string(),
+            series: vec![shown],
+            markers: self
+                .markers
+                .iter()
+                .map(|m| ExportMarker {
+                    x: m.t,
+                    label: m.label.clone(),
+                    color: rgb(m.kind.color()),
+                })
+                .collect(),
+            x_range: if full_session {
+                None
+            } else {
+                self.timeline_view
+            },
+        };
+        (export, other)
+    }
+
+    // Export plot... 对话框：PNG / SVG 为当前标签页，CSV 含 Total 和 Rate；渲染在后台线程进行
+    fn ui_plot_export_dialog(&mut self, ctx: &egui::Context) {
+        let finished = match self.plot_export_dialog.as_ref().and_then(|d| d.saving.as_ref()) {
+            Some(rx) => match rx.try_recv() {
+                Ok(result) => Some(result),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => Some(Err("导出线程意外退出".to_string())),
+            },
+            None => None,
+        };
+        if let Some(result) = finished {
+            if let Ok(msg) = &result {
+                self.push_log(format!("[EXPORT] {}", msg.replace('\n', " ")));
+            }
+            if let Some(d) = self.plot_export_dialog.as_mut() {
+                d.saving = None;
+                d.result = Some(result);
+            }
+        }
+
+        let dialog = match self.plot_export_dialog.as_mut() {
+            Some(d) => d,
+            None => return,
+        };
+
+        let mut close = false;
+        let mut run = false;
+        let mut open_dir = false;
+
+        egui::Window::new("导出曲线")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                egui::Grid::new("plot_export_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Range:");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut dialog.full_session, false, "Current view");
+                        ui.radio_value(&mut dialog.full_session, true, "Full session");
+                    });
+                    ui.end_row();
+                    ui.label("Size:");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut dialog.width_text).desired_width(50.0));
+                        ui.label("x");
+                        ui.add(egui::TextEdit::singleline(&mut dialog.height_text).desired_width(50.0));
+                    });
+                    ui.end_row();
+                    ui.label("Format:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut dialog.formats.png, "PNG");
+                        ui.checkbox(&mut dialog.formats.svg, "SVG");
+                        ui.checkbox(&mut dialog.formats.csv, "CSV");
+                    });
+                    ui.end_row();
+                    ui.label("Folder:");
+                    ui.add(egui::TextEdit::singleline(&mut dialog.out_dir_text).desired_width(260.0));
+                    ui.end_row();
+                });
+
+                if dialog.saving.is_some() {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("正在导出...");
+                    });
+                } else if let Some(result) = &dialog.result {
+                    ui.separator();
+                    match result {
+                        Ok(msg) => ui.label(msg),
+                        Err(msg) => ui.colored_label(Color32::from_rgb(255, 120, 120), msg),
+                    };
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(dialog.saving.is_none(), egui::Button::new("Export"))
+                        .clicked()
+                    {
+                        run = true;
+                    }
+                    if ui.button("Open folder").clicked() {
+                        open_dir = true;
+                    }
+                    if ui.button("Close").clicked() {
+                        close = true;
+                    }
+                });
+            });
+
+        if run {
+            let size = parse_size(&format!(
+                "{}x{}",
+                dialog.width_text.trim(),
+                dialog.height_text.trim()
+            ));
+            let (full_session, formats) = (dialog.full_session, dialog.formats);
+            let out_dir = dialog.out_dir_text.trim().to_string();
+            let result = match size {
+                None => Err(format!("尺寸应为 16 ~ {} 的整数", MAX_IMAGE_SIDE)),
+                Some(_) if !(formats.png || formats.svg || formats.csv) => {
+                    Err("至少选择一种格式".to_string())
+                }
+                Some(size) => Ok(self.spawn_plot_export(full_session, formats, size, out_dir)),
+            };
+            if let Some(d) = self.plot_export_dialog.as_mut() {
+                match result {
+                    Ok(rx) => d.saving = Some(rx),
+                    Err(e) => d.result = Some(Err(e)),
+                }
+            }
+            return;
+        }
+        if open_dir {
+            open_with_system(dialog.out_dir_text.trim());
+        }
+        if close {
+            self.plot_export_dialog = None;
+        }
+    }
+
+    // 曲线数据在这里复制一份，渲染和写文件放到后台线程，大图不卡界面
+    fn spawn_plot_export(
+        &self,
+        full_session: bool,
+        formats: ExportFormats,
+        size: (u32, u32),
+        out_dir: String,
+    ) -> Receiver<Result<String, String>> {
+        let (export, other) = self.plot_export(full_session);
+        let stem = format!(
+            "plot_{}_{}",
+            if self.plot_tab == PlotTab::Rate { "rate" } else { "total" },
+            Local::now().format("%Y%m%d_%H%M%S")
+        );
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(save_plot_export(export, other, &stem, &out_dir, formats, size));
+        });
+        rx
+    }
+
+    fn open_history(&mut self) {
+        self.history = Some(HistoryBrowser {
+            items: list_history(&self.cfg.log_folder),
+            selected: None,
+            loading: None,
+            data: None,
+        });
+    }
+
+    // History... 窗口；解析在后台线程进行
+    fn ui_history_browser(&mut self, ctx: &egui::Context) {
+        let browser = match self.history.as_mut() {
+            Some(b) => b,
+            None => return,
+        };
+
+        if let Some(rx) = &browser.loading {
+            match rx.try_recv() {
+                Ok(result) => {
+                    browser.data = Some(result);
+                    browser.loading = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    browser.data = Some(Err("解析线程意外退出".to_string()));
+                    browser.loading = None;
+                }
+            }
+        }
+
+        let mut open = true;
+        let mut pick = None;
+        let mut refresh = false;
+
+        egui::Window::new("历史日志")
+            .open(&mut open)
+            .default_size(egui::vec2(800.0, 500.0))
+            .resizable(true)
+            .show(ctx, |ui| {
+                egui::SidePanel::left("history_list")
+                    .resizable(true)
+                    .default_width(180.0)
+                    .show_inside(ui, |ui| {
+                        if ui.button("Refresh").clicked() {
+                            refresh = true;
+                        }
+                        ui.separator();
+                        if browser.items.is_empty() {
+                            ui.label("日志目录中没有文本日志");
+                        }
+                        egui::ScrollArea::vertical().show(ui, |ui| {
+                            for (i, item) in browser.items.iter().enumerate() {
+                                let text = if item.session {
+                                    format!("    {}", item.label)
+                                } else {
+                                    item.label.clone()
+                                };
+                                if ui
+                                    .selectable_label(browser.selected == Some(i), text)
+                                    .clicked()
+                                {
+                                    pick = Some(i);
+                                }
+                            }
+                        });
+                    });
+
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    if browser.loading.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("解析中...");
+                        });
+                        return;
+                    }
+                    match &browser.data {
+                        None => {
+                            ui.label("选择左侧的日期或 session");
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(Color32::from_rgb(255, 120, 120), e);
+                        }
+                        Some(Ok(data)) => ui_history_data(ui, data),
+                    }
+                });
+            });
+
+        if refresh {
+            browser.items = list_history(&self.cfg.log_folder);
+            browser.selected = None;
+            browser.data = None;
+        }
+        if let Some(i) = pick {
+            browser.selected = Some(i);
+            browser.data = None;
+            browser.loading = Some(spawn_load(&browser.items[i]));
+        }
+        if !open {
+            self.history = None;
+        }
+    }
+
+    // 启动时的配置问题对话框
+    fn ui_config_issues(&mut self, ctx: &egui::Context) {
+        if !self.show_config_issues {
+            return;
+        }
+
+        let mut close = false;
+        let mut open_config = false;
+
+        egui::Window::new("配置问题")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} 中发现以下问题：",
+                    self.cfg.config_path.to_string_lossy()
+                ));
+                ui.add_space(6.0);
+
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        for issue in &self.config_issues {
+                            let color = match issue.level {
+                                IssueLevel::Error => Color32::from_rgb(220, 60, 60),
+                                IssueLevel::Warning => Color32::from_rgb(230, 180, 70),
+                            };
+                            let location = match issue.line {
+                                Some(line) => format!("第 {} 行", line),
+                                None => "文件".to_string(),
+                            };
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label(
+                                    egui::RichText::new(location)
+                                        .monospace()
+                                        .color(color),
+                                );
+                                ui.label(&issue.message);
+                            });
+                        }
+                    });
+
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Continue")
+                        .on_hover_text("有问题的配置项已忽略，其余设置照常生效")
+                        .clicked()
+                    {
+                        close = true;
+                    }
+                    if ui.button("Open config").clicked() {
+                        open_config = true;
+                        close = true;
+                    }
+                });
+            });
+
+        if open_config {
+            open_with_system(&self.cfg.config_path.to_string_lossy());
+        }
+        if close {
+            self.show_config_issues = false;
+        }
+    }
+
+    fn rate_hz(&self) -> f64 {
+        self.rate.value()
+    }
+
+    // Rate 的悬停说明（对应配置 rate_mode / rate_window_ms / rate_smoothing_ms）
+    fn rate_description(&self) -> String {
+        let r = self.rate.settings();
+        let mut text = match r.mode {
+            RateMode::Window => format!("最近 {:.1} s 内的脉冲数 / 时长", r.window_s),
+            RateMode::Interval => "最近两个脉冲之间的瞬时间隔".to_string(),
+        };
+        if r.smoothing_s > 0.0 {
+            text.push_str(&format!("，EMA 平滑 {:.1} s", r.smoothing_s));
+        }
+        text
+    }
+
+    // 每帧更新速率；连接中时按固定间隔记入速率曲线
+    fn sample_rate(&mut self) {
+        let t = self.start_time.elapsed().as_secs_f64();
+        let rate = self.rate.update(t);
+        if self.status != ConnectionStatus::Connected
+            || t - self.last_rate_sample < RATE_SAMPLE_INTERVAL
+        {
+            return;
+        }
+        self.last_rate_sample = t;
+        self.rate_points.push(t, rate);
+    }
+
+    fn handle_incoming_line(&mut self, line: &str) {
+        // 处理错误行
+        if line.starts_with("[ERROR]") {
+            self.last_error = Some(line.to_string());
+        }
+
+        let is_live = line.contains("[Live]") || line.contains("[LIVE]");
+
+        if is_live {
+            // ✅ Live 行：实时显示
+            self.last_live_line = Some(line.to_string());
+        } else {
+            // ✅ 非 Live 行：推送到日志
+            self.log_lines.push(line.to_string());
+            if self.log_lines.len() > self.max_log_lines {
+                let overflow = self.log_lines.len() - self.max_log_lines;
+                self.log_lines.drain(0..overflow);
+            }
+            // 回放的内容本来就来自日志文件，不再写回日志
+            if !self.replaying() {
+                self.logger.write_line(line);
+            }
+        }
+
+        // ✅ 更新总数与绘图逻辑
+        let prev_total = self.core.current_total;
+        let change: Change = self.core.process_line(line);
+        self.handle_core_events();
+        if change.total_changed || change.session_reset {
+            self.update_batch();
+        }
+
+        if change.stage_changed {
+            let stage = self.core.stage;
+            self.add_marker(
+                MarkerKind::Stage(stage),
+                format!("S{}", stage),
+                vec![format!("Stage {}（进行中）", stage)],
+            );
+        }
+
+        if change.session_reset {
+            self.rate.reset();
+        }
+        if self.core.current_total > prev_total {
+            self.last_pulse_time = Some(Instant::now());
+            self.rate.record(
+                self.start_time.elapsed().as_secs_f64(),
+                self.core.current_total as i64,
+            );
+        }
+
+        if change.total_changed {
+            let t = self.start_time.elapsed().as_secs_f64();
+
+            if self.core.current_total < prev_total {
+                return;
+            }
+
+            const GAP_THRESHOLD: f64 = 5.0;
+            let mut gap_too_long = false;
+
+            if let Some(last_time) = self.last_pulse_time {
+                let gap = last_time.elapsed().as_secs_f64();
+                if gap > GAP_THRESHOLD {
+                    gap_too_long = true;
+                }
+            }
+
+            if gap_too_long {
+                self.plot_points.push_gap(t);
+            }
+
+            self.plot_points.push(t, self.core.current_total as f64);
+
+            self.last_pulse_time = Some(Instant::now());
+        }
+    }
+
+
+    // 解析出的完整事件分发给各个输出；回放时只更新界面，不写日志 / Event Log / CSV / 数据库
+    fn handle_core_events(&mut self) {
+        let record = !self.replaying();
+        for mut event in self.core.take_events() {
+            match &mut event.kind {
+                CoreEventKind::SystemReset | CoreEventKind::Banner(_) => {
+                    if matches!(event.kind, CoreEventKind::SystemReset) {
+                        self.add_marker(
+                            MarkerKind::Reset,
+                            "Reset".to_string(),
+                            vec!["SYSTEM RESET OK".to_string()],
+                        );
+                    }
+                    self.active_drift = None;
+                    self.intervals.reset();
+                    self.stage_intervals.clear();
+                    self.interval_charts.stale = true;
+                    if record {
+                        self.logger.start_session();
+                    }
+                }
+                CoreEventKind::Live(l) => {
+                    self.intervals
+                        .on_live(self.start_time.elapsed().as_secs_f64(), l);
+                    self.interval_charts.stale = true;
+                    if record {
+                        self.logger.note_activity();
+                    }
+                }
+                CoreEventKind::StageReport(r) => {
+                    // 间隔统计随阶段报告一起写入 JSON / CSV / SQLite
+                    let runs = self.intervals.runs().to_vec();
+                    r.host_intervals = self.intervals.finish_stage(r.stage_id).map(Box::new);
+                    self.interval_charts.stale = true;
+                    if r.host_intervals.is_some() {
+                        self.stage_intervals.push(FinishedIntervals {
+                            stage: r.stage_id,
+                            summary: BoxSummary::from_runs(&runs),
+                            runs,
+                        });
+                        if self.stage_intervals.len() > self.max_stage_stats {
+                            self.stage_intervals.remove(0);
+                        }
+                    }
+                    self.stage_stats.push(r.clone());
+                    if self.stage_stats.len() > self.max_stage_stats {
+                        self.stage_stats.remove(0);
+                    }
+                    self.mark_stage_report(r);
+                    if record {
+                        self.logger.note_activity();
+                    }
+                }
+                CoreEventKind::TotalSummary(s) => {
+                    self.mark_summary(s);
+                    self.check_active_drift(s);
+                    if record {
+                        self.logger.end_session(s);
+                    }
+                }
+                CoreEventKind::ConsistencyWarning(w) => {
+                    self.push_record(format!("[WARN] {}", w.message()))
+                }
+                _ => {}
+            }
+            let transitions = self.alarms.on_event(
+                self.start_time.elapsed().as_secs_f64(),
+                self.clock.now().wall,
+                &event.kind,
+            );
+            self.apply_alarm_transitions(transitions);
+            if !record {
+                continue;
+            }
+            if let Some(w) = &self.event_log {
+                w.write_event(&event);
+            }
+            self.csv.record(self.clock.now().wall.naive_local(), &event);
+            if let Some(store) = &self.store {
+                store.record(event);
+            }
+        }
+
+        let errors = self.store.as_ref().map(|s| s.take_errors()).unwrap_or_default();
+        for e in errors {
+            self.push_log(format!("[DB] {}", e));
+        }
+    }
+
+    // 持续型报警（Rate、无脉冲）按时间评估；未连接或回放时暂停计时。未确认的响铃报警定时重复响铃
+    fn check_alarms(&mut self) {
+        const ALARM_BEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+        // 回放加速了时间，Rate / 无脉冲时长都不是实际的，持续型规则暂停
+        let rate = (self.status == ConnectionStatus::Connected && !self.replaying())
+            .then(|| self.rate_hz());
+        let transitions = self.alarms.tick(
+            self.start_time.elapsed().as_secs_f64(),
+            self.clock.now().wall,
+            rate,
+        );
+        self.apply_alarm_transitions(transitions);
+
+        if self.alarms.needs_sound()
+            && self
+                .last_alarm_beep
+                .is_none_or(|t| t.elapsed() >= ALARM_BEEP_INTERVAL)
+        {
+            self.last_alarm_beep = Some(Instant::now());
+            beep();
+        }
+    }
+
+    fn apply_alarm_transitions(&mut self, transitions: Vec<AlarmTransition>) {
+        for t in transitions {
+            if matches!(t, AlarmTransition::Raised { sound: true, .. }) {
+                // 新报警立即响，不等重复间隔
+                self.last_alarm_beep = None;
+            }
+            self.push_record(t.log_line());
+        }
+    }
+
+    // 顶部报警横幅：未确认时闪烁；每条可确认 / 清除
+    fn ui_alarm_banner(&mut self, ctx: &egui::Context) {
+        if self.alarms.alarms().is_empty() {
+            return;
+        }
+
+        let unacked = self.alarms.alarms().iter().any(|a| !a.acknowledged);
+        let flash = unacked && (self.start_time.elapsed().as_millis() / 500).is_multiple_of(2);
+        let fill = if flash {
+            Color32::from_rgb(200, 40, 40)
+        } else {
+            Color32::from_rgb(120, 30, 30)
+        };
+
+        let mut ack: Option<usize> = None;
+        let mut clear: Option<usize> = None;
+        let mut ack_all = false;
+        let mut clear_all = false;
+
+        egui::TopBottomPanel::top("alarm_banner")
+            .frame(egui::Frame::new().fill(fill).inner_margin(6))
+            .show(ctx, |ui| {
+                for (i, a) in self.alarms.alarms().iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let mut text = format!(
+                            "⚠ {}  {}: {}",
+                            a.raised_at.format("%H:%M:%S"),
+                            a.name,
+                            a.message
+                        );
+                        if a.returned {
+                            text.push_str("（已恢复）");
+                        }
+                        if a.acknowledged {
+                            text.push_str("（已确认）");
+                        }
+                        let label =
+                            ui.label(egui::RichText::new(text).strong().color(Color32::WHITE));
+                        if let Some(rule) = self.alarms.rule_of(a) {
+                            label.on_hover_text(rule.describe());
+                        }
+
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            if ui.button("Clear").clicked() {
+                                clear = Some(i);
+                            }
+                            if !a.acknowledged && ui.button("Acknowledge").clicked() {
+                                ack = Some(i);
+                            }
+                        });
+                    });
+                }
+                if self.alarms.alarms().len() > 1 {
+                    ui.horizontal(|ui| {
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            if ui.button("Clear all").clicked() {
+                                clear_all = true;
+                            }
+                            if unacked && ui.button("Acknowledge all").clicked() {
+                                ack_all = true;
+                            }
+                        });
+                    });
+                }
+            });
+
+        let now_s = self.start_time.elapsed().as_secs_f64();
+        let mut transitions = Vec::new();
+        if ack_all {
+            let n = self.alarms.alarms().len();
+            transitions.extend((0..n).filter_map(|i| self.alarms.acknowledge(i)));
+        }
+        if clear_all {
+            while let Some(t) = self.alarms.clear(0, now_s) {
+                transitions.push(t);
+            }
+        }
+        transitions.extend(ack.and_then(|i| self.alarms.acknowledge(i)));
+        transitions.extend(clear.and_then(|i| self.alarms.clear(i, now_s)));
+        self.apply_alarm_transitions(transitions);
+    }
+
+    fn start_batch(&mut self) {
+        let target = match self.batch_target_text.trim().parse::<u64>() {
+            Ok(t) if t > 0 => t,
+            _ => {
+                self.push_record(format!(
+                    "[BATCH] 目标弧数无效: `{}`",
+                    self.batch_target_text.trim()
+                ));
+                return;
+            }
+        };
+        let total = self.core.current_total;
+        self.batch = Some(BatchRun::start(target, total, self.clock.now().wall));
+        self.push_record(format!(
+            "[BATCH] 开始：目标 {} arcs（当前 Total {}）",
+            target, total
+        ));
+    }
+
+    fn cancel_batch(&mut self) {
+        if let Some(b) = self.batch.take() {
+            if b.finished.is_none() {
+                self.push_record(format!(
+                    "[BATCH] 取消：已完成 {} / {} arcs，用时 {}",
+                    b.done,
+                    b.target,
+                    format_duration(b.elapsed_s(self.clock.now().wall))
+                ));
+            }
+        }
+    }
+
+    // Total 变化时累计；刚达到目标：写日志、报警横幅 + 响铃、按配置发停止命令
+    fn update_batch(&mut self) {
+        let wall = self.clock.now().wall;
+        let reached = match self.batch.as_mut() {
+            Some(b) => b.update(self.core.current_total, wall),
+            None => return,
+        };
+        if !reached {
+            return;
+        }
+        let (done, target, elapsed) = match &self.batch {
+            Some(b) => (b.done, b.target, b.elapsed_s(wall)),
+            None => return,
+        };
+
+        let msg = format!(
+            "{} / {} arcs，用时 {}",
+            done,
+            target,
+            format_duration(elapsed)
+        );
+        self.push_record(format!("[BATCH] 完成：{}", msg));
+        let transition = self.alarms.notify("Batch complete", msg, true, wall);
+        self.apply_alarm_transitions(vec![transition]);
+
+        if let (true, Some(cmd)) = (self.batch_send_stop, self.cfg.batch_stop_command.clone()) {
+            match &self.cmd_tx {
+                Some(tx) if tx.send(format!("{}\n", cmd)).is_ok() => {
+                    self.push_record(format!("[BATCH] 已发送停止命令 `{}`", cmd))
+                }
+                _ => self.push_record(format!("[WARN] 停止命令 `{}` 未发送：未连接", cmd)),
+            }
+        }
+    }
+
+    // 左侧批次卡片：未开始时输入目标；进行中显示进度、剩余和预计时间
+    fn ui_batch_panel(&mut self, ui: &mut egui::Ui) {
+        let mut start = false;
+        let mut cancel = false;
+        let rate = self.rate_hz();
+        let now = self.clock.now().wall;
+
+        egui::Frame::new()
+            .fill(Color32::from_rgb(235, 239, 245))
+            .corner_radius(10)
+            .inner_margin(8)
+            .show(ui, |ui| {
+                ui.set_width(200.0);
+                ui.vertical_centered(|ui| {
+                    ui.label(
+                        egui::RichText::new("Batch")
+                            .size(16.0)
+                            .color(Color32::from_rgb(40, 40, 60))
+                            .strong(),
+                    );
+                });
+                ui.add_space(2.0);
+
+                let text_color = Color32::from_rgb(10, 10, 30);
+                match &self.batch {
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new("Target:").color(text_color));
+                            let edit = ui.add(
+                                egui::TextEdit::singleline(&mut self.batch_target_text)
+                                    .hint_text("arcs")
+                                    .desired_width(70.0),
+                            );
+                            let enter =
+                                edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                            if ui.button("Start").clicked() || enter {
+                                start = true;
+                            }
+                        });
+                        if let Some(cmd) = &self.cfg.batch_stop_command {
+                            ui.checkbox(&mut self.batch_send_stop, "Send stop command")
+                                .on_hover_text(format!("达到目标时向 MCU 发送 `{}`", cmd));
+                        }
+                    }
+                    Some(b) => {
+                        ui.add(
+                            egui::ProgressBar::new(b.fraction())
+                                .text(format!("{:.1}%", b.fraction() * 100.0)),
+                        );
+                        let lines = [
+                            format!("Done: {} / {}", b.done, b.target),
+                            format!("Remaining: {} arcs", b.remaining()),
+                            match (b.finished, b.eta_s(rate)) {
+                                (Some(at), _) => {
+                                    format!("Finished: {}", at.format("%H:%M:%S"))
+                                }
+                                (None, Some(eta)) => format!("ETA: {}", format_duration(eta)),
+                                (None, None) => "ETA: -".to_string(),
+                            },
+                            format!("Elapsed: {}", format_duration(b.elapsed_s(now))),
+                        ];
+                        for line in lines {
+                            ui.label(egui::RichText::new(line).monospace().color(text_color));
+                        }
+                        let label = if b.finished.is_some() {
+                            "New batch"
+                        } else {
+                            "Cancel"
+                        };
+                        if ui.button(label).clicked() {
+                            cancel = true;
+                        }
+                    }
+                }
+            });
 
-        self.line_rx = Some(rx_line);
-        self.cmd_tx = Some(tx_cmd);
-        self.status = ConnectionStatus::Connected;
-        self.last_error = None;
+        if start {
+            self.start_batch();
+        }
+        if cancel {
+            self.cancel_batch();
+        }
     }
 
-    fn disconnect(&mut self) {
-        self.status = ConnectionStatus::Disconnected;
-        self.line_rx = None;
-        self.cmd_tx = None;
+    fn add_marker(&mut self, kind: MarkerKind, label: String, detail: Vec<String>) {
+        self.markers.push(PlotMarker {
+            t: self.start_time.elapsed().as_secs_f64(),
+            kind,
+            label,
+            detail,
+        });
+        if self.markers.len() > self.max_markers {
+            let overflow = self.markers.len() - self.max_markers;
+            self.markers.drain(0..overflow);
+        }
     }
 
-    fn send_reset(&mut self) {
-        if let Some(tx) = &self.cmd_tx {
-            let _ = tx.send("R\n".to_string());
+    // 阶段报告的数字挂到该阶段开始处的标记上；没见到开始（中途连上、单脉冲）时在报告处补一个
+    fn mark_stage_report(&mut self, r: &StageReport) {
+        let opt = |v: Option<f64>| {
+            v.map(|v| format!("{:.1}", v))
+                .unwrap_or_else(|| "-".to_string())
+        };
+        let stage = r.stage_id.unwrap_or(self.core.stage);
+        let mut detail = vec![
+            format!("Stage {}：{}", stage, r.status.as_deref().unwrap_or("-")),
+            format!(
+                "Arcs: {}",
+                r.arcs
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "-".to_string())
+            ),
+            format!("Duration: {} ms", opt(r.duration_ms)),
+            format!(
+                "Interval: {} ~ {} ms",
+                opt(r.min_interval_ms),
+                opt(r.max_interval_ms)
+            ),
+        ];
+        if let Some(h) = &r.host_intervals {
+            detail.push(format!(
+                "主机统计: n={} 均值 {:.1} ms，P95 {:.1} ms",
+                h.count, h.mean_ms, h.p95_ms
+            ));
+        }
+
+        // 只在本 session 内找（遇到汇总 / 复位就停）
+        let found = self
+            .markers
+            .iter_mut()
+            .rev()
+            .take_while(|m| {
+                !matches!(
+                    m.kind,
+                    MarkerKind::Summary | MarkerKind::Timeout | MarkerKind::Reset
+                )
+            })
+            .find(|m| m.kind == MarkerKind::Stage(stage));
+        match found {
+            Some(m) => m.detail = detail,
+            None => self.add_marker(MarkerKind::Stage(stage), format!("S{}", stage), detail),
         }
     }
 
-    fn open_logs_folder(&self) {
-        let folder = self.cfg.log_folder.clone();
+    fn mark_summary(&mut self, s: &TotalSummary) {
+        let status = s.status.as_deref().unwrap_or("-");
+        let kind = if status.contains("Timeout") {
+            MarkerKind::Timeout
+        } else {
+            MarkerKind::Summary
+        };
+        let detail = vec![
+            format!("Status: {}", status),
+            format!(
+                "Grand Total: {}",
+                s.grand_total
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "-".to_string())
+            ),
+            format!(
+                "Stages: {}",
+                s.total_stages
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "-".to_string())
+            ),
+            format!(
+                "Active Time: {} s",
+                s.active_time_s
+                    .map(|v| format!("{:.3}", v))
+                    .unwrap_or_else(|| "-".to_string())
+            ),
+            format!(
+                "Avg Frequency: {} Hz",
+                s.avg_frequency_hz
+                    .map(|v| format!("{:.2}", v))
+                    .unwrap_or_else(|| "-".to_string())
+            ),
+        ];
+        self.add_marker(kind, kind.legend().to_string(), detail);
+    }
 
-        #[cfg(target_os = "windows")]
-        {
-            let _ = Command::new("explorer").arg(folder).spawn();
-        }
-        #[cfg(target_os = "linux")]
-        {
-            let _ = Command::new("xdg-open").arg(folder).spawn();
+    // 用户注释：画在当前时刻，写进文本日志（随 session 文件保存）和数据库
+    fn add_note(&mut self) {
+        let text = self.note_text.trim().to_string();
+        if text.is_empty() {
+            return;
         }
-        #[cfg(target_os = "macos")]
-        {
-            let _ = Command::new("open").arg(folder).spawn();
+        self.add_marker(MarkerKind::Note, note_label(&text), vec![text.clone()]);
+        self.push_record(format!("[NOTE] {}", text));
+        if let Some(store) = self.store.as_ref().filter(|_| !self.replaying()) {
+            store.annotate(&text);
         }
+        self.note_text.clear();
     }
 
-    fn rate_hz(&self) -> f64 {
-        if let Some(wait_ms) = self.last_wait_ms {
-            if wait_ms.is_finite() && wait_ms > 0.0 {
-                let rate = 1000.0 / wait_ms;
-                // 限制到合理范围
-                if (1.0..=1000.0).contains(&rate) {
-                    rate
-                } else {
-                    0.0
-                }
-            } else {
-                0.0
+    fn ui_note_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Note:");
+            let edit = ui.add(
+                egui::TextEdit::singleline(&mut self.note_text)
+                    .hint_text("在当前时刻加注释，回车添加")
+                    .desired_width(280.0),
+            );
+            let enter = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Add").clicked() || enter {
+                self.add_note();
             }
-        } else {
-            0.0
-        }
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui.button("Export plot...").clicked() {
+                    self.open_plot_export_dialog();
+                }
+            });
+        });
     }
 
-    fn handle_incoming_line(&mut self, line: &str) {
-        // 处理错误行
-        if line.starts_with("[ERROR]") {
-            self.last_error = Some(line.to_string());
+    fn check_active_drift(&mut self, s: &TotalSummary) {
+        let (drift, summed) = match (s.active_time_drift_s(), s.summed_duration_s) {
+            (Some(d), Some(sum)) => (d, sum),
+            _ => {
+                self.active_drift = None;
+                return;
+            }
+        };
+        let tolerance_s = self.cfg.active_time_tolerance_ms as f64 / 1000.0;
+        if drift.abs() <= tolerance_s {
+            self.active_drift = None;
+            return;
         }
+        let msg = format!(
+            "Active Time 与 Duration 之和相差 {:+.3} s（Σ Duration {:.3} s，容差 {:.3} s）",
+            drift, summed, tolerance_s
+        );
+        self.push_record(format!("[WARN] {}", msg));
+        self.active_drift = Some(msg);
+    }
 
-        let is_live = line.contains("[Live]") || line.contains("[LIVE]");
+    // 每个阶段：MCU 报告的数字 + 主机由 Live 计数推出的间隔统计（最新的在上）
+    fn ui_stage_stats(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("Stage Statistics ({})", self.stage_stats.len()))
+            .default_open(true)
+            .show(ui, |ui| {
+                if self.stage_stats.is_empty() {
+                    ui.label("还没有阶段报告");
+                    return;
+                }
+                let ms = |v: Option<f64>| {
+                    v.map(|v| format!("{:.1}", v))
+                        .unwrap_or_else(|| "-".to_string())
+                };
 
-        if is_live {
-            // ✅ Live 行：实时显示 + 更新频率
-            self.last_live_line = Some(line.to_string());
+                egui::ScrollArea::both().max_height(180.0).show(ui, |ui| {
+                    egui::Grid::new("stage_stats_grid")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for h in [
+                                "Stage",
+                                "Arcs",
+                                "n",
+                                "Mean",
+                                "Std",
+                                "Median",
+                                "P95",
+                                "P99",
+                                "Min (host/MCU)",
+                                "Max (host/MCU)",
+                            ] {
+                                ui.strong(h);
+                            }
+                            ui.end_row();
+
+                            for r in self.stage_stats.iter().rev() {
+                                let h = r.host_intervals.as_ref();
+                                ui.label(
+                                    r.stage_id
+                                        .map(|v| v.to_string())
+                                        .unwrap_or_else(|| "-".to_string()),
+                                );
+                                ui.label(
+                                    r.arcs
+                                        .map(|v| v.to_string())
+                                        .unwrap_or_else(|| "-".to_string()),
+                                );
+                                let n = ui.label(
+                                    h.map(|h| h.count.to_string())
+                                        .unwrap_or_else(|| "-".to_string()),
+                                );
+                                if let Some(h) = h {
+                                    n.on_hover_ui(|ui| {
+                                        for (i, count) in h.histogram.iter().enumerate() {
+                                            ui.monospace(format!(
+                                                "{:>9} {}",
+                                                histogram_label(i),
+                                                count
+                                            ));
+                                        }
+                                    });
+                                }
+                                ui.label(ms(h.map(|h| h.mean_ms)));
+                                ui.label(ms(h.map(|h| h.std_ms)));
+                                ui.label(ms(h.map(|h| h.median_ms)));
+                                ui.label(ms(h.map(|h| h.p95_ms)));
+                                ui.label(ms(h.map(|h| h.p99_ms)));
+                                ui.label(format!(
+                                    "{} / {}",
+                                    ms(h.map(|h| h.min_ms)),
+                                    ms(r.min_interval_ms)
+                                ));
+                                ui.label(format!(
+                                    "{} / {}",
+                                    ms(h.map(|h| h.max_ms)),
+                                    ms(r.max_interval_ms)
+                                ));
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+    }
 
-            if let Some(wait_ms) = Self::parse_wait_ms_from_live(line) {
-                // 第一次解析，先存，不立刻显示
-                if self.prev_wait_ms.is_none() {
-                    self.prev_wait_ms = Some(wait_ms);
-                    self.last_wait_ms = None;
-                } else {
-                    let avg = (self.prev_wait_ms.unwrap() + wait_ms) / 2.0;
-                    self.last_wait_ms = Some(avg);
-                    self.prev_wait_ms = Some(wait_ms);
+    // 绘图区标题行：标签页 + 右侧 Rate
+    fn ui_plot_tabs(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for (tab, name) in [
+                (PlotTab::Total, "Total Timeline"),
+                (PlotTab::Rate, "Rate"),
+                (PlotTab::Histogram, "Interval Histogram"),
+                (PlotTab::BoxPlot, "Stage Box Plot"),
+            ] {
+                ui.selectable_value(&mut self.plot_tab, tab, egui::RichText::new(name).strong());
+            }
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                ui.label(
+                    egui::RichText::new(format!("Rate: {:.2} pulses/s", self.rate_hz()))
+                        .monospace(),
+                )
+                .on_hover_text(self.rate_description());
+                if matches!(self.plot_tab, PlotTab::Total | PlotTab::Rate) {
+                    ui.checkbox(&mut self.clock_axis, "Clock time")
+                        .on_hover_text("横轴显示本地时间（HH:MM:SS）");
                 }
+            });
+        });
+
+        ui.add_space(4.0);
+
+        match self.plot_tab {
+            PlotTab::Total => self.ui_total_plot(ui),
+            PlotTab::Rate => self.ui_rate_plot(ui),
+            PlotTab::Histogram => {
+                self.refresh_interval_charts();
+                self.ui_interval_histogram(ui)
             }
-        } else {
-            // ✅ 非 Live 行：推送到日志
-            self.log_lines.push(line.to_string());
-            if self.log_lines.len() > self.max_log_lines {
-                let overflow = self.log_lines.len() - self.max_log_lines;
-                self.log_lines.drain(0..overflow);
+            PlotTab::BoxPlot => {
+                self.refresh_interval_charts();
+                self.ui_interval_box_plot(ui)
             }
-            self.logger.write_line(line);
         }
-
-        // ✅ 更新总数与绘图逻辑
-        let prev_total = self.core.current_total;
-        let change: Change = self.core.process_line(line);
-
-        if self.core.current_total > prev_total {
-            self.last_pulse_time = Some(Instant::now());
+        if matches!(self.plot_tab, PlotTab::Total | PlotTab::Rate) {
+            self.ui_note_input(ui);
         }
+    }
 
-        if change.total_changed {
-            let t = self.start_time.elapsed().as_secs_f64();
+    // Total 和 Rate 同属 "timeline" 组：切换标签页时保持同一段时间
+    fn timeline_plot(&self, id: &str) -> Plot<'static> {
+        let plot = Plot::new(id)
+            .height(260.0)
+            .legend(Legend::default())
+            .link_axis("timeline", Vec2b::new(true, false));
+        if !self.clock_axis {
+            return plot;
+        }
 
-            if self.core.current_total < prev_total {
-                return;
+        let start = self.start_wall;
+        plot.x_axis_formatter(move |mark, _| {
+            wall_at(start, mark.value)
+                .map(|t| t.format("%H:%M:%S").to_string())
+                .unwrap_or_default()
+        })
+        .x_grid_spacer(clock_grid_spacer(start))
+        .label_formatter(move |name, p| {
+            let ts = wall_at(start, p.x)
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+                .unwrap_or_default();
+            if name.is_empty() {
+                format!("{}\ny = {:.2}", ts, p.y)
+            } else {
+                format!("{}\n{}\ny = {:.2}", name, ts, p.y)
             }
+        })
+    }
 
-            const GAP_THRESHOLD: f64 = 5.0;
-            let mut gap_too_long = false;
+    fn ui_total_plot(&mut self, ui: &mut egui::Ui) {
+        let resp = self
+            .timeline_plot("pulse_plot")
+            .show(ui, |plot_ui| {
+                if !self.plot_points.is_empty() {
+                    let points: PlotPoints = visible_points(plot_ui, &self.plot_points).into();
+                    let line = Line::new("Total", points)
+                        .color(Color32::from_rgb(120, 180, 255))
+                        .width(2.0);
+                    plot_ui.line(line);
+                }
+                draw_markers(plot_ui, &self.markers)
+            });
+        let b = resp.transform.bounds();
+        self.timeline_view = Some((b.min()[0], b.max()[0]));
+        if let Some(i) = resp.inner {
+            show_marker_detail(resp.response, &self.markers[i]);
+        }
+    }
 
-            if let Some(last_time) = self.last_pulse_time {
-                let gap = last_time.elapsed().as_secs_f64();
-                if gap > GAP_THRESHOLD {
-                    gap_too_long = true;
+    fn ui_rate_plot(&mut self, ui: &mut egui::Ui) {
+        let resp = self
+            .timeline_plot("rate_plot")
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                if !self.rate_points.is_empty() {
+                    let points: PlotPoints = visible_points(plot_ui, &self.rate_points).into();
+                    let line = Line::new("Rate (pulses/s)", points)
+                        .color(Color32::from_rgb(255, 190, 90))
+                        .width(2.0);
+                    plot_ui.line(line);
                 }
-            }
+                draw_markers(plot_ui, &self.markers)
+            });
+        let b = resp.transform.bounds();
+        self.timeline_view = Some((b.min()[0], b.max()[0]));
+        if let Some(i) = resp.inner {
+            show_marker_detail(resp.response, &self.markers[i]);
+        }
+    }
 
-            if gap_too_long {
-                self.plot_points.push([t, f64::NAN]);
-            }
+    // 间隔有变化后第一次显示直方图 / 箱线图时重算：本 session 所有间隔的统计和当前阶段的箱
+    fn refresh_interval_charts(&mut self) {
+        if !self.interval_charts.stale {
+            return;
+        }
+        let mut all: Vec<IntervalRun> = self
+            .stage_intervals
+            .iter()
+            .flat_map(|f| f.runs.iter().copied())
+            .collect();
+        all.extend_from_slice(self.intervals.runs());
+        self.interval_charts = IntervalCharts {
+            stale: false,
+            session: IntervalStats::from_runs(&all),
+            current: BoxSummary::from_runs(self.intervals.runs()),
+        };
+    }
 
-            self.plot_points.push([t, self.core.current_total as f64]);
+    // 分档与阶段统计的直方图一致（HISTOGRAM_EDGES_MS），x 为档位序号
+    fn ui_interval_histogram(&self, ui: &mut egui::Ui) {
+        let stats = self.interval_charts.session.as_ref();
+        match stats {
+            Some(s) => ui.label(format!(
+                "本 session {} 个间隔：均值 {:.1} ms，中位数 {:.1} ms，P95 {:.1} ms",
+                s.count, s.mean_ms, s.median_ms, s.p95_ms
+            )),
+            None => ui.label("本 session 还没有脉冲间隔"),
+        };
+
+        let bars: Vec<Bar> = stats
+            .map(|s| {
+                s.histogram
+                    .iter()
+                    .enumerate()
+                    .map(|(i, count)| Bar::new(i as f64, *count as f64).name(histogram_label(i)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let bins = HISTOGRAM_EDGES_MS.len() + 1;
+
+        Plot::new("interval_histogram")
+            .height(240.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .include_x(-0.5)
+            .include_x(bins as f64 - 0.5)
+            .include_y(0.0)
+            .x_axis_formatter(move |mark, _| {
+                let i = mark.value.round();
+                if (mark.value - i).abs() < 1e-6 && i >= 0.0 && (i as usize) < bins {
+                    histogram_label(i as usize)
+                } else {
+                    String::new()
+                }
+            })
+            .show(ui, |plot_ui| {
+                let chart = BarChart::new("Intervals", bars)
+                    .width(0.8)
+                    .color(Color32::from_rgb(120, 180, 255))
+                    .element_formatter(Box::new(|bar, _| format!("{}: {}", bar.name, bar.value)));
+                plot_ui.bar_chart(chart);
+            });
+    }
 
-            if self.plot_points.len() > self.max_plot_points {
-                let overflow = self.plot_points.len() - self.max_plot_points;
-                self.plot_points.drain(0..overflow);
+    // 每个阶段一个箱：x 为阶段号（没有阶段号时用序号），当前阶段用另一种颜色
+    fn ui_interval_box_plot(&self, ui: &mut egui::Ui) {
+        let mut finished = Vec::new();
+        for (i, f) in self.stage_intervals.iter().enumerate() {
+            if let Some(b) = f.summary {
+                let x = f.stage.unwrap_or(i as i32 + 1) as f64;
+                finished.push(box_elem(x, b, f.stage, run_count(&f.runs)));
             }
+        }
+        let current = self.interval_charts.current.map(|b| {
+            let stage = self.intervals.stage();
+            let x = stage.unwrap_or(self.stage_intervals.len() as i32 + 1) as f64;
+            box_elem(x, b, stage, run_count(self.intervals.runs()))
+        });
 
-            self.last_pulse_time = Some(Instant::now());
+        if finished.is_empty() && current.is_none() {
+            ui.label("本 session 还没有脉冲间隔");
         }
-    }
 
+        Plot::new("interval_box_plot")
+            .height(240.0)
+            .legend(Legend::default())
+            .include_y(0.0)
+            .y_axis_label("ms")
+            .show(ui, |plot_ui| {
+                if !finished.is_empty() {
+                    plot_ui.box_plot(
+                        BoxPlot::new("Finished stages", finished)
+                            .color(Color32::from_rgb(120, 180, 255))
+                            .element_formatter(Box::new(box_label)),
+                    );
+                }
+                if let Some(current) = current {
+                    plot_ui.box_plot(
+                        BoxPlot::new("Current stage", vec![current])
+                            .color(Color32::from_rgb(255, 190, 90))
+                            .element_formatter(Box::new(box_label)),
+                    );
+                }
+            });
+    }
 
     // 顶部 RUN 小灯
     fn draw_run_led(&self, ui: &mut egui::Ui, on: bool) {
@@ -676,7 +2399,7 @@ impl DhjcApp {
     }
 
     // 左侧卡片区域
-        fn ui_stats_panel(&self, ui: &mut egui::Ui) {
+        fn ui_stats_panel(&mut self, ui: &mut egui::Ui) {
         // 左侧区域大致宽度
         ui.set_width(220.0);
 
@@ -705,12 +2428,26 @@ impl DhjcApp {
         );
         ui.add_space(6.0);
 
+        self.ui_batch_panel(ui);
+        ui.add_space(6.0);
+
         self.stat_card(
             ui,
             "Active Time",
             format!("{:.3}", self.core.active_time_s),
             "s",
         );
+        if let Some(msg) = &self.active_drift {
+            ui.add_space(2.0);
+            ui.add(
+                egui::Label::new(
+                    egui::RichText::new(msg)
+                        .size(12.0)
+                        .color(Color32::from_rgb(230, 150, 40)),
+                )
+                .wrap(),
+            );
+        }
         ui.add_space(6.0);
 
         let ts = self.core.last_timestamp.as_deref().unwrap_or("N/A");
@@ -802,6 +2539,9 @@ impl eframe::App for DhjcApp {
         if self.status == ConnectionStatus::Connected {
         self.line_rx = temp_rx;
         }
+        self.poll_logger();
+        self.sample_rate();
+        self.check_alarms();
         ctx.request_repaint_after(Duration::from_millis(50));
 
         // 2. 顶部两行
@@ -821,6 +2561,36 @@ impl eframe::App for DhjcApp {
 
                     ui.add_space(6.0);
 
+                    if ui
+                        .button("History...")
+                        .on_hover_text("浏览历史日志中的阶段报告和汇总")
+                        .clicked()
+                    {
+                        self.open_history();
+                    }
+
+                    ui.add_space(6.0);
+
+                    if ui
+                        .button("Export...")
+                        .on_hover_text("把历史日志按日期范围导出为 CSV")
+                        .clicked()
+                    {
+                        self.open_export_dialog();
+                    }
+
+                    ui.add_space(6.0);
+
+                    if ui
+                        .button("Save")
+                        .on_hover_text("保存当前设置到配置文件")
+                        .clicked()
+                    {
+                        self.save_settings();
+                    }
+
+                    ui.add_space(6.0);
+
                     let top_fill = if self.always_on_top {
                         Color32::from_rgb(255, 210, 80)
                     } else {
@@ -855,16 +2625,41 @@ impl eframe::App for DhjcApp {
             cols[0].horizontal(|ui| {
                 // 这一块配置在连接后变灰，不可编辑
                 ui.add_enabled_ui(!is_connected, |ui| {
+                    // Profile：配置文件里有 [profile.xxx] 时才显示
+                    if !self.cfg.profile_names.is_empty() {
+                        let mut selected = self.cfg.profile.clone();
+                        ui.label(egui::RichText::new("Profile:").strong());
+                        egui::ComboBox::from_id_salt("profile_combo")
+                            .selected_text(selected.as_deref().unwrap_or("(default)"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut selected, None, "(default)");
+                                for name in &self.cfg.profile_names {
+                                    ui.selectable_value(
+                                        &mut selected,
+                                        Some(name.clone()),
+                                        name.as_str(),
+                                    );
+                                }
+                            });
+                        if selected != self.cfg.profile {
+                            self.apply_profile(ctx, selected);
+                        }
+
+                        ui.add_space(8.0);
+                    }
+
                     // Mode: 加粗
                     ui.label(egui::RichText::new("Mode:").strong());
                     egui::ComboBox::from_id_source("mode_combo")
                         .selected_text(match self.mode {
                             ConnectionMode::Serial => "Serial",
                             ConnectionMode::Tcp => "TCP",
+                            ConnectionMode::Replay => "Replay",
                         })
                         .show_ui(ui, |ui| {
                             ui.selectable_value(&mut self.mode, ConnectionMode::Serial, "Serial");
                             ui.selectable_value(&mut self.mode, ConnectionMode::Tcp, "TCP");
+                            ui.selectable_value(&mut self.mode, ConnectionMode::Replay, "Replay");
                         });
 
                     ui.add_space(8.0);
@@ -906,6 +2701,14 @@ impl eframe::App for DhjcApp {
                                     .horizontal_align(egui::Align::Center),
                             );
                         }
+                        ConnectionMode::Replay => {
+                            // 回放：日志文件路径
+                            ui.label(egui::RichText::new("File:").strong());
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.replay_file_text)
+                                    .desired_width(200.0),
+                            );
+                        }
                     }
                 });
 
@@ -950,6 +2753,14 @@ impl eframe::App for DhjcApp {
             }
         });
 
+        self.ui_alarm_banner(ctx);
+        self.poll_config_file(ctx);
+        self.ui_config_issues(ctx);
+        self.ui_reconnect_prompt(ctx);
+        self.ui_export_dialog(ctx);
+        self.ui_plot_export_dialog(ctx);
+        self.ui_history_browser(ctx);
+
         // 3. 底部 Event Log（固定）
         egui::TopBottomPanel::bottom("log_panel")
         .resizable(false)
@@ -1045,56 +2856,387 @@ impl eframe::App for DhjcApp {
 
                 ui.add_space(6.0);
 
-                // 标题 + Rate
-                ui.horizontal(|ui| {
-                ui.label(egui::RichText::new("Total Timeline").strong());
-                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-                    ui.label(
-                        egui::RichText::new(format!("Rate: {:.2} pulses/s", self.rate_hz()))
-                            .monospace(),
-                    );
-                });
+                // 标签页 + Rate + 当前标签页的图
+                self.ui_plot_tabs(ui);
+
+                ui.add_space(6.0);
+                self.ui_stage_stats(ui);
             });
+        });
+    }
+}
 
-                ui.add_space(4.0);
+// 画出标记（竖线 + 顶部标签），返回鼠标附近的那个
+fn draw_markers(plot_ui: &mut egui_plot::PlotUi, markers: &[PlotMarker]) -> Option<usize> {
+    const HOVER_PX: f32 = 5.0;
+    let top = plot_ui.plot_bounds().max()[1];
+    let pointer_x = plot_ui.response().hover_pos().map(|p| p.x);
+    let mut hovered: Option<(usize, f32)> = None;
+
+    for (i, m) in markers.iter().enumerate() {
+        let color = m.kind.color();
+        plot_ui.vline(
+            VLine::new(m.kind.legend(), m.t)
+                .color(color)
+                .width(1.0)
+                .style(LineStyle::dashed_loose()),
+        );
+        plot_ui.text(
+            Text::new(m.kind.legend(), PlotPoint::new(m.t, top), m.label.as_str())
+                .color(color)
+                .anchor(egui::Align2::LEFT_TOP),
+        );
+
+        if let Some(px) = pointer_x {
+            let dist = (plot_ui.screen_from_plot(PlotPoint::new(m.t, top)).x - px).abs();
+            if dist <= HOVER_PX && hovered.is_none_or(|(_, d)| dist < d) {
+                hovered = Some((i, dist));
+            }
+        }
+    }
+    hovered.map(|(i, _)| i)
+}
+
+// 当前缩放下可见的点，抽稀到约每像素两个点；自动范围时取整个 session
+fn visible_points(plot_ui: &egui_plot::PlotUi, series: &PlotSeries) -> Vec<[f64; 2]> {
+    let range = if plot_ui.auto_bounds().x {
+        None
+    } else {
+        let b = plot_ui.plot_bounds();
+        Some((b.min()[0], b.max()[0]))
+    };
+    let max_points = (plot_ui.response().rect.width() * 2.0).max(200.0) as usize;
+    series.view(range, max_points)
+}
+
+// 曲线横轴 x（start_time 以来的秒数）对应的本地时间；缩得太小超出 chrono 范围时为 None
+fn wall_at(start: DateTime<Local>, x: f64) -> Option<DateTime<Local>> {
+    let ms = (x * 1000.0).round();
+    if !ms.is_finite() {
+        return None;
+    }
+    TimeDelta::try_milliseconds(ms as i64).and_then(|d| start.checked_add_signed(d))
+}
+
+// 时钟横轴的网格：按本地时间的整秒 / 整分 / 整点对齐，取三档由细到粗
+fn clock_grid_spacer(start: DateTime<Local>) -> impl Fn(GridInput) -> Vec<GridMark> {
+    // 当天 0 点以来的秒数，加到 x 上再按步长取整
+    let offset =
+        start.time().num_seconds_from_midnight() as f64 + start.nanosecond() as f64 / 1e9;
+
+    move |input: GridInput| {
+        // 步长超过一天时不再按时钟对齐，否则缩得很小时一天一条线会生成海量刻度
+        let first = match CLOCK_STEPS_S
+            .iter()
+            .position(|s| *s >= input.base_step_size)
+        {
+            Some(i) => i,
+            None => return egui_plot::log_grid_spacer(10)(input),
+        };
+        let mut marks: Vec<GridMark> = Vec::new();
+        // 先放粗的：稳定排序后去重，重合的位置保留粗线
+        for &step in CLOCK_STEPS_S[first..].iter().take(3).rev() {
+            let lo = ((input.bounds.0 + offset) / step).ceil() as i64;
+            let hi = ((input.bounds.1 + offset) / step).floor() as i64;
+            marks.extend((lo..=hi).map(|k| GridMark {
+                value: k as f64 * step - offset,
+                step_size: step,
+            }));
+        }
+        marks.sort_by(|a, b| a.value.total_cmp(&b.value));
+        marks.dedup_by(|m, kept| (m.value - kept.value).abs() < 1e-6);
+        marks
+    }
+}
 
-                Plot::new("pulse_plot")
-                    .height(260.0)
-                    .legend(Legend::default())
-                    .show(ui, |plot_ui| {
-                        if !self.plot_points.is_empty() {
-                            let points: PlotPoints = self
-                                .plot_points
-                                .iter()
-                                .copied()
-                                .collect();
-                            let line = Line::new("Total", points)
-                                .color(Color32::from_rgb(120, 180, 255))
-                                .width(2.0);
-                            plot_ui.line(line);
+// 注释在图上只显示开头几个字，全文在悬停里
+fn note_label(text: &str) -> String {
+    text.chars().take(16).collect()
+}
+
+fn show_marker_detail(response: egui::Response, marker: &PlotMarker) {
+    response.on_hover_ui_at_pointer(|ui| {
+        for line in &marker.detail {
+            ui.label(line);
+        }
+    });
+}
+
+// 箱线图中的一个阶段
+fn box_elem(x: f64, b: BoxSummary, stage: Option<i32>, count: usize) -> BoxElem {
+    let name = match stage {
+        Some(id) => format!("Stage {} (n={})", id, count),
+        None => format!("Stage ? (n={})", count),
+    };
+    BoxElem::new(
+        x,
+        BoxSpread::new(b.lower_whisker, b.q1, b.median, b.q3, b.upper_whisker),
+    )
+    .name(name)
+    .box_width(0.6)
+    .whisker_width(0.4)
+}
+
+fn box_label(b: &BoxElem, _: &BoxPlot) -> String {
+    let s = &b.spread;
+    format!(
+        "{}\nMax {:.1} ms\nQ3 {:.1} ms\nMedian {:.1} ms\nQ1 {:.1} ms\nMin {:.1} ms",
+        b.name, s.upper_whisker, s.quartile3, s.median, s.quartile1, s.lower_whisker
+    )
+}
+
+// 历史日志窗口右侧：总数曲线 + 汇总表 + 阶段报告表
+fn ui_history_data(ui: &mut egui::Ui, data: &HistoryData) {
+    ui.label(format!(
+        "{} 行，{} 个阶段报告，{} 个汇总",
+        data.lines,
+        data.stages.len(),
+        data.summaries.len()
+    ));
+
+    let x_label = if data.curve_in_seconds { "s" } else { "line" };
+    let notes: Vec<PlotMarker> = data
+        .notes
+        .iter()
+        .map(|(x, text)| PlotMarker {
+            t: *x,
+            kind: MarkerKind::Note,
+            label: note_label(text),
+            detail: vec![text.clone()],
+        })
+        .collect();
+    let resp = Plot::new("history_plot")
+        .height(200.0)
+        .legend(Legend::default())
+        .x_axis_label(x_label)
+        .show(ui, |plot_ui| {
+            if !data.curve.is_empty() {
+                let points: PlotPoints = data.curve.iter().copied().collect();
+                let line = Line::new("Total", points)
+                    .color(Color32::from_rgb(120, 180, 255))
+                    .width(2.0);
+                plot_ui.line(line);
+            }
+            draw_markers(plot_ui, &notes)
+        });
+    if let Some(i) = resp.inner {
+        show_marker_detail(resp.response, &notes[i]);
+    }
+
+    let time = |t: &Option<chrono::NaiveDateTime>| {
+        t.map(|t| t.format("%H:%M:%S").to_string())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let opt = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::CollapsingHeader::new(format!("TOTAL SUMMARY ({})", data.summaries.len()))
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::Grid::new("history_summaries")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for h in ["Time", "Status", "Active (s)", "Stages", "Total", "Avg Hz"] {
+                            ui.strong(h);
+                        }
+                        ui.end_row();
+                        for s in &data.summaries {
+                            let v = &s.value;
+                            ui.label(time(&s.at));
+                            ui.label(opt(v.status.clone()));
+                            ui.label(opt(v.active_time_s.map(|x| format!("{:.1}", x))));
+                            ui.label(opt(v.total_stages.map(|x| x.to_string())));
+                            ui.label(opt(v.grand_total.map(|x| x.to_string())));
+                            ui.label(opt(v.avg_frequency_hz.map(|x| format!("{:.2}", x))));
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        egui::CollapsingHeader::new(format!("STAGE REPORT ({})", data.stages.len()))
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::Grid::new("history_stages")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for h in ["Time", "Stage", "Status", "Arcs", "Duration (ms)", "Min/Max (ms)"] {
+                            ui.strong(h);
+                        }
+                        ui.end_row();
+                        for s in &data.stages {
+                            let v = &s.value;
+                            ui.label(time(&s.at));
+                            ui.label(opt(v.stage_id.map(|x| x.to_string())));
+                            ui.label(opt(v.status.clone()));
+                            ui.label(opt(v.arcs.map(|x| x.to_string())));
+                            ui.label(opt(v.duration_ms.map(|x| format!("{:.0}", x))));
+                            ui.label(match (v.min_interval_ms, v.max_interval_ms) {
+                                (Some(a), Some(b)) => format!("{:.0} / {:.0}", a, b),
+                                _ => "N/A".to_string(),
+                            });
+                            ui.end_row();
                         }
                     });
             });
+    });
+}
+
+fn open_event_log(cfg: &AppConfig, clock: Clock) -> Option<EventLogWriter> {
+    if cfg.json_log {
+        Some(EventLogWriter::new(&cfg.log_folder, &cfg.device_id(), clock))
+    } else {
+        None
+    }
+}
+
+// 整个日期范围的日志都要重新解析，放到后台线程
+fn spawn_log_export(
+    log_folder: String,
+    from: NaiveDate,
+    to: NaiveDate,
+    out_dir: PathBuf,
+    device_id: String,
+) -> Receiver<Result<String, String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = export_logs(&log_folder, from, to, &out_dir, &device_id).map(|r| {
+            format!(
+                "已导出 {} 个日志文件：{} 个阶段，{} 个 session\n{}\n{}",
+                r.files,
+                r.stage_rows,
+                r.session_rows,
+                r.stages_path.to_string_lossy(),
+                r.sessions_path.to_string_lossy()
+            )
         });
+        let _ = tx.send(result);
+    });
+    rx
+}
+
+// 导出对话框的后台部分：PNG / SVG 只画当前曲线，CSV 另外带上另一条
+fn save_plot_export(
+    mut export: PlotExport,
+    other: ExportSeries,
+    stem: &str,
+    out_dir: &str,
+    formats: ExportFormats,
+    size: (u32, u32),
+) -> Result<String, String> {
+    let dir = Path::new(out_dir);
+    let image_formats = ExportFormats { csv: false, ..formats };
+    let mut paths = export.save(dir, stem, image_formats, size)?;
+    if formats.csv {
+        export.series.push(other);
+        let csv_formats = ExportFormats {
+            png: false,
+            svg: false,
+            csv: true,
+        };
+        paths.extend(export.save(dir, stem, csv_formats, size)?);
+    }
+    let names: Vec<String> = paths.iter().map(|p| p.to_string_lossy().to_string()).collect();
+    Ok(format!("已导出曲线：\n{}", names.join("\n")))
+}
+
+// 写盘线程的句柄 Drop 时要等队列写完再 join，放到后台线程，慢盘 / 队列满时界面不卡
+fn drop_in_background<T: Send + 'static>(old: T) {
+    thread::spawn(move || drop(old));
+}
+
+fn open_store(cfg: &AppConfig, clock: Clock) -> Option<Store> {
+    if cfg.sqlite_log {
+        Some(Store::open(&cfg.log_folder, &cfg.device_id(), clock))
+    } else {
+        None
+    }
+}
+
+// 后台配置检查的结果
+struct ConfigCheck {
+    /// 发起检查时的 profile
+    profile: Option<String>,
+    mtime: Option<SystemTime>,
+    /// 文件没变时为 None
+    reload: Option<Result<(AppConfig, Vec<ConfigIssue>), ConfigIssue>>,
+}
+
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// 用系统默认程序打开文件或目录
+fn open_with_system(path: &str) {
+    #[cfg(target_os = "windows")]
+    {
+        let _ = Command::new("explorer").arg(path).spawn();
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let _ = Command::new("xdg-open").arg(path).spawn();
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let _ = Command::new("open").arg(path).spawn();
+    }
+}
+
+// 报警提示音：Windows / macOS 用系统提示音，Linux 用 canberra-gtk-play，启动不了时退回终端响铃
+fn beep() {
+    #[cfg(target_os = "windows")]
+    let child = Command::new("rundll32")
+        .arg("user32.dll,MessageBeep")
+        .spawn();
+    #[cfg(target_os = "linux")]
+    let child = Command::new("canberra-gtk-play")
+        .args(["-i", "bell"])
+        .spawn();
+    #[cfg(target_os = "macos")]
+    let child = Command::new("osascript").args(["-e", "beep"]).spawn();
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    let child: std::io::Result<std::process::Child> = Err(std::io::ErrorKind::Unsupported.into());
+
+    match child {
+        // 每 5 s 可能响一次：子进程放到后台线程回收，不留僵尸进程
+        Ok(mut child) => {
+            thread::spawn(move || {
+                let _ = child.wait();
+            });
+        }
+        Err(_) => eprint!("\x07"),
     }
 }
 
 // ================= main =================
 
 fn main() -> eframe::Result<()> {
-    let cfg = AppConfig::load();
+    // 子命令：dhjc_rust_gui import|plot <file>...（不启动 GUI）
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("import") => std::process::exit(dhjc_import::run_cli(&args[1..])),
+        Some("plot") => std::process::exit(dhjc_plot_export::run_cli(&args[1..])),
+        _ => {}
+    }
+
+    let cli = CliArgs::from_env();
+    let (cfg, issues) = AppConfig::load(&cli);
+
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size(egui::vec2(1200.0, 750.0))
+        .with_min_inner_size(egui::vec2(800.0, 620.0))
+        .with_title("DHJC ARC MONITOR - Rust GUI");
+    if cfg.always_on_top {
+        viewport = viewport.with_always_on_top();
+    }
 
     let native_options = NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size(egui::vec2(1200.0, 750.0))
-            .with_min_inner_size(egui::vec2(800.0, 620.0))
-            .with_title("DHJC ARC MONITOR - Rust GUI"),
+        viewport,
         ..Default::default()
     };
 
     eframe::run_native(
         "dhjc_rust_gui",
         native_options,
-        Box::new(move |cc| Ok(Box::new(DhjcApp::new(cc, cfg.clone())))),
+        Box::new(move |cc| Ok(Box::new(DhjcApp::new(cc, cfg.clone(), issues.clone())))),
     )
 }